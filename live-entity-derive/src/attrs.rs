@@ -0,0 +1,284 @@
+use quote::ToTokens;
+use syn::punctuated::Punctuated;
+use syn::{
+    parse_quote, Attribute, Error, Field, Ident, LitInt, LitStr, Member, Meta, Path, Token, Type,
+};
+
+use crate::util::generic_args_of;
+
+/// How a field's value is carried in the generated update type.
+pub enum UpdateKind {
+    /// The whole value is replaced.
+    Replace,
+    /// An `Option<T>` updated through `live_entity::Patch<T>`, so it can be cleared.
+    Optional(Box<Type>),
+    /// A `Vec<T>` updated through `live_entity::ListUpdate<T>`.
+    List(Box<Type>),
+    /// A `HashMap<K, V>` updated through `live_entity::MapUpdate<K, V>`.
+    Map(Box<Type>, Box<Type>),
+    /// A number updated through `live_entity::NumericUpdate<T>`.
+    Increment,
+}
+
+/// A field that stores fill in themselves.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Maintained {
+    /// Set when the entity is created, and left out of the update.
+    CreatedAt,
+    /// Set when the entity is created or updated.
+    UpdatedAt,
+    /// Set to whoever the store acts for when the entity is created or updated.
+    ModifiedBy,
+}
+
+pub struct FieldAttrs {
+    pub kind: UpdateKind,
+    /// Left out of the update entirely.
+    pub readonly: bool,
+    pub maintained: Option<Maintained>,
+}
+
+pub fn parse_field_attrs(field: &Field, member: &Member) -> Result<FieldAttrs, Error> {
+    let mut kind = UpdateKind::Replace;
+    let mut explicit = false;
+    let mut readonly = false;
+    let mut maintained = None;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("entity")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("readonly") {
+                readonly = true;
+                return Ok(());
+            }
+            let maintains = if meta.path.is_ident("created_at") {
+                Some(Maintained::CreatedAt)
+            } else if meta.path.is_ident("updated_at") {
+                Some(Maintained::UpdatedAt)
+            } else if meta.path.is_ident("modified_by") {
+                Some(Maintained::ModifiedBy)
+            } else {
+                None
+            };
+            if let Some(maintains) = maintains {
+                if maintained.replace(maintains).is_some() {
+                    return Err(meta.error("A field can only be filled in by the store once."));
+                }
+                return Ok(());
+            }
+            if explicit {
+                return Err(meta.error("Only one update operation may be specified per field."));
+            }
+            explicit = true;
+            if meta.path.is_ident("list") {
+                let [item] = generic_args_of(&field.ty, "Vec")?;
+                kind = UpdateKind::List(Box::new(item));
+            } else if meta.path.is_ident("map") {
+                let [key, val] = generic_args_of(&field.ty, "HashMap")?;
+                kind = UpdateKind::Map(Box::new(key), Box::new(val));
+            } else if meta.path.is_ident("increment") {
+                kind = UpdateKind::Increment;
+            } else {
+                return Err(meta.error("Unrecognized entity attribute."));
+            }
+            Ok(())
+        })?;
+    }
+    if readonly && explicit {
+        return Err(Error::new_spanned(
+            &field.ty,
+            "A readonly field can't have an update operation.",
+        ));
+    }
+    if maintained.is_some() && (readonly || explicit) {
+        return Err(Error::new_spanned(
+            &field.ty,
+            "A field filled in by the store can't be readonly or have an update operation.",
+        ));
+    }
+    if maintained == Some(Maintained::CreatedAt) {
+        readonly = true;
+    }
+    // A positional `Patch` can't tell being unchanged from being cleared once
+    // serialized, so only named fields get one automatically.
+    if !explicit && matches!(member, Member::Named(_)) {
+        if let Ok([inner]) = generic_args_of(&field.ty, "Option") {
+            kind = UpdateKind::Optional(Box::new(inner));
+        }
+    }
+    Ok(FieldAttrs {
+        kind,
+        readonly,
+        maintained,
+    })
+}
+
+#[derive(Default)]
+pub struct ContainerAttrs {
+    pub update_name: Option<Ident>,
+    /// The name of the struct generated for an ID made of several fields.
+    pub id_name: Option<Ident>,
+    pub update_derives: Vec<Path>,
+    /// Skips implementing `Diff`, for types whose fields can't be compared.
+    pub no_diff: bool,
+    /// The container's serde attributes that also apply to its update.
+    pub serde_attrs: Vec<Attribute>,
+    /// The entity's schema version.
+    pub version: Option<LitInt>,
+    /// How many seconds the entities live once created.
+    pub ttl_secs: Option<LitInt>,
+    /// Deleting an entity only hides it.
+    pub soft_delete: bool,
+    /// The serde `rename_all` rule of the container.
+    pub rename_all: Option<String>,
+}
+
+pub fn parse_container_attrs(attrs: &[Attribute]) -> Result<ContainerAttrs, Error> {
+    let mut container = ContainerAttrs {
+        serde_attrs: passthrough_serde_attrs(attrs, CONTAINER_SERDE_PASSTHROUGH)?,
+        rename_all: serde_rename(attrs, "rename_all")?,
+        ..Default::default()
+    };
+    for attr in attrs.iter().filter(|a| a.path().is_ident("entity")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("update_name") {
+                let name: LitStr = meta.value()?.parse()?;
+                container.update_name = Some(name.parse()?);
+            } else if meta.path.is_ident("id_name") {
+                let name: LitStr = meta.value()?.parse()?;
+                container.id_name = Some(name.parse()?);
+            } else if meta.path.is_ident("version") {
+                let version: LitInt = meta.value()?.parse()?;
+                version.base10_parse::<u32>()?;
+                container.version = Some(version);
+            } else if meta.path.is_ident("ttl_secs") {
+                let secs: LitInt = meta.value()?.parse()?;
+                secs.base10_parse::<u64>()?;
+                container.ttl_secs = Some(secs);
+            } else if meta.path.is_ident("soft_delete") {
+                container.soft_delete = true;
+            } else if meta.path.is_ident("no_diff") {
+                container.no_diff = true;
+            } else if meta.path.is_ident("update_derive") {
+                meta.parse_nested_meta(|derive| {
+                    container.update_derives.push(derive.path);
+                    Ok(())
+                })?;
+            } else {
+                return Err(meta.error("Unrecognized entity attribute."));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(container)
+}
+
+/// Serde container attributes that make sense on an update too. Anything
+/// else, like `from` or `into`, refers to the original type.
+const CONTAINER_SERDE_PASSTHROUGH: &[&str] =
+    &["rename_all", "rename_all_fields", "deny_unknown_fields", "crate"];
+
+/// Serde field and variant attributes that make sense on an update too.
+/// Anything else, like `with` or `default = "..."`, would apply to the
+/// original type rather than to the update.
+const FIELD_SERDE_PASSTHROUGH: &[&str] =
+    &["rename", "alias", "skip", "skip_serializing", "skip_deserializing"];
+
+/// The attributes of a field or variant to copy onto its counterpart in the
+/// update, which are its docs and any serde attributes that still apply.
+pub fn passthrough_field_attrs(attrs: &[Attribute]) -> Result<Vec<Attribute>, Error> {
+    let mut passthrough: Vec<_> = attrs
+        .iter()
+        .filter(|a| a.path().is_ident("doc"))
+        .cloned()
+        .collect();
+    passthrough.extend(passthrough_serde_attrs(attrs, FIELD_SERDE_PASSTHROUGH)?);
+    Ok(passthrough)
+}
+
+/// The name a field is serialized under, by its own `rename` or else by
+/// the container's `rename_all` rule.
+pub fn serialized_name(
+    field: &Field,
+    ident: &Ident,
+    rename_all: Option<&str>,
+) -> Result<String, Error> {
+    let name = ident.to_string().trim_start_matches("r#").to_owned();
+    if let Some(renamed) = serde_rename(&field.attrs, "rename")? {
+        return Ok(renamed);
+    }
+    match rename_all {
+        Some(rule) => apply_rename_rule(rule, &name)
+            .ok_or_else(|| Error::new(ident.span(), format!("Unknown rename rule {}.", rule))),
+        None => Ok(name),
+    }
+}
+
+/// Serde's `rename_all` rules, for snake_case field names.
+fn apply_rename_rule(rule: &str, name: &str) -> Option<String> {
+    let pascal = || {
+        name.split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                chars.next().map_or(String::new(), |first| {
+                    first.to_uppercase().chain(chars).collect()
+                })
+            })
+            .collect::<String>()
+    };
+    Some(match rule {
+        "lowercase" | "snake_case" => name.to_owned(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => name.to_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            chars.next().map_or(String::new(), |first| {
+                first.to_lowercase().chain(chars).collect()
+            })
+        }
+        "kebab-case" => name.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => name.to_uppercase().replace('_', "-"),
+        _ => return None,
+    })
+}
+
+/// The serialized name given by a serde attribute like `rename` or
+/// `rename_all`, as either `key = "..."` or `key(serialize = "...")`.
+fn serde_rename(attrs: &[Attribute], key: &str) -> Result<Option<String>, Error> {
+    let mut name = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        let metas = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+        for meta in metas.iter().filter(|m| m.path().is_ident(key)) {
+            match meta {
+                Meta::NameValue(nv) => {
+                    name = Some(syn::parse2::<LitStr>(nv.value.to_token_stream())?.value())
+                }
+                Meta::List(list) => list.parse_nested_meta(|nested| {
+                    let value = nested.value()?.parse::<LitStr>()?;
+                    if nested.path.is_ident("serialize") {
+                        name = Some(value.value());
+                    }
+                    Ok(())
+                })?,
+                Meta::Path(_) => {}
+            }
+        }
+    }
+    Ok(name)
+}
+
+fn passthrough_serde_attrs(attrs: &[Attribute], allowed: &[&str]) -> Result<Vec<Attribute>, Error> {
+    let mut kept: Vec<Meta> = Vec::new();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        let metas = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+        kept.extend(
+            metas
+                .into_iter()
+                .filter(|m| allowed.iter().any(|a| m.path().is_ident(a))),
+        );
+    }
+    Ok(if kept.is_empty() {
+        Vec::new()
+    } else {
+        vec![parse_quote!(#[serde(#(#kept),*)])]
+    })
+}
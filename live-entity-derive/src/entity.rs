@@ -1,76 +1,435 @@
-use super::updatable::gen_update_name;
-use proc_macro2::{Ident, Span, TokenStream};
+use super::attrs::{parse_field_attrs, passthrough_field_attrs, serialized_name, Maintained};
+use super::updatable::{gen_set_value, impl_updatable, update_fields, Target, UpdateField};
+use super::util::{input_as_struct_fields, members_of, with_predicates, FieldMember};
+use proc_macro2::{Span, TokenStream};
 use quote::{format_ident, quote, quote_spanned};
 use syn::spanned::Spanned;
-use syn::{Attribute, Error, Expr, Field, FieldsNamed, Lit, LitStr};
+use syn::{
+    parse_quote, Attribute, DeriveInput, Error, Expr, Field, Fields, Lit, LitStr, Member, Meta,
+    Type,
+};
 
-pub fn impl_entity(
-    name: &Ident,
+pub fn expand_entity(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let target = Target::new(input)?;
+    let fields = input_as_struct_fields(input, "Entity")?;
+    let (ids, other_fields) = extract_id_fields(fields, input.ident.span());
+    let maintained = other_fields.clone();
+    let other_fields = update_fields(other_fields)?;
+    let maintained = maintained_fields(&target, maintained)?;
+
+    // The update type is generated even if the entity is malformed, so that
+    // the errors don't cascade into every use of it.
+    let mut output = impl_updatable(&target, fields, &other_fields);
+    output.extend(
+        ids.and_then(|ids| {
+            let name_str = get_name_str(&input.attrs, input.ident.span())?;
+            let id = EntityId::new(&target, ids)?;
+            Ok(impl_entity(
+                &target,
+                name_str,
+                &id,
+                &other_fields,
+                &maintained,
+            ))
+        })
+        .unwrap_or_else(Error::into_compile_error),
+    );
+    Ok(output)
+}
+
+/// The fields an entity's ID is made of.
+struct EntityId<'a> {
+    fields: Vec<FieldMember<'a>>,
+    ty: Type,
+    /// The struct generated for an ID made of several fields.
+    composite: Option<TokenStream>,
+    generator: Option<TokenStream>,
+}
+
+impl<'a> EntityId<'a> {
+    fn new(target: &Target, fields: Vec<FieldMember<'a>>) -> Result<Self, Error> {
+        let mut generators = Vec::new();
+        for (_, field) in &fields {
+            generators.push((field, parse_id_generator(field)?));
+        }
+        if let [(_, field)] = fields.as_slice() {
+            return Ok(Self {
+                ty: field.ty.clone(),
+                composite: None,
+                generator: generators.pop().and_then(|(_, g)| g),
+                fields,
+            });
+        }
+        if let Some((field, _)) = generators.iter().find(|(_, g)| g.is_some()) {
+            return Err(Error::new(
+                field.span(),
+                "An ID made of several fields can't be generated.",
+            ));
+        }
+        let (ty, composite) = gen_composite_id(target, &fields)?;
+        Ok(Self {
+            fields,
+            ty,
+            composite: Some(composite),
+            generator: None,
+        })
+    }
+
+    /// An expression for the ID of `self`.
+    fn get(&self) -> TokenStream {
+        match self.fields.as_slice() {
+            [(member, _)] => quote! { std::borrow::Cow::Borrowed(&self.#member) },
+            fields => {
+                let ty = &self.ty;
+                let members = fields.iter().map(|(member, _)| member);
+                quote! {
+                    std::borrow::Cow::Owned(#ty {
+                        #(#members: core::clone::Clone::clone(&self.#members),)*
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// The struct for an ID made of several fields, which holds their values
+/// under the same names.
+fn gen_composite_id(target: &Target, fields: &[FieldMember]) -> Result<(Type, TokenStream), Error> {
+    if !target.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            target.generics,
+            "An ID made of several fields isn't supported on generic entities.",
+        ));
+    }
+    let id_name = target
+        .attrs
+        .id_name
+        .clone()
+        .unwrap_or_else(|| format_ident!("{}Id", target.name));
+    let vis = target.vis;
+    let mut id_fields = Vec::new();
+    let mut names = Vec::new();
+    for (member, field) in fields {
+        let Member::Named(ident) = member else {
+            return Err(Error::new(
+                field.span(),
+                "An ID made of several fields needs them to be named.",
+            ));
+        };
+        let mut id_field = (*field).clone();
+        id_field.attrs = passthrough_field_attrs(&field.attrs)?;
+        names.push(serialized_name(field, ident, None)?);
+        id_fields.push(id_field);
+    }
+    let output = quote! {
+        #[derive(
+            std::fmt::Debug,
+            serde::Serialize,
+            serde::Deserialize,
+            core::clone::Clone,
+            core::cmp::PartialEq,
+            core::cmp::Eq,
+            core::hash::Hash
+        )]
+        #vis struct #id_name {
+            #(#id_fields),*
+        }
+
+        impl live_entity::CompositeId for #id_name {
+            const FIELDS: &'static [&'static str] = &[#(#names),*];
+        }
+    };
+    Ok((parse_quote!(#id_name), output))
+}
+
+/// A field that stores fill in themselves.
+struct MaintainedField<'a> {
+    member: Member,
+    field: &'a Field,
+    kind: Maintained,
+    /// The name it's serialized under, if it has one.
+    name: Option<String>,
+}
+
+fn maintained_fields<'a>(
+    target: &Target,
+    fields: Vec<FieldMember<'a>>,
+) -> Result<Vec<MaintainedField<'a>>, Error> {
+    let mut maintained = Vec::new();
+    for (member, field) in fields {
+        let Some(kind) = parse_field_attrs(field, &member)?.maintained else {
+            continue;
+        };
+        let name = match &member {
+            Member::Named(ident) => Some(serialized_name(
+                field,
+                ident,
+                target.attrs.rename_all.as_deref(),
+            )?),
+            Member::Unnamed(_) => None,
+        };
+        maintained.push(MaintainedField {
+            member,
+            field,
+            kind,
+            name,
+        });
+    }
+    Ok(maintained)
+}
+
+/// The `stamp_created` and `stamp_updated` functions and the names of the
+/// maintained fields.
+fn impl_stamps(maintained: &[MaintainedField], update_fields: &[UpdateField]) -> TokenStream {
+    if maintained.is_empty() {
+        return TokenStream::new();
+    }
+    let on_create = maintained.iter().map(|m| {
+        let member = &m.member;
+        let ty = &m.field.ty;
+        match m.kind {
+            Maintained::CreatedAt | Maintained::UpdatedAt => quote_spanned! {ty.span()=>
+                self.#member = <#ty as core::convert::From<_>>::from(stamp.at);
+            },
+            Maintained::ModifiedBy => quote_spanned! {ty.span()=>
+                if let core::option::Option::Some(by) = &stamp.by {
+                    self.#member = <#ty as core::convert::From<_>>::from(
+                        core::clone::Clone::clone(by)
+                    );
+                }
+            },
+        }
+    });
+    let on_update = update_fields.iter().filter_map(|f| {
+        let update_member = &f.update_member;
+        let ty = &f.field.ty;
+        let set_value = gen_set_value(f, quote!(value));
+        match f.maintained? {
+            Maintained::CreatedAt => None,
+            Maintained::UpdatedAt => Some(quote_spanned! {ty.span()=>
+                let value = <#ty as core::convert::From<_>>::from(stamp.at);
+                update.#update_member = #set_value;
+            }),
+            // Cleared first, so that only the store can say who made a change.
+            Maintained::ModifiedBy => Some(quote_spanned! {ty.span()=>
+                update.#update_member = core::default::Default::default();
+                if let core::option::Option::Some(by) = &stamp.by {
+                    let value = <#ty as core::convert::From<_>>::from(
+                        core::clone::Clone::clone(by)
+                    );
+                    update.#update_member = #set_value;
+                }
+            }),
+        }
+    });
+    let updated_at = maintained
+        .iter()
+        .filter(|m| m.kind == Maintained::UpdatedAt)
+        .filter_map(|m| m.name.as_ref());
+    let all = maintained.iter().filter_map(|m| m.name.as_ref());
+    quote! {
+        const UPDATED_AT_FIELDS: &'static [&'static str] = &[#(#updated_at),*];
+        const MAINTAINED_FIELDS: &'static [&'static str] = &[#(#all),*];
+
+        // The fields are often `Timestamp`s themselves.
+        #[allow(clippy::useless_conversion)]
+        fn stamp_created(&mut self, stamp: &live_entity::Stamp) {
+            #(#on_create)*
+        }
+
+        #[allow(clippy::useless_conversion)]
+        fn stamp_updated(update: &mut Self::Update, stamp: &live_entity::Stamp) {
+            #(#on_update)*
+        }
+    }
+}
+
+fn impl_entity(
+    target: &Target,
     name_str: &LitStr,
-    id_field: &Field,
-    other_fields: &Vec<&Field>,
+    id: &EntityId,
+    other_fields: &[UpdateField],
+    maintained: &[MaintainedField],
 ) -> TokenStream {
-    let update_name = gen_update_name(name);
-    let id_name = &id_field.ident;
-    let id_type = &id_field.ty;
-    let mut output = impl_eq_for_entity(name, id_field);
-    output.extend(impl_into_update(&name, &update_name, other_fields));
+    let name = target.name;
+    let update_type = target.update_type();
+    let id_type = &id.ty;
+    let mut output = id.composite.clone().unwrap_or_default();
+    output.extend(impl_eq_for_entity(target, &id.fields));
+    output.extend(impl_into_update(target, other_fields));
+
+    let generics = if target.generics.params.is_empty() {
+        target.generics.clone()
+    } else {
+        let (_, ty_generics, _) = target.generics.split_for_impl();
+        with_predicates(
+            target.generics,
+            [
+                parse_quote! { #name #ty_generics: live_entity::ProtoEntity<#update_type> },
+                parse_quote! { #update_type: live_entity::UpdateTrait },
+                parse_quote! { #id_type: live_entity::IDTrait },
+            ],
+        )
+    };
+    let version = target
+        .attrs
+        .version
+        .as_ref()
+        .map(|v| quote! { const VERSION: u32 = #v; });
+    let ttl = target.attrs.ttl_secs.as_ref().map(|secs| {
+        quote! {
+            const TTL: core::option::Option<core::time::Duration> =
+                core::option::Option::Some(core::time::Duration::from_secs(#secs));
+        }
+    });
+    let soft_delete = target
+        .attrs
+        .soft_delete
+        .then(|| quote! { const SOFT_DELETE: bool = true; });
+    let get_id = id.get();
+    let stamps = impl_stamps(maintained, other_fields);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
     output.extend(quote! {
-        impl live_entity::Entity for #name {
-            type Update = #update_name;
+        impl #impl_generics live_entity::Entity for #name #ty_generics #where_clause {
+            type Update = #update_type;
             type ID = #id_type;
             const TYPE_NAME: &'static str = #name_str;
+            #version
+            #ttl
+            #soft_delete
 
-            fn get_id(&self) -> &Self::ID {
-                &self.#id_name
+            fn get_id(&self) -> std::borrow::Cow<'_, Self::ID> {
+                #get_id
             }
+            #stamps
         }
     });
+    if let (Some(generator), [(id_member, _)]) = (&id.generator, id.fields.as_slice()) {
+        output.extend(quote! {
+            impl #impl_generics live_entity::GeneratedId for #name #ty_generics #where_clause {
+                const ID_GENERATOR: live_entity::IdGenerator<Self::ID> = #generator;
+
+                fn set_id(&mut self, id: Self::ID) {
+                    self.#id_member = id;
+                }
+            }
+        });
+    }
     output
 }
 
-fn impl_into_update(name: &Ident, update_name: &Ident, fields: &Vec<&Field>) -> TokenStream {
+/// The `IdGenerator` picked by `#[entity_id(generate = "...")]`, if any.
+fn parse_id_generator(id_field: &Field) -> Result<Option<TokenStream>, Error> {
+    let id_type = &id_field.ty;
+    let mut generator = None;
+    let attrs = id_field
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("entity_id") && matches!(a.meta, Meta::List(_)));
+    for attr in attrs {
+        attr.parse_nested_meta(|meta| {
+            if !meta.path.is_ident("generate") {
+                return Err(meta.error("Unrecognized entity_id attribute."));
+            }
+            let strategy: LitStr = meta.value()?.parse()?;
+            generator = Some(match strategy.value().as_str() {
+                "uuid" => quote_spanned! {strategy.span()=>
+                    live_entity::IdGenerator::Random(<#id_type as live_entity::UuidId>::new_uuid)
+                },
+                "ulid" => quote_spanned! {strategy.span()=>
+                    live_entity::IdGenerator::Random(<#id_type as live_entity::UlidId>::new_ulid)
+                },
+                "object_id" => quote_spanned! {strategy.span()=>
+                    live_entity::IdGenerator::Random(
+                        <#id_type as live_entity::ObjectIdId>::new_object_id
+                    )
+                },
+                "sequence" => quote_spanned! {strategy.span()=>
+                    live_entity::IdGenerator::Sequence(
+                        <#id_type as live_entity::SequenceId>::from_sequence
+                    )
+                },
+                _ => {
+                    return Err(Error::new_spanned(
+                        &strategy,
+                        "Expected uuid, ulid, object_id or sequence.",
+                    ))
+                }
+            });
+            Ok(())
+        })?;
+    }
+    Ok(generator)
+}
+
+fn impl_into_update(target: &Target, fields: &[UpdateField]) -> TokenStream {
+    let name = target.name;
+    let update_type = target.update_type();
     let update_var = format_ident!("update");
-    let field_copies = fields.iter().map(|&f| {
-        let name = f.ident.as_ref();
-        quote_spanned! {f.span()=> #update_var.#name = std::option::Option::Some(self.#name); }
+    let field_copies = fields.iter().map(|f| {
+        let member = &f.member;
+        let update_member = &f.update_member;
+        let set_value = gen_set_value(f, quote!(self.#member));
+        quote_spanned! {f.field.span()=> #update_var.#update_member = #set_value; }
     });
+    let (impl_generics, ty_generics, where_clause) = target.generics.split_for_impl();
     quote! {
-        impl std::convert::Into<#update_name> for #name {
-            fn into(self) -> #update_name {
-                let mut #update_var = #update_name::default();
+        impl #impl_generics std::convert::Into<#update_type> for #name #ty_generics #where_clause {
+            fn into(self) -> #update_type {
+                let mut #update_var = <#update_type as std::default::Default>::default();
                 #(#field_copies)*
-                update
+                #update_var
             }
         }
     }
 }
 
-fn impl_eq_for_entity(name: &Ident, id_field: &Field) -> TokenStream {
-    let id_ident = &id_field.ident;
-    quote_spanned! {id_field.span()=>
-        impl core::cmp::PartialEq for #name {
+fn impl_eq_for_entity(target: &Target, id_fields: &[FieldMember]) -> TokenStream {
+    let name = target.name;
+    let generics = if target.generics.params.is_empty() {
+        target.generics.clone()
+    } else {
+        with_predicates(
+            target.generics,
+            id_fields.iter().map(|(_, field)| {
+                let id_type = &field.ty;
+                parse_quote! { #id_type: core::cmp::Eq }
+            }),
+        )
+    };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let members = id_fields.iter().map(|(member, _)| member);
+    let span = id_fields[0].1.span();
+    quote_spanned! {span=>
+        impl #impl_generics core::cmp::PartialEq for #name #ty_generics #where_clause {
             fn eq(&self, other: &Self) -> bool {
-                self.#id_ident == other.#id_ident
+                #(self.#members == other.#members)&&*
             }
         }
-        impl core::cmp::Eq for #name {}
+        impl #impl_generics core::cmp::Eq for #name #ty_generics #where_clause {}
     }
 }
 
-pub fn extract_id_field(fields: &FieldsNamed) -> (Result<&Field, Error>, Vec<&Field>) {
-    let (ids, others): (Vec<_>, Vec<_>) = fields
-        .named
-        .iter()
-        .partition(|f| f.attrs.iter().any(|a| a.path().is_ident("entity_id")));
-    let id = ids.into_iter().next().ok_or(Error::new(
-        fields.span(),
-        "No ID field specified for Entity.",
-    ));
-    (id, others)
+pub fn extract_id_fields(
+    fields: &Fields,
+    name_span: Span,
+) -> (Result<Vec<FieldMember<'_>>, Error>, Vec<FieldMember<'_>>) {
+    let (ids, others): (Vec<_>, Vec<_>) = members_of(fields)
+        .into_iter()
+        .partition(|(_, f)| f.attrs.iter().any(|a| a.path().is_ident("entity_id")));
+    let span = match fields {
+        Fields::Unit => name_span,
+        _ => fields.span(),
+    };
+    let ids = if ids.is_empty() {
+        Err(Error::new(span, "No ID field specified for Entity."))
+    } else {
+        Ok(ids)
+    };
+    (ids, others)
 }
 
-pub fn get_name_str(attrs: &Vec<Attribute>, name_span: Span) -> Result<&LitStr, Error> {
+pub fn get_name_str(attrs: &[Attribute], name_span: Span) -> Result<&LitStr, Error> {
     attrs
         .iter()
         .find(|&a| a.path().is_ident("entity_name"))
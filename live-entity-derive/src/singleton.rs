@@ -0,0 +1,76 @@
+use super::updatable::{expand_updatable, Target};
+use super::util::with_predicates;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, DeriveInput, Error, LitStr};
+
+pub fn expand_singleton(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let target = Target::new(input)?;
+    let (type_name, id) = get_singleton_attrs(input)?;
+    let mut output = expand_updatable(input)?;
+    output.extend(impl_singleton(&target, &type_name, &id));
+    Ok(output)
+}
+
+fn impl_singleton(target: &Target, type_name: &LitStr, id: &LitStr) -> TokenStream {
+    let name = target.name;
+    let update_type = target.update_type();
+    let generics = if target.generics.params.is_empty() {
+        target.generics.clone()
+    } else {
+        let (_, ty_generics, _) = target.generics.split_for_impl();
+        with_predicates(
+            target.generics,
+            [
+                parse_quote! {
+                    #name #ty_generics: live_entity::Updatable<#update_type>
+                        + core::fmt::Debug
+                        + core::clone::Clone
+                        + serde::Serialize
+                        + serde::de::DeserializeOwned
+                        + core::marker::Unpin
+                        + core::marker::Send
+                        + core::marker::Sync
+                        + 'static
+                },
+                parse_quote! { #update_type: live_entity::UpdateTrait },
+            ],
+        )
+    };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics live_entity::Singleton for #name #ty_generics #where_clause {
+            type Update = #update_type;
+            const ENTITY_ID: &'static str = #id;
+            const TYPE_NAME: &'static str = #type_name;
+        }
+    }
+}
+
+fn get_singleton_attrs(input: &DeriveInput) -> Result<(LitStr, LitStr), Error> {
+    let mut type_name = None;
+    let mut id = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("singleton")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("type_name") {
+                type_name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("id") {
+                id = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("Unrecognized singleton attribute."));
+            }
+            Ok(())
+        })?;
+    }
+    match (type_name, id) {
+        (Some(type_name), Some(id)) => Ok((type_name, id)),
+        (None, _) => Err(Error::new(
+            input.ident.span(),
+            "No type name specified for singleton, add #[singleton(type_name = \"...\")].",
+        )),
+        (_, None) => Err(Error::new(
+            input.ident.span(),
+            "No ID specified for singleton, add #[singleton(id = \"...\")].",
+        )),
+    }
+}
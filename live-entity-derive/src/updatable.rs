@@ -1,30 +1,253 @@
+use super::attrs::{
+    parse_container_attrs, parse_field_attrs, passthrough_field_attrs, ContainerAttrs, Maintained,
+    UpdateKind,
+};
+use super::util::{member_ident, members_of, phantom_type, with_predicates, FieldMember};
 use proc_macro2::{Ident, TokenStream};
 use quote::{format_ident, quote, quote_spanned};
 use syn::spanned::Spanned;
-use syn::{parse_quote, parse_quote_spanned, Field, ImplItemFn};
+use syn::{
+    parse_quote, parse_quote_spanned, Attribute, Data, DataEnum, DeriveInput, Error, Expr, Field, Fields,
+    Generics, ImplItemFn, Index, Member, Type, Visibility, WherePredicate,
+};
 
-pub fn impl_updatable(name: &Ident, fields: &Vec<&Field>) -> TokenStream {
-    let update_name = gen_update_name(name);
-    let update_fields = gen_update_fields(fields);
+pub struct UpdateField<'a> {
+    pub field: &'a Field,
+    /// Where the field is in the type being updated.
+    pub member: Member,
+    /// Where the field is in the update, which differs from `member` for
+    /// tuple structs that leave some fields out of their update.
+    pub update_member: Member,
+    pub kind: UpdateKind,
+    /// Attributes copied onto the field in the update.
+    pub attrs: Vec<Attribute>,
+    /// Set by the store, so the update has no builder for it and `Diff`
+    /// leaves it unchanged.
+    pub maintained: Option<Maintained>,
+}
+
+/// The type an update is generated for.
+pub struct Target<'a> {
+    pub name: &'a Ident,
+    pub vis: &'a Visibility,
+    pub generics: &'a Generics,
+    pub update_name: Ident,
+    pub attrs: ContainerAttrs,
+}
+
+impl<'a> Target<'a> {
+    pub fn new(input: &'a DeriveInput) -> Result<Self, Error> {
+        let attrs = parse_container_attrs(&input.attrs)?;
+        Ok(Self {
+            name: &input.ident,
+            vis: &input.vis,
+            generics: &input.generics,
+            update_name: attrs
+                .update_name
+                .clone()
+                .unwrap_or_else(|| gen_update_name(&input.ident)),
+            attrs,
+        })
+    }
+
+    pub fn update_type(&self) -> Type {
+        let update_name = &self.update_name;
+        let (_, ty_generics, _) = self.generics.split_for_impl();
+        parse_quote! { #update_name #ty_generics }
+    }
+}
+
+/// Collects the fields that make up the update, leaving out readonly ones.
+pub fn update_fields<'a>(
+    members: impl IntoIterator<Item = FieldMember<'a>>,
+) -> Result<Vec<UpdateField<'a>>, Error> {
+    let mut fields = Vec::new();
+    let mut errors: Option<Error> = None;
+    for (member, field) in members {
+        let parsed = parse_field_attrs(field, &member)
+            .and_then(|f| passthrough_field_attrs(&field.attrs).map(|attrs| (f, attrs)));
+        match parsed {
+            Ok((parsed, _)) if parsed.readonly => {}
+            Ok((parsed, attrs)) => {
+                let update_member = match &member {
+                    Member::Named(_) => member.clone(),
+                    Member::Unnamed(index) => Member::Unnamed(Index {
+                        index: fields.len() as u32,
+                        span: index.span,
+                    }),
+                };
+                fields.push(UpdateField {
+                    field,
+                    member,
+                    update_member,
+                    kind: parsed.kind,
+                    attrs,
+                    maintained: parsed.maintained,
+                })
+            }
+            Err(e) => match &mut errors {
+                Some(errors) => errors.combine(e),
+                None => errors = Some(e),
+            },
+        }
+    }
+    match errors {
+        Some(errors) => Err(errors),
+        None => Ok(fields),
+    }
+}
+
+pub fn expand_updatable(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let target = Target::new(input)?;
+    match &input.data {
+        Data::Struct(s) => {
+            let fields = update_fields(members_of(&s.fields))?;
+            Ok(impl_updatable(&target, &s.fields, &fields))
+        }
+        Data::Enum(e) => impl_updatable_enum(&target, e),
+        Data::Union(_) => Err(Error::new(
+            input.ident.span(),
+            "Updatable cannot be derived for unions.",
+        )),
+    }
+}
+
+/// Implements `Updatable` for a struct, where `fields` are the subset of
+/// `all_fields` that can be updated.
+pub fn impl_updatable(target: &Target, all_fields: &Fields, fields: &[UpdateField]) -> TokenStream {
+    let name = target.name;
+    let update_name = &target.update_name;
+    let update_type = target.update_type();
+    let update_struct = gen_update_struct(target, all_fields, fields);
     let builder_fns = gen_update_builder_fns(fields);
     let with_name = format_ident!("with");
     let update_fn_body = gen_update_fn_body(fields, &with_name);
+    let diff_impl = impl_diff(target, fields);
+    let composable_impl = impl_composable(target, fields);
+
+    let generics = with_predicates(target.generics, field_predicates(target.generics, fields));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let (update_impl_generics, _, update_where_clause) = target.generics.split_for_impl();
 
     quote! {
-        #[derive(std::default::Default, std::fmt::Debug, serde::Serialize, serde::Deserialize, core::clone::Clone)]
-        pub struct #update_name {
-            #(#update_fields),*
-        }
+        #update_struct
 
-        impl #update_name {
+        #[allow(dead_code)]
+        impl #update_impl_generics #update_name #ty_generics #update_where_clause {
             #(#builder_fns)*
         }
 
-        impl live_entity::Updatable<#update_name> for #name {
-            fn update(&mut self, #with_name: &#update_name) {
+        impl #impl_generics live_entity::Updatable<#update_type> for #name #ty_generics #where_clause {
+            fn update(&mut self, #with_name: &#update_type) {
                 #update_fn_body
             }
         }
+
+        #diff_impl
+
+        #composable_impl
+    }
+}
+
+fn impl_diff(target: &Target, fields: &[UpdateField]) -> TokenStream {
+    if target.attrs.no_diff {
+        return TokenStream::new();
+    }
+    let name = target.name;
+    let update_type = target.update_type();
+    let generics = with_predicates(target.generics, diff_field_predicates(target.generics, fields));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let lines = fields.iter().filter(|f| f.maintained.is_none()).map(|f| {
+        let member = &f.member;
+        let update_member = &f.update_member;
+        quote_spanned! {f.field.ty.span()=>
+            update.#update_member = live_entity::Diff::diff(&old.#member, &new.#member);
+        }
+    });
+    quote! {
+        impl #impl_generics live_entity::Diff<#update_type> for #name #ty_generics #where_clause {
+            fn diff(old: &Self, new: &Self) -> #update_type {
+                let mut update = <#update_type as std::default::Default>::default();
+                #(#lines)*
+                update
+            }
+        }
+    }
+}
+
+fn impl_composable(target: &Target, fields: &[UpdateField]) -> TokenStream {
+    let name = target.name;
+    let update_type = target.update_type();
+    let generics = with_predicates(
+        target.generics,
+        composable_field_predicates(target.generics, fields),
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let merge_lines = fields.iter().map(|f| {
+        let ty = &f.field.ty;
+        let update_member = &f.update_member;
+        quote_spanned! {f.field.ty.span()=>
+            merged.#update_member = <#ty as live_entity::Composable<_>>::merge(
+                earlier.#update_member,
+                later.#update_member,
+            );
+        }
+    });
+    let inverse_lines = fields.iter().map(|f| {
+        let member = &f.member;
+        let update_member = &f.update_member;
+        quote_spanned! {f.field.ty.span()=>
+            inverse.#update_member = live_entity::Composable::inverse(&self.#member, &update.#update_member);
+        }
+    });
+    let mut output = quote! {
+        impl #impl_generics live_entity::Composable<#update_type> for #name #ty_generics #where_clause {
+            fn merge(earlier: #update_type, later: #update_type) -> #update_type {
+                let mut merged = <#update_type as std::default::Default>::default();
+                #(#merge_lines)*
+                merged
+            }
+
+            fn inverse(&self, update: &#update_type) -> #update_type {
+                let mut inverse = <#update_type as std::default::Default>::default();
+                #(#inverse_lines)*
+                inverse
+            }
+        }
+    };
+    output.extend(impl_composable_fns(target));
+    output
+}
+
+/// Exposes `Composable` as methods on the update type itself.
+fn impl_composable_fns(target: &Target) -> TokenStream {
+    let name = target.name;
+    let update_name = &target.update_name;
+    let update_type = target.update_type();
+    let (_, ty_generics, _) = target.generics.split_for_impl();
+    let generics = if target.generics.params.is_empty() {
+        target.generics.clone()
+    } else {
+        with_predicates(
+            target.generics,
+            [parse_quote! { #name #ty_generics: live_entity::Composable<#update_type> }],
+        )
+    };
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    quote! {
+        #[allow(dead_code)]
+        impl #impl_generics #update_name #ty_generics #where_clause {
+            /// Combines this update with one applied after it.
+            pub fn merge(self, later: Self) -> Self {
+                <#name #ty_generics as live_entity::Composable<Self>>::merge(self, later)
+            }
+
+            /// Gets the update that undoes this one, given the value from
+            /// before it was applied.
+            pub fn inverse_against(&self, entity: &#name #ty_generics) -> Self {
+                live_entity::Composable::inverse(entity, self)
+            }
+        }
     }
 }
 
@@ -32,43 +255,425 @@ pub fn gen_update_name(name: &Ident) -> Ident {
     format_ident!("Updated{}", name)
 }
 
-fn gen_update_fields(fields: &Vec<&Field>) -> Vec<Field> {
+// `Default` is implemented by hand, since deriving it would require every
+// type parameter to be `Default` even though the fields never need them to be.
+fn update_derives(target: &Target) -> TokenStream {
+    let extra_derives = &target.attrs.update_derives;
+    let serde_attrs = &target.attrs.serde_attrs;
+    quote! {
+        #[derive(std::fmt::Debug, serde::Serialize, serde::Deserialize, core::clone::Clone #(, #extra_derives)*)]
+        #(#serde_attrs)*
+    }
+}
+
+fn impl_default(target: &Target, body: TokenStream) -> TokenStream {
+    let update_name = &target.update_name;
+    let (impl_generics, ty_generics, where_clause) = target.generics.split_for_impl();
+    quote! {
+        impl #impl_generics std::default::Default for #update_name #ty_generics #where_clause {
+            fn default() -> Self {
+                #body
+            }
+        }
+    }
+}
+
+fn gen_update_struct(target: &Target, all_fields: &Fields, fields: &[UpdateField]) -> TokenStream {
+    let update_name = &target.update_name;
+    let vis = target.vis;
+    let derives = update_derives(target);
+    let (_, _, where_clause) = target.generics.split_for_impl();
+    let params = &target.generics.params;
+    let phantom = phantom_type(target.generics);
+    let update_fields = gen_update_fields(fields);
+    let default = quote!(std::default::Default::default());
+    let defaults = fields.iter().map(|_| &default);
+    match all_fields {
+        Fields::Named(_) => {
+            let members = fields.iter().map(|f| &f.update_member);
+            let phantom_default = phantom.as_ref().map(|_| quote!(_phantom: #default));
+            let phantom = phantom.map(|ty| quote! { #[serde(skip)] #vis _phantom: #ty });
+            let fields = update_fields.iter().map(|f| quote!(#f)).chain(phantom);
+            let default_impl = impl_default(
+                target,
+                quote! { Self { #(#members: #defaults,)* #phantom_default } },
+            );
+            quote! {
+                #derives
+                #vis struct #update_name<#params> #where_clause {
+                    #(#fields),*
+                }
+                #default_impl
+            }
+        }
+        Fields::Unnamed(_) | Fields::Unit if !fields.is_empty() || phantom.is_some() => {
+            let phantom_default = phantom.as_ref().map(|_| &default);
+            let phantom = phantom.map(|ty| quote! { #[serde(skip)] #vis #ty });
+            let fields = update_fields.iter().map(|f| quote!(#f)).chain(phantom);
+            let default_impl =
+                impl_default(target, quote! { Self(#(#defaults,)* #phantom_default) });
+            quote! {
+                #derives
+                #vis struct #update_name<#params>(#(#fields),*) #where_clause;
+                #default_impl
+            }
+        }
+        _ => {
+            let default_impl = impl_default(target, quote! { Self });
+            quote! {
+                #derives
+                #vis struct #update_name;
+                #default_impl
+            }
+        }
+    }
+}
+
+/// Requires that each field can be updated with its update type, so that
+/// generic fields carry the bounds they need.
+fn field_predicates(generics: &Generics, fields: &[UpdateField]) -> Vec<WherePredicate> {
+    fields_bound_by(generics, fields, quote!(live_entity::Updatable))
+}
+
+fn diff_field_predicates(generics: &Generics, fields: &[UpdateField]) -> Vec<WherePredicate> {
+    fields_bound_by(generics, fields, quote!(live_entity::Diff))
+}
+
+fn composable_field_predicates(generics: &Generics, fields: &[UpdateField]) -> Vec<WherePredicate> {
+    fields_bound_by(generics, fields, quote!(live_entity::Composable))
+}
+
+fn fields_bound_by(
+    generics: &Generics,
+    fields: &[UpdateField],
+    bound: TokenStream,
+) -> Vec<WherePredicate> {
+    if generics.params.is_empty() {
+        return Vec::new();
+    }
     fields
         .iter()
-        .map(|&f| {
-            let mut update_field = f.clone();
-            let original_type = &f.ty;
-            update_field.ty = parse_quote! { std::option::Option<#original_type> };
-            update_field
-                .attrs
-                .push(parse_quote!(#[serde(skip_serializing_if = "std::option::Option::is_none")]));
+        .map(|f| {
+            let ty = &f.field.ty;
+            let update_type = gen_update_type(f);
+            parse_quote! { #ty: #bound<#update_type> }
+        })
+        .collect()
+}
+
+/// The type of this field in the generated update struct.
+fn gen_update_type(field: &UpdateField) -> Type {
+    let ty = &field.field.ty;
+    match &field.kind {
+        UpdateKind::Replace => parse_quote! { std::option::Option<#ty> },
+        UpdateKind::Optional(inner) => parse_quote! { live_entity::Patch<#inner> },
+        UpdateKind::List(item) => parse_quote! { std::option::Option<live_entity::ListUpdate<#item>> },
+        UpdateKind::Map(key, val) => {
+            parse_quote! { std::option::Option<live_entity::MapUpdate<#key, #val>> }
+        }
+        UpdateKind::Increment => parse_quote! { std::option::Option<live_entity::NumericUpdate<#ty>> },
+    }
+}
+
+/// Wraps `val`, a full value of the field, into the update for this field.
+pub fn gen_set_value(field: &UpdateField, val: TokenStream) -> Expr {
+    let wrapped = match &field.kind {
+        UpdateKind::Replace => val,
+        UpdateKind::Optional(_) => return parse_quote! { live_entity::Patch::from(#val) },
+        UpdateKind::List(_) => quote! { live_entity::ListUpdate::Set(#val) },
+        UpdateKind::Map(..) => quote! { live_entity::MapUpdate::Set(#val) },
+        UpdateKind::Increment => quote! { live_entity::NumericUpdate::Set(#val) },
+    };
+    parse_quote! { core::option::Option::Some(#wrapped) }
+}
+
+fn gen_update_fields(fields: &[UpdateField]) -> Vec<Field> {
+    fields
+        .iter()
+        .map(|f| {
+            let mut update_field = f.field.clone();
+            update_field.attrs = f.attrs.clone();
+            update_field.ty = gen_update_type(f);
+            // Skipping positional fields would shift the ones after them.
+            if let Member::Named(_) = f.member {
+                update_field.attrs.push(match f.kind {
+                    UpdateKind::Optional(_) => parse_quote!(
+                        #[serde(default, skip_serializing_if = "live_entity::Patch::is_unchanged")]
+                    ),
+                    _ => parse_quote!(#[serde(skip_serializing_if = "std::option::Option::is_none")]),
+                });
+            }
             update_field
         })
         .collect()
 }
 
-fn gen_update_builder_fns(fields: &Vec<&Field>) -> Vec<ImplItemFn> {
+fn gen_update_builder_fns(fields: &[UpdateField]) -> Vec<ImplItemFn> {
     fields
         .iter()
-        .map(|&f| {
-            let name = &f.ident;
-            let ty = &f.ty;
-            parse_quote_spanned! {f.span()=>
+        .filter(|f| f.maintained.is_none())
+        .flat_map(|f| {
+            let name = member_ident("", &f.member);
+            let update_member = &f.update_member;
+            let ty = &f.field.ty;
+            let set_value = gen_set_value(f, quote!(val));
+            let mut fns: Vec<ImplItemFn> = vec![parse_quote_spanned! {f.field.span()=>
                 pub fn #name(mut self, val: #ty) -> Self {
-                    self.#name = core::option::Option::Some(val);
+                    self.#update_member = #set_value;
                     self
                 }
-            }
+            }];
+            fns.extend(gen_operation_builder_fns(f));
+            fns
         })
         .collect()
 }
 
-fn gen_update_fn_body(fields: &Vec<&Field>, with_name: &Ident) -> TokenStream {
-    let lines = fields.iter().map(|&f| {
-        let id = &f.ident;
-        quote_spanned! {f.ty.span()=>
-            live_entity::Updatable::update(&mut self.#id, &#with_name.#id);
+fn gen_operation_builder_fns(f: &UpdateField) -> Vec<ImplItemFn> {
+    let name = &f.update_member;
+    let span = f.field.span();
+    let op_fn = |prefix: &str| member_ident(prefix, &f.member);
+    match &f.kind {
+        UpdateKind::Replace => Vec::new(),
+        UpdateKind::Optional(_) => {
+            let clear = op_fn("clear");
+            vec![parse_quote_spanned! {span=>
+                pub fn #clear(mut self) -> Self {
+                    self.#name = live_entity::Patch::Clear;
+                    self
+                }
+            }]
+        }
+        UpdateKind::List(item) => {
+            let (push, pull) = (op_fn("push"), op_fn("pull"));
+            vec![
+                parse_quote_spanned! {span=>
+                    pub fn #push(mut self, item: #item) -> Self {
+                        if let core::option::Option::Some(live_entity::ListUpdate::Push(items)) = &mut self.#name {
+                            items.push(item);
+                        } else {
+                            self.#name = core::option::Option::Some(live_entity::ListUpdate::Push(std::vec![item]));
+                        }
+                        self
+                    }
+                },
+                parse_quote_spanned! {span=>
+                    pub fn #pull(mut self, item: #item) -> Self {
+                        if let core::option::Option::Some(live_entity::ListUpdate::Pull(items)) = &mut self.#name {
+                            items.push(item);
+                        } else {
+                            self.#name = core::option::Option::Some(live_entity::ListUpdate::Pull(std::vec![item]));
+                        }
+                        self
+                    }
+                },
+            ]
+        }
+        UpdateKind::Map(key, val) => {
+            let (insert, remove) = (op_fn("insert"), op_fn("remove"));
+            vec![
+                parse_quote_spanned! {span=>
+                    pub fn #insert(mut self, key: #key, val: #val) -> Self {
+                        if let core::option::Option::Some(live_entity::MapUpdate::Insert(entries)) = &mut self.#name {
+                            entries.insert(key, val);
+                        } else {
+                            self.#name = core::option::Option::Some(live_entity::MapUpdate::Insert(
+                                std::collections::HashMap::from([(key, val)])
+                            ));
+                        }
+                        self
+                    }
+                },
+                parse_quote_spanned! {span=>
+                    pub fn #remove(mut self, key: #key) -> Self {
+                        if let core::option::Option::Some(live_entity::MapUpdate::Remove(keys)) = &mut self.#name {
+                            keys.push(key);
+                        } else {
+                            self.#name = core::option::Option::Some(live_entity::MapUpdate::Remove(std::vec![key]));
+                        }
+                        self
+                    }
+                },
+            ]
+        }
+        UpdateKind::Increment => {
+            let ty = &f.field.ty;
+            let increment = op_fn("increment");
+            vec![parse_quote_spanned! {span=>
+                pub fn #increment(mut self, by: #ty) -> Self {
+                    self.#name = core::option::Option::Some(live_entity::NumericUpdate::Increment(by));
+                    self
+                }
+            }]
+        }
+    }
+}
+
+fn gen_update_fn_body(fields: &[UpdateField], with_name: &Ident) -> TokenStream {
+    let lines = fields.iter().map(|f| {
+        let member = &f.member;
+        let update_member = &f.update_member;
+        quote_spanned! {f.field.ty.span()=>
+            live_entity::Updatable::update(&mut self.#member, &#with_name.#update_member);
         }
     });
     quote! { #(#lines)* }
 }
+
+const RESERVED_VARIANTS: [&str; 2] = ["Unchanged", "Replace"];
+
+/// Implements `Updatable` for an enum. The generated update enum can leave
+/// the value `Unchanged`, `Replace` it outright, or update the fields of a
+/// variant with a matching variant, which does nothing if the current value
+/// is a different variant.
+fn impl_updatable_enum(target: &Target, data: &DataEnum) -> Result<TokenStream, Error> {
+    let name = target.name;
+    let update_name = &target.update_name;
+    let update_type = target.update_type();
+    let vis = target.vis;
+    let derives = update_derives(target);
+    let default_impl = impl_default(target, quote! { Self::Unchanged });
+    let params = &target.generics.params;
+    let (_, ty_generics, where_clause) = target.generics.split_for_impl();
+
+    let mut update_variants = Vec::new();
+    let mut match_arms = Vec::new();
+    let mut diff_arms = Vec::new();
+    let mut predicates: Vec<WherePredicate> = vec![parse_quote! { Self: core::clone::Clone }];
+    let mut diff_predicates = predicates.clone();
+    let mut composable_predicates = predicates.clone();
+    let mut merge_arms = Vec::new();
+    for variant in &data.variants {
+        let ident = &variant.ident;
+        if RESERVED_VARIANTS.iter().any(|r| ident == r) {
+            return Err(Error::new(
+                ident.span(),
+                format!("Variant name {} is reserved by the generated update type.", ident),
+            ));
+        }
+        if let Fields::Unit = variant.fields {
+            diff_arms.push(quote! {
+                (#name::#ident, #name::#ident) => #update_name::Unchanged,
+            });
+            continue;
+        }
+        let fields = update_fields(members_of(&variant.fields))?;
+        predicates.extend(field_predicates(target.generics, &fields));
+        diff_predicates.extend(diff_field_predicates(target.generics, &fields));
+        composable_predicates.extend(composable_field_predicates(target.generics, &fields));
+        let update_fields = gen_update_fields(&fields);
+        let self_bindings: Vec<_> = fields.iter().map(|f| member_ident("self", &f.member)).collect();
+        let with_bindings: Vec<_> = fields.iter().map(|f| member_ident("with", &f.member)).collect();
+        let members: Vec<_> = fields.iter().map(|f| &f.member).collect();
+        let update_members: Vec<_> = fields.iter().map(|f| &f.update_member).collect();
+        let attrs = passthrough_field_attrs(&variant.attrs)?;
+        update_variants.push(match variant.fields {
+            Fields::Named(_) => quote! { #(#attrs)* #ident { #(#update_fields),* } },
+            _ => quote! { #(#attrs)* #ident(#(#update_fields),*) },
+        });
+        match_arms.push(quote! {
+            (
+                #name::#ident { #(#members: #self_bindings,)* .. },
+                #update_name::#ident { #(#update_members: #with_bindings),* },
+            ) => {
+                #(live_entity::Updatable::update(#self_bindings, #with_bindings);)*
+            }
+        });
+        let old_bindings: Vec<_> = fields.iter().map(|f| member_ident("old", &f.member)).collect();
+        let new_bindings: Vec<_> = fields.iter().map(|f| member_ident("new", &f.member)).collect();
+        diff_arms.push(quote! {
+            (
+                #name::#ident { #(#members: #old_bindings,)* .. },
+                #name::#ident { #(#members: #new_bindings,)* .. },
+            ) => #update_name::#ident {
+                #(#update_members: live_entity::Diff::diff(#old_bindings, #new_bindings),)*
+            },
+        });
+        let earlier_bindings: Vec<_> = fields.iter().map(|f| member_ident("earlier", &f.member)).collect();
+        let later_bindings: Vec<_> = fields.iter().map(|f| member_ident("later", &f.member)).collect();
+        let tys = fields.iter().map(|f| &f.field.ty);
+        merge_arms.push(quote! {
+            (
+                #update_name::#ident { #(#update_members: #earlier_bindings),* },
+                #update_name::#ident { #(#update_members: #later_bindings),* },
+            ) => #update_name::#ident {
+                #(#update_members: <#tys as live_entity::Composable<_>>::merge(#earlier_bindings, #later_bindings),)*
+            },
+        });
+    }
+
+    let diff_impl = if target.attrs.no_diff {
+        TokenStream::new()
+    } else {
+        let generics = with_predicates(target.generics, diff_predicates);
+        let (impl_generics, _, where_clause) = generics.split_for_impl();
+        quote! {
+            impl #impl_generics live_entity::Diff<#update_type> for #name #ty_generics #where_clause {
+                fn diff(old: &Self, new: &Self) -> #update_type {
+                    #[allow(unreachable_patterns)]
+                    match (old, new) {
+                        #(#diff_arms)*
+                        _ => #update_name::Replace(core::clone::Clone::clone(new)),
+                    }
+                }
+            }
+        }
+    };
+
+    let generics = with_predicates(target.generics, composable_predicates);
+    let (composable_impl_generics, _, composable_where_clause) = generics.split_for_impl();
+    let composable_fns = impl_composable_fns(target);
+
+    let generics = with_predicates(target.generics, predicates);
+    let (impl_generics, _, impl_where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        #derives
+        #vis enum #update_name<#params> #where_clause {
+            Unchanged,
+            Replace(#name #ty_generics),
+            #(#update_variants),*
+        }
+
+        #default_impl
+
+        impl #impl_generics live_entity::Updatable<#update_type> for #name #ty_generics #impl_where_clause {
+            fn update(&mut self, with: &#update_type) {
+                #[allow(unreachable_patterns)]
+                match (self, with) {
+                    (_, #update_name::Unchanged) => {}
+                    (this, #update_name::Replace(val)) => this.clone_from(val),
+                    #(#match_arms)*
+                    _ => {}
+                }
+            }
+        }
+
+        #diff_impl
+
+        // Updates to different variants can't be merged, since which one
+        // applies depends on the current value, so the later one wins.
+        impl #composable_impl_generics live_entity::Composable<#update_type> for #name #ty_generics #composable_where_clause {
+            fn merge(earlier: #update_type, later: #update_type) -> #update_type {
+                #[allow(unreachable_patterns)]
+                match (earlier, later) {
+                    (earlier, #update_name::Unchanged) => earlier,
+                    (#update_name::Replace(mut val), later) => {
+                        live_entity::Updatable::update(&mut val, &later);
+                        #update_name::Replace(val)
+                    }
+                    #(#merge_arms)*
+                    (_, later) => later,
+                }
+            }
+
+            fn inverse(&self, update: &#update_type) -> #update_type {
+                match update {
+                    #update_name::Unchanged => #update_name::Unchanged,
+                    _ => #update_name::Replace(core::clone::Clone::clone(self)),
+                }
+            }
+        }
+
+        #composable_fns
+    })
+}
@@ -1,15 +1,111 @@
-use syn::{Data, DataStruct, DeriveInput, Fields, FieldsNamed};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{
+    Data, DeriveInput, Error, Field, Fields, GenericArgument, GenericParam, Generics, Ident, Index,
+    Member, PathArguments, Type, WherePredicate,
+};
 
-pub fn input_as_struct(input: &DeriveInput) -> &DataStruct {
+pub fn input_as_struct_fields<'a>(input: &'a DeriveInput, derive: &str) -> Result<&'a Fields, Error> {
     match &input.data {
-        Data::Struct(s) => s,
-        _ => unimplemented!("Can only derive for struct."),
+        Data::Struct(s) => Ok(&s.fields),
+        _ => Err(Error::new(
+            input.ident.span(),
+            format!("{} can only be derived for structs.", derive),
+        )),
     }
 }
 
-pub fn named_fields_of_struct(s: &DataStruct) -> &FieldsNamed {
-    match &s.fields {
-        Fields::Named(named) => named,
-        _ => unimplemented!("Can only derive for structs with named fields."),
+/// A field with the member used to access it, i.e. its name or its position
+/// in a tuple struct.
+pub type FieldMember<'a> = (Member, &'a Field);
+
+pub fn members_of(fields: &Fields) -> Vec<FieldMember<'_>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let member = match &f.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(Index {
+                    index: i as u32,
+                    span: f.span(),
+                }),
+            };
+            (member, f)
+        })
+        .collect()
+}
+
+/// Names a generated function or binding after a field, e.g. `push_tags` for
+/// a field `tags`, or `push_0` for the first field of a tuple struct.
+pub fn member_ident(prefix: &str, member: &Member) -> Ident {
+    match (prefix, member) {
+        ("", Member::Named(ident)) => ident.clone(),
+        ("", Member::Unnamed(index)) => format_ident!("_{}", index.index, span = index.span),
+        (_, Member::Named(ident)) => format_ident!("{}_{}", prefix, ident, span = ident.span()),
+        (_, Member::Unnamed(index)) => {
+            format_ident!("{}_{}", prefix, index.index, span = index.span)
+        }
+    }
+}
+
+/// Gets the `N` generic type arguments of `ty`, which must be a path type
+/// whose last segment is `expected`, e.g. `Vec` in `std::vec::Vec<String>`.
+pub fn generic_args_of<const N: usize>(ty: &Type, expected: &str) -> Result<[Type; N], Error> {
+    let err = || Error::new(ty.span(), format!("Expected a {} type here.", expected));
+    let segment = match ty {
+        Type::Path(p) => p.path.segments.last().ok_or_else(err)?,
+        _ => return Err(err()),
+    };
+    if segment.ident != expected {
+        return Err(err());
     }
+    let args: Vec<Type> = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|a| match a {
+                GenericArgument::Type(t) => Some(t.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    args.try_into().map_err(|_| err())
 }
+
+/// A `PhantomData` type that uses every type and lifetime parameter, for
+/// generated types that might not otherwise mention all of them.
+pub fn phantom_type(generics: &Generics) -> Option<Type> {
+    let params: Vec<_> = generics
+        .params
+        .iter()
+        .filter_map(|p| match p {
+            GenericParam::Type(t) => {
+                let ident = &t.ident;
+                Some(quote!(#ident))
+            }
+            GenericParam::Lifetime(l) => {
+                let lifetime = &l.lifetime;
+                Some(quote!(&#lifetime ()))
+            }
+            GenericParam::Const(_) => None,
+        })
+        .collect();
+    if params.is_empty() {
+        None
+    } else {
+        Some(syn::parse_quote! { core::marker::PhantomData<fn() -> (#(#params,)*)> })
+    }
+}
+
+/// Copies `generics`, adding `predicates` to its where clause.
+pub fn with_predicates(
+    generics: &Generics,
+    predicates: impl IntoIterator<Item = WherePredicate>,
+) -> Generics {
+    let mut generics = generics.clone();
+    generics.make_where_clause().predicates.extend(predicates);
+    generics
+}
+
@@ -0,0 +1,179 @@
+use super::util::{generic_args_of, input_as_struct_fields, members_of, with_predicates};
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{parse_quote, DeriveInput, Error, Expr, Field, LitStr, Member, Path};
+
+pub fn expand_validate(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let name = &input.ident;
+    let fields = input_as_struct_fields(input, "Validate")?;
+    let (custom, update) = parse_container_rules(input)?;
+    let checks = members_of(fields)
+        .into_iter()
+        .map(|(member, field)| field_checks(&member, field))
+        .collect::<Result<Vec<_>, _>>()?;
+    let validate_update = update.map(|update| {
+        quote! {
+            fn validate_update(
+                &self,
+                update: &<Self as live_entity::Entity>::Update,
+            ) -> core::result::Result<(), live_entity::ValidationError> {
+                #update(self, update)?;
+                let mut updated = core::clone::Clone::clone(self);
+                live_entity::Updatable::update(&mut updated, update);
+                live_entity::Validate::validate(&updated)
+            }
+        }
+    });
+
+    let generics = if input.generics.params.is_empty() {
+        input.generics.clone()
+    } else {
+        let (_, ty_generics, _) = input.generics.split_for_impl();
+        with_predicates(
+            &input.generics,
+            [parse_quote! { #name #ty_generics: live_entity::Entity }],
+        )
+    };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics live_entity::Validate for #name #ty_generics #where_clause {
+            fn validate(&self) -> core::result::Result<(), live_entity::ValidationError> {
+                #(#checks)*
+                #(#custom(self)?;)*
+                core::result::Result::Ok(())
+            }
+            #validate_update
+        }
+    })
+}
+
+/// The `custom` functions that check the whole entity and the `update`
+/// function that checks updates against it, from `#[validate(...)]` on the
+/// container.
+fn parse_container_rules(input: &DeriveInput) -> Result<(Vec<Path>, Option<Path>), Error> {
+    let mut custom = Vec::new();
+    let mut update = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("validate")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("custom") {
+                let path: LitStr = meta.value()?.parse()?;
+                custom.push(path.parse()?);
+            } else if meta.path.is_ident("update") {
+                let path: LitStr = meta.value()?.parse()?;
+                update = Some(path.parse()?);
+            } else {
+                return Err(meta.error("Unrecognized validate attribute."));
+            }
+            Ok(())
+        })?;
+    }
+    Ok((custom, update))
+}
+
+fn field_checks(member: &Member, field: &Field) -> Result<TokenStream, Error> {
+    let field_name = match member {
+        Member::Named(ident) => ident.to_string().trim_start_matches("r#").to_owned(),
+        Member::Unnamed(index) => index.index.to_string(),
+    };
+    let fail = |message: TokenStream| {
+        quote! {
+            return core::result::Result::Err(
+                live_entity::ValidationError::for_field(#field_name, #message)
+            );
+        }
+    };
+    let mut checks = Vec::new();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("validate")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("range") {
+                let (min, max) = parse_bounds(&meta)?;
+                if let Some(min) = min {
+                    let fail = fail(quote!(format!("must be at least {}", #min)));
+                    checks.push(quote! { if value < &(#min) { #fail } });
+                }
+                if let Some(max) = max {
+                    let fail = fail(quote!(format!("must be at most {}", #max)));
+                    checks.push(quote! { if value > &(#max) { #fail } });
+                }
+            } else if meta.path.is_ident("length") {
+                let (min, max) = parse_bounds(&meta)?;
+                if let Some(min) = min {
+                    let fail = fail(quote!(format!("must have a length of at least {}", #min)));
+                    checks.push(quote! {
+                        if live_entity::HasLength::length(value) < (#min) { #fail }
+                    });
+                }
+                if let Some(max) = max {
+                    let fail = fail(quote!(format!("must have a length of at most {}", #max)));
+                    checks.push(quote! {
+                        if live_entity::HasLength::length(value) > (#max) { #fail }
+                    });
+                }
+            } else if meta.path.is_ident("non_empty") {
+                let fail = fail(quote!("must not be empty"));
+                checks.push(quote! {
+                    if live_entity::HasLength::length(value) == 0 { #fail }
+                });
+            } else if meta.path.is_ident("regex") {
+                let pattern: LitStr = meta.value()?.parse()?;
+                #[cfg(feature = "regex")]
+                if let Err(e) = regex::Regex::new(&pattern.value()) {
+                    return Err(Error::new(pattern.span(), format!("Invalid pattern: {}", e)));
+                }
+                let fail = fail(quote!(concat!("must match ", #pattern)));
+                checks.push(quote_spanned! {pattern.span()=>
+                    let regex = {
+                        static REGEX: std::sync::OnceLock<live_entity::regex::Regex> =
+                            std::sync::OnceLock::new();
+                        REGEX.get_or_init(|| {
+                            live_entity::regex::Regex::new(#pattern)
+                                .expect("Invalid pattern in #[validate(regex)].")
+                        })
+                    };
+                    if !regex.is_match(core::convert::AsRef::<str>::as_ref(value)) { #fail }
+                });
+            } else {
+                return Err(meta.error("Unrecognized validate attribute."));
+            }
+            Ok(())
+        })?;
+    }
+    if checks.is_empty() {
+        return Ok(TokenStream::new());
+    }
+    // The rules for an `Option` apply to its value, if there is one.
+    Ok(if generic_args_of::<1>(&field.ty, "Option").is_ok() {
+        quote_spanned! {field.span()=>
+            if let core::option::Option::Some(value) = &self.#member {
+                #(#checks)*
+            }
+        }
+    } else {
+        quote_spanned! {field.span()=>
+            {
+                let value = &self.#member;
+                #(#checks)*
+            }
+        }
+    })
+}
+
+fn parse_bounds(meta: &syn::meta::ParseNestedMeta) -> Result<(Option<Expr>, Option<Expr>), Error> {
+    let mut min = None;
+    let mut max = None;
+    meta.parse_nested_meta(|bound| {
+        if bound.path.is_ident("min") {
+            min = Some(bound.value()?.parse()?);
+        } else if bound.path.is_ident("max") {
+            max = Some(bound.value()?.parse()?);
+        } else {
+            return Err(bound.error("Expected min or max."));
+        }
+        Ok(())
+    })?;
+    if min.is_none() && max.is_none() {
+        return Err(meta.error("Expected min or max."));
+    }
+    Ok((min, max))
+}
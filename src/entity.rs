@@ -1,8 +1,10 @@
-use crate::Updatable;
+use crate::{Stamp, Updatable};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::borrow::Cow;
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::time::Duration;
 pub trait IDTrait: Eq + Hash + Send + Sync + DeserializeOwned + Serialize + Debug + Clone {}
 impl<T: Eq + Hash + Send + Sync + DeserializeOwned + Serialize + Debug + Clone> IDTrait for T {}
 
@@ -37,6 +39,74 @@ pub trait Entity: ProtoEntity<Self::Update>
     type Update: UpdateTrait;
     type ID: IDTrait;
     const TYPE_NAME: &'static str;
+    /// The version of the type's schema, for stores that persist it to
+    /// upgrade what older versions wrote through `SchemaMigrations`.
+    const VERSION: u32 = 1;
 
-    fn get_id(&self) -> &Self::ID;
+    /// The serialized names of the `#[entity(updated_at)]` fields, for
+    /// stores that can set them to their own time.
+    const UPDATED_AT_FIELDS: &'static [&'static str] = &[];
+
+    /// The serialized names of every field stores maintain, like
+    /// `#[entity(created_at)]`, for comparing entities apart from them.
+    const MAINTAINED_FIELDS: &'static [&'static str] = &[];
+
+    /// How long entities of the type live once created, for stores that
+    /// delete them when they expire. `Store::create_with_ttl` overrides it.
+    const TTL: Option<Duration> = None;
+
+    /// Whether deleting an entity only hides it, so it can be restored with
+    /// `Store::restore` until it's purged.
+    const SOFT_DELETE: bool = false;
+
+    /// The entity's ID, borrowed unless it's a composite of several fields.
+    fn get_id(&self) -> Cow<'_, Self::ID>;
+
+    /// Fills in the fields stores maintain, like `#[entity(created_at)]`,
+    /// on an entity about to be created.
+    fn stamp_created(&mut self, _stamp: &Stamp) {}
+
+    /// Fills in the fields stores maintain, like `#[entity(updated_at)]`,
+    /// on an update about to be applied, overwriting whatever the update set
+    /// them to. A `#[entity(modified_by)]` field is left unchanged without an
+    /// actor.
+    fn stamp_updated(_update: &mut Self::Update, _stamp: &Stamp) {}
+}
+
+/// IDs made of several fields marked `#[entity_id]`, which entities can be
+/// looked up by the leading fields of through `Store::get_by_id_prefix`.
+pub trait CompositeId: IDTrait {
+    /// The names the fields are serialized under, in order.
+    const FIELDS: &'static [&'static str];
+}
+
+/// Pairs the values of an ID prefix, which serializes as a sequence like a
+/// tuple or as a single value, with the fields they're for.
+pub(crate) fn id_prefix<ID: CompositeId, V>(
+    values: Vec<V>,
+) -> Result<Vec<(&'static str, V)>, IdPrefixError> {
+    if values.len() > ID::FIELDS.len() {
+        return Err(IdPrefixError {
+            fields: ID::FIELDS.len(),
+            given: values.len(),
+        });
+    }
+    Ok(ID::FIELDS.iter().copied().zip(values).collect())
+}
+
+/// A prefix had more values than the ID has fields.
+#[derive(Debug)]
+pub struct IdPrefixError {
+    pub fields: usize,
+    pub given: usize,
+}
+impl std::fmt::Display for IdPrefixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ID prefix has {} values, but the ID only has {} fields.",
+            self.given, self.fields
+        )
+    }
 }
+impl std::error::Error for IdPrefixError {}
@@ -1,12 +1,26 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
 use crate::{Entity, Singleton, SingletonEntity};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub enum Event<E: Entity> {
     Create(E),
     Update { id: E::ID, update: E::Update },
     Delete(E::ID),
 }
 
+impl<E: Entity> Event<E> {
+    /// The ID of the entity the event happened to.
+    pub fn id(&self) -> Cow<'_, E::ID> {
+        match self {
+            Self::Create(e) => e.get_id(),
+            Self::Update { id, .. } | Self::Delete(id) => Cow::Borrowed(id),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum SingletonEvent<S: Singleton> {
     Create(S),
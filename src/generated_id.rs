@@ -0,0 +1,140 @@
+use crate::Entity;
+use std::error::Error;
+
+/// Where the ID of an entity created through `Store::insert_new` comes from.
+pub enum IdGenerator<ID> {
+    /// Made up on the spot, like a UUID.
+    Random(fn() -> ID),
+    /// Made from the next value of a counter the store keeps for the type,
+    /// starting at 1.
+    Sequence(fn(u64) -> Result<ID, SequenceOutOfRangeError>),
+}
+
+/// Entities whose IDs are assigned when they are inserted, rather than by
+/// the caller. Derived with `#[entity_id(generate = "...")]`.
+pub trait GeneratedId: Entity {
+    const ID_GENERATOR: IdGenerator<Self::ID>;
+
+    fn set_id(&mut self, id: Self::ID);
+}
+
+/// IDs that can be a random UUID (v4).
+pub trait UuidId {
+    fn new_uuid() -> Self;
+}
+
+/// IDs that can be a ULID.
+pub trait UlidId {
+    fn new_ulid() -> Self;
+}
+
+/// IDs that can be made from a value of a sequence.
+pub trait SequenceId: Sized {
+    fn from_sequence(value: u64) -> Result<Self, SequenceOutOfRangeError>;
+}
+
+/// IDs that can be a new MongoDB `ObjectId`.
+pub trait ObjectIdId {
+    fn new_object_id() -> Self;
+}
+
+#[cfg(feature = "uuid")]
+pub use uuid;
+
+#[cfg(feature = "uuid")]
+impl UuidId for uuid::Uuid {
+    fn new_uuid() -> Self {
+        uuid::Uuid::new_v4()
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl UuidId for String {
+    fn new_uuid() -> Self {
+        uuid::Uuid::new_v4().to_string()
+    }
+}
+
+#[cfg(feature = "ulid")]
+pub use ulid;
+
+#[cfg(feature = "ulid")]
+impl UlidId for ulid::Ulid {
+    fn new_ulid() -> Self {
+        ulid::Ulid::new()
+    }
+}
+
+#[cfg(feature = "ulid")]
+impl UlidId for String {
+    fn new_ulid() -> Self {
+        ulid::Ulid::new().to_string()
+    }
+}
+
+#[cfg(feature = "mongodb")]
+impl ObjectIdId for mongodb::bson::oid::ObjectId {
+    fn new_object_id() -> Self {
+        mongodb::bson::oid::ObjectId::new()
+    }
+}
+
+#[cfg(feature = "mongodb")]
+impl ObjectIdId for String {
+    fn new_object_id() -> Self {
+        mongodb::bson::oid::ObjectId::new().to_hex()
+    }
+}
+
+macro_rules! impl_sequence_id {
+    ($($t:ty),*) => {
+        $(impl SequenceId for $t {
+            fn from_sequence(value: u64) -> Result<Self, SequenceOutOfRangeError> {
+                <$t>::try_from(value).map_err(|_| SequenceOutOfRangeError {
+                    value,
+                    id_type: stringify!($t),
+                })
+            }
+        })*
+    };
+}
+impl_sequence_id!(u32, u64, usize, i32, i64);
+
+impl SequenceId for String {
+    fn from_sequence(value: u64) -> Result<Self, SequenceOutOfRangeError> {
+        Ok(value.to_string())
+    }
+}
+
+/// The store has no counters to generate sequential IDs from.
+#[derive(Debug)]
+pub struct SequenceUnsupportedError {
+    pub type_name: &'static str,
+}
+impl std::fmt::Display for SequenceUnsupportedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Store can't generate sequential IDs for {}.",
+            self.type_name
+        )
+    }
+}
+impl Error for SequenceUnsupportedError {}
+
+/// The sequence went past what the ID type can hold.
+#[derive(Debug)]
+pub struct SequenceOutOfRangeError {
+    pub value: u64,
+    pub id_type: &'static str,
+}
+impl std::fmt::Display for SequenceOutOfRangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Sequence value {} is out of range of {} IDs.",
+            self.value, self.id_type
+        )
+    }
+}
+impl Error for SequenceOutOfRangeError {}
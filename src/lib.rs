@@ -4,6 +4,9 @@ pub use entity::*;
 mod updatable;
 pub use updatable::*;
 
+mod update_ops;
+pub use update_ops::*;
+
 mod event;
 pub use event::*;
 
@@ -15,5 +18,19 @@ pub use store::*;
 mod singleton_entity;
 pub use singleton_entity::*;
 
+mod schema;
+pub use schema::*;
+
+mod validate;
+pub use validate::*;
+
+mod generated_id;
+pub use generated_id::*;
+
+mod timestamp;
+pub use timestamp::*;
+
+mod telemetry;
+
 #[cfg(feature = "mongodb")]
 pub mod mongodb;
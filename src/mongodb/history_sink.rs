@@ -0,0 +1,146 @@
+use super::mongodb_store::collection_name;
+use super::MongoDBContractViolationError;
+use crate::history::{HistoryRecord, HistorySink};
+use crate::{Entity, Event};
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, from_bson, to_bson, Bson, DateTime, Document};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use mongodb::{Collection, Database};
+use std::error::Error;
+use std::time::SystemTime;
+
+/// Records events in a `{TYPE_NAME}_history` collection per entity type.
+#[derive(Clone)]
+pub struct MongoDBHistorySink {
+    db: Database,
+    tenant: Option<String>,
+}
+
+// The counter for sequence numbers lives alongside the records, which
+// always have an `entity_id`.
+const SEQUENCE_ID: &str = "sequence";
+
+impl MongoDBHistorySink {
+    pub fn new(db: Database) -> Self {
+        Self { db, tenant: None }
+    }
+
+    /// Records into collections prefixed with `tenant`, like the tenant's
+    /// `MongoDBStore`.
+    pub fn for_tenant(&self, tenant: &str) -> Self {
+        Self {
+            db: self.db.clone(),
+            tenant: Some(tenant.to_owned()),
+        }
+    }
+
+    fn collection<E: Entity>(&self) -> Collection<Document> {
+        let name = format!("{}_history", E::TYPE_NAME);
+        self.db
+            .collection(&collection_name(self.tenant.as_deref(), &name))
+    }
+}
+
+#[async_trait]
+impl HistorySink for MongoDBHistorySink {
+    async fn record<E: Entity>(
+        &self,
+        event: &Event<E>,
+        timestamp: SystemTime,
+    ) -> Result<HistoryRecord<E>, Box<dyn Error>> {
+        let collection = self.collection::<E>();
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+        let counter = collection
+            .find_one_and_update(
+                doc! { "_id": SEQUENCE_ID },
+                doc! { "$inc": { "value": 1_i64 } },
+                options,
+            )
+            .await?
+            .ok_or(MongoDBContractViolationError(
+                "MongoDB did not return the upserted sequence counter".to_owned(),
+            ))?;
+        let sequence = counter.get_i64("value")?;
+
+        let timestamp = DateTime::from_system_time(timestamp);
+        collection
+            .insert_one(
+                doc! {
+                    "entity_id": to_bson(&*event.id())?,
+                    "sequence": sequence,
+                    "timestamp": timestamp,
+                    "event": escape_keys(to_bson(event)?),
+                },
+                None,
+            )
+            .await?;
+        Ok(HistoryRecord {
+            sequence: sequence as u64,
+            timestamp: timestamp.to_system_time(),
+            event: event.clone(),
+        })
+    }
+
+    async fn get_history<E: Entity>(
+        &self,
+        id: &E::ID,
+    ) -> Result<Vec<HistoryRecord<E>>, Box<dyn Error>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "timestamp": 1, "sequence": 1 })
+            .build();
+        let docs: Vec<Document> = self
+            .collection::<E>()
+            .find(doc! { "entity_id": to_bson(id)? }, options)
+            .await?
+            .try_collect()
+            .await?;
+        docs.into_iter()
+            .map(|mut d| {
+                let event = d.remove("event").unwrap_or(Bson::Null);
+                Ok(HistoryRecord {
+                    sequence: d.get_i64("sequence")? as u64,
+                    timestamp: d.get_datetime("timestamp")?.to_system_time(),
+                    event: from_bson(unescape_keys(event))?,
+                })
+            })
+            .collect()
+    }
+}
+
+// Updates serialize with `$` keys for their operations, like `$push`, which
+// can't be stored as field names. They're stored with a `~` in front, as are
+// keys already starting with `~`, so that they read back unchanged.
+const ESCAPE: char = '~';
+
+fn escape_keys(event: Bson) -> Bson {
+    map_keys(event, &|key| {
+        if key.starts_with('$') || key.starts_with(ESCAPE) {
+            format!("{}{}", ESCAPE, key)
+        } else {
+            key
+        }
+    })
+}
+
+fn unescape_keys(event: Bson) -> Bson {
+    map_keys(event, &|key| match key.strip_prefix(ESCAPE) {
+        Some(key) => key.to_owned(),
+        None => key,
+    })
+}
+
+fn map_keys(value: Bson, rename: &impl Fn(String) -> String) -> Bson {
+    match value {
+        Bson::Document(d) => Bson::Document(
+            d.into_iter()
+                .map(|(key, value)| (rename(key), map_keys(value, rename)))
+                .collect(),
+        ),
+        Bson::Array(a) => Bson::Array(a.into_iter().map(|v| map_keys(v, rename)).collect()),
+        other => other,
+    }
+}
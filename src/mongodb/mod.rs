@@ -1,2 +1,8 @@
 mod mongodb_store;
 pub use mongodb_store::*;
+
+mod history_sink;
+pub use history_sink::*;
+
+mod update_document;
+pub use update_document::UnsupportedUpdateError;
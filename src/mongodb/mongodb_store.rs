@@ -1,17 +1,61 @@
-use crate::{Entity, Event, NotFoundError, Store};
+use super::update_document::{from_update_description, to_update_document};
+use super::MongoDBHistorySink;
+use crate::entity::id_prefix;
+use crate::telemetry::{self, instrument, Watching};
+use crate::{
+    ActingAs, CompositeId, Entity, Event, MultiTenant, NotFoundError, SchemaMigrations, Stamp,
+    Store,
+};
 use async_trait::async_trait;
 use futures_util::{StreamExt, TryStreamExt};
-use mongodb::bson::{doc, from_bson, from_document, to_bson, to_document, Document};
+use mongodb::bson::{
+    doc, from_bson, from_document, to_bson, to_document, Bson, DateTime, Document,
+};
 use mongodb::change_stream::event::{ChangeStreamEvent, OperationType};
-use mongodb::options::{ChangeStreamOptions, ClientOptions, FullDocumentType};
-use mongodb::{Client, Database};
+use mongodb::options::{
+    ChangeStreamOptions, ClientOptions, FindOneAndUpdateOptions, FindOneOptions, FindOptions,
+    FullDocumentType, IndexOptions, ReplaceOptions, ReturnDocument,
+};
+use mongodb::{Client, Collection, Database, IndexModel};
+use serde::Serialize;
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt::Formatter;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 use tokio::sync::broadcast::Sender;
+use tokio::sync::Notify;
+
+const STORE: &str = "mongodb";
+
+/// The collection holding the counters sequential IDs come from, with a
+/// document per entity type.
+pub const COUNTERS_COLLECTION: &str = "counters";
+
+/// The field each document's schema version is stored in. Documents
+/// without it were written at version 1.
+pub const VERSION_FIELD: &str = "_version";
+
+/// The field holding when an expiring document does, which a TTL index
+/// deletes it after. MongoDB only looks for expired documents about once a
+/// minute, so they can linger for that long.
+pub const EXPIRES_AT_FIELD: &str = "_expires_at";
+
+/// The field marking the documents of `#[entity(soft_delete)]` types that
+/// are deleted.
+pub const DELETED_FIELD: &str = "_deleted";
+
+/// The fields the store keeps on documents besides the entity's own.
+const STORE_FIELDS: [&str; 3] = [VERSION_FIELD, EXPIRES_AT_FIELD, DELETED_FIELD];
 
 #[derive(Clone)]
 pub struct MongoDBStore {
     db: Database,
+    migrations: Arc<SchemaMigrations>,
+    tenant: Option<String>,
+    actor: Option<String>,
+    /// The collections known to have a TTL index.
+    ttl_indexed: Arc<Mutex<HashSet<String>>>,
 }
 
 impl MongoDBStore {
@@ -25,27 +69,190 @@ impl MongoDBStore {
         let client = Client::with_options(options);
         client
             .map(|c| c.database(&database_name))
-            .map(|db| Self { db })
+            .map(|db| db.into())
     }
 
-    pub async fn delete_filtered<E: Entity>(
+    /// Upgrades documents written at older schema versions with
+    /// `migrations` when they are read or watched.
+    pub fn with_migrations(mut self, migrations: SchemaMigrations) -> Self {
+        self.migrations = Arc::new(migrations);
+        self
+    }
+
+    /// Rewrites every stored `E` older than `E::VERSION` at the current
+    /// version, returning how many were rewritten. Reads upgrade outdated
+    /// documents without this; it only saves migrating them again on every
+    /// read, and can run in the background while the store is in use.
+    pub async fn rewrite_outdated<E: Entity>(&self) -> Result<usize, Box<dyn Error>> {
+        self.rewrite::<E>(doc! {}).await
+    }
+
+    async fn rewrite<E: Entity>(&self, mut filter: Document) -> Result<usize, Box<dyn Error>> {
+        if E::VERSION <= 1 {
+            return Ok(0);
+        }
+        filter.insert(
+            "$or",
+            vec![
+                doc! { VERSION_FIELD: { "$lt": E::VERSION } },
+                doc! { VERSION_FIELD: { "$exists": false } },
+            ],
+        );
+        let collection = self.collection::<E, Document>();
+        let mut cursor = collection.find(filter, None).await?;
+        let mut rewritten = 0;
+        while let Some(doc) = cursor.try_next().await? {
+            let id = doc
+                .get("_id")
+                .cloned()
+                .ok_or(MongoDBContractViolationError(
+                    "MongoDB returned a document without _id".to_owned(),
+                ))?;
+            let previous = doc.get(VERSION_FIELD).cloned().unwrap_or(Bson::Null);
+            let kept: Vec<(&str, Bson)> = [EXPIRES_AT_FIELD, DELETED_FIELD]
+                .into_iter()
+                .filter_map(|field| Some((field, doc.get(field)?.clone())))
+                .collect();
+            let mut upgraded = self.upgrade::<E>(doc)?;
+            upgraded.insert(VERSION_FIELD, E::VERSION);
+            upgraded.extend(
+                kept.into_iter()
+                    .map(|(field, value)| (field.to_owned(), value)),
+            );
+            // Only replace the version that was read, so a concurrent rewrite
+            // is not migrated twice.
+            let result = collection
+                .replace_one(doc! { "_id": id, VERSION_FIELD: previous }, upgraded, None)
+                .await?;
+            rewritten += result.modified_count as usize;
+        }
+        Ok(rewritten)
+    }
+
+    /// Migrates a stored document to `E::VERSION` and strips the fields the
+    /// store keeps on it.
+    fn upgrade<E: Entity>(&self, mut doc: Document) -> Result<Document, Box<dyn Error>> {
+        let version = stored_version(&doc);
+        for field in STORE_FIELDS {
+            doc.remove(field);
+        }
+        if version >= E::VERSION {
+            return Ok(doc);
+        }
+        let value = Bson::Document(doc).into_relaxed_extjson();
+        let value = self.migrations.upgrade::<E>(value, version)?;
+        Ok(from_bson(Bson::try_from(value)?)?)
+    }
+
+    fn decode<E: Entity>(&self, doc: Document) -> Result<E, Box<dyn Error>> {
+        Ok(from_document(self.upgrade::<E>(doc)?)?)
+    }
+
+    /// A history sink that records into the same database, for the same
+    /// tenant.
+    pub fn history_sink(&self) -> MongoDBHistorySink {
+        let sink = MongoDBHistorySink::new(self.db.clone());
+        match &self.tenant {
+            Some(tenant) => sink.for_tenant(tenant),
+            None => sink,
+        }
+    }
+
+    fn collection<E: Entity, T>(&self) -> Collection<T> {
+        self.db
+            .collection(&collection_name(self.tenant.as_deref(), E::TYPE_NAME))
+    }
+
+    /// Stamps the entity as created unless it's being inserted as it is.
+    async fn insert<E: Entity>(
         &self,
-        filter: Option<Document>,
+        operation: &'static str,
+        entity: &E,
+        raw: bool,
+        expires_at: Option<SystemTime>,
     ) -> Result<(), Box<dyn Error>> {
-        let collection = self.db.collection::<E>(E::TYPE_NAME);
-        collection
-            .delete_many(filter.unwrap_or(doc! {}), None)
+        let id = &*entity.get_id();
+        instrument(STORE, operation, E::TYPE_NAME, Some(id), async {
+            let collection = self.collection::<E, Document>();
+            let mut entity = entity.clone();
+            if !raw {
+                entity.stamp_created(&Stamp::now(self.actor.as_deref()));
+            }
+            let mut doc = to_document(&entity)?;
+            doc.insert("_id", to_bson(id)?);
+            doc.insert(VERSION_FIELD, E::VERSION);
+            if E::SOFT_DELETE {
+                // A deleted entity makes way for a new one with its ID.
+                collection
+                    .delete_one(doc! { "_id": to_bson(id)?, DELETED_FIELD: true }, None)
+                    .await?;
+            }
+            if let Some(expires_at) = expires_at {
+                self.ensure_ttl_index::<E>().await?;
+                doc.insert(EXPIRES_AT_FIELD, DateTime::from_system_time(expires_at));
+            }
+            if raw {
+                // A copy replaces whatever has its ID.
+                let options = ReplaceOptions::builder().upsert(true).build();
+                collection
+                    .replace_one(doc! { "_id": to_bson(id)? }, doc, options)
+                    .await?;
+            } else {
+                collection.insert_one(doc, None).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Creates the index that deletes expired documents, once per
+    /// collection.
+    async fn ensure_ttl_index<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
+        let name = collection_name(self.tenant.as_deref(), E::TYPE_NAME);
+        if self.ttl_indexed.lock().unwrap().contains(&name) {
+            return Ok(());
+        }
+        let index = IndexModel::builder()
+            .keys(doc! { EXPIRES_AT_FIELD: 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        self.collection::<E, Document>()
+            .create_index(index, None)
             .await?;
+        self.ttl_indexed.lock().unwrap().insert(name);
         Ok(())
     }
 
+    /// The IDs of the documents marked deleted.
+    async fn deleted_ids<E: Entity>(&self) -> Result<HashSet<E::ID>, Box<dyn Error>> {
+        let options = FindOptions::builder().projection(doc! { "_id": 1 }).build();
+        let res = self
+            .collection::<E, Document>()
+            .find(doc! { DELETED_FIELD: true }, options)
+            .await?;
+        let docs: Vec<Document> = res.try_collect().await?;
+        docs.into_iter()
+            .map(|doc| Ok(from_bson(doc.get("_id").cloned().unwrap_or(Bson::Null))?))
+            .collect()
+    }
+
+    fn counters_name(&self) -> String {
+        collection_name(self.tenant.as_deref(), COUNTERS_COLLECTION)
+    }
+
+    pub async fn delete_filtered<E: Entity>(
+        &self,
+        filter: Option<Document>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.delete_matching::<E>("delete_filtered", filter).await
+    }
+
     pub async fn get_filtered<E: Entity>(
         &self,
         filter: Option<Document>,
     ) -> Result<Vec<E>, Box<dyn Error>> {
-        let collection = self.db.collection::<E>(E::TYPE_NAME);
-        let res = collection.find(filter, None).await?;
-        Ok(res.try_collect().await?)
+        self.find("get_filtered", live::<E>(filter.unwrap_or_default()), None)
+            .await
     }
 
     pub async fn watch_filtered<E: Entity>(
@@ -53,7 +260,75 @@ impl MongoDBStore {
         channel: Sender<Event<E>>,
         filter: Option<Document>,
     ) -> Result<(), Box<dyn Error>> {
-        let collection = self.db.collection::<Document>(E::TYPE_NAME);
+        self.watch_changes("watch_filtered", channel, filter, None).await
+    }
+
+    async fn delete_matching<E: Entity>(
+        &self,
+        operation: &'static str,
+        filter: Option<Document>,
+    ) -> Result<(), Box<dyn Error>> {
+        instrument(STORE, operation, E::TYPE_NAME, None::<&E::ID>, async {
+            let collection = self.collection::<E, E>();
+            if E::SOFT_DELETE {
+                collection
+                    .update_many(
+                        live::<E>(filter.unwrap_or_default()),
+                        doc! { "$set": { DELETED_FIELD: true } },
+                        None,
+                    )
+                    .await?;
+                return Ok(());
+            }
+            collection
+                .delete_many(filter.unwrap_or(doc! {}), None)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn find<E: Entity>(
+        &self,
+        operation: &'static str,
+        filter: Document,
+        options: Option<FindOptions>,
+    ) -> Result<Vec<E>, Box<dyn Error>> {
+        instrument(STORE, operation, E::TYPE_NAME, None::<&E::ID>, async {
+            let collection = self.collection::<E, Document>();
+            let res = collection.find(filter, options).await?;
+            let docs: Vec<Document> = res.try_collect().await?;
+            docs.into_iter().map(|doc| self.decode(doc)).collect()
+        })
+        .await
+    }
+
+    async fn watch_changes<E: Entity>(
+        &self,
+        operation: &'static str,
+        channel: Sender<Event<E>>,
+        filter: Option<Document>,
+        ready: Option<Arc<Notify>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let _watching = Watching::start(STORE, E::TYPE_NAME);
+        instrument(
+            STORE,
+            operation,
+            E::TYPE_NAME,
+            None::<&E::ID>,
+            self.forward_changes(channel, filter, ready),
+        )
+        .await
+    }
+
+    /// Notifies `ready`, if given, once the change stream is open.
+    async fn forward_changes<E: Entity>(
+        &self,
+        channel: Sender<Event<E>>,
+        filter: Option<Document>,
+        ready: Option<Arc<Notify>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let collection = self.collection::<E, Document>();
         let mut mtch = doc! { "$match": {
             "operationType": {
                 "$in": to_bson(&[OperationType::Update, OperationType::Insert, OperationType::Delete, OperationType::Replace])?
@@ -68,37 +343,80 @@ impl MongoDBStore {
             .full_document(Some(FullDocumentType::UpdateLookup))
             .build();
         let mut watch = collection.watch([mtch], options).await?;
+        // The documents of a `soft_delete` type marked deleted, read once the
+        // stream is open so none are missed. Removing one of them only drops
+        // what watchers were told was deleted already, while removing any
+        // other, as when it expires or is purged, is news.
+        let mut deleted = HashSet::new();
+        if E::SOFT_DELETE {
+            deleted = self.deleted_ids::<E>().await?;
+        }
+        if let Some(ready) = ready {
+            ready.notify_one();
+        }
         while let Some(evt) = watch.next().await.transpose()? {
             match evt.operation_type {
                 OperationType::Insert => {
                     let doc = evt.full_document.ok_or(MongoDBContractViolationError(
                         "MongoDB did not provide full document on insert event".to_owned(),
                     ))?;
-                    let entity = from_document(doc)?;
-                    channel.send(Event::Create(entity))?;
+                    let entity = self.decode(doc)?;
+                    send(&channel, Event::Create(entity))?;
                 }
                 OperationType::Update => {
                     let id = get_id_from_change_event::<E>(&evt)?;
-                    let doc = evt
-                        .update_description
-                        .ok_or(MongoDBContractViolationError(
-                            "MongoDB did not provide update description on update event".to_owned(),
-                        ))?
-                        .updated_fields;
+                    if E::SOFT_DELETE {
+                        match soft_deletion(&evt) {
+                            Some(SoftDeletion::Deleted) => {
+                                deleted.insert(id.clone());
+                                send(&channel, Event::Delete(id))?;
+                                continue;
+                            }
+                            // Gone again if there's no document to look up.
+                            Some(SoftDeletion::Restored) => {
+                                deleted.remove(&id);
+                                if let Some(doc) = evt.full_document {
+                                    send(&channel, Event::Create(self.decode(doc)?))?;
+                                }
+                                continue;
+                            }
+                            None if evt.full_document.as_ref().is_some_and(is_deleted) => continue,
+                            None => {}
+                        }
+                    }
+                    let doc = match evt.full_document {
+                        // An update to an outdated document describes fields of
+                        // its old schema, so send the whole upgraded document.
+                        Some(doc) if stored_version(&doc) < E::VERSION => self.upgrade::<E>(doc)?,
+                        full_document => {
+                            let description =
+                                evt.update_description.ok_or(MongoDBContractViolationError(
+                                    "MongoDB did not provide update description on update event"
+                                        .to_owned(),
+                                ))?;
+                            from_update_description(description, full_document.as_ref())
+                        }
+                    };
                     let update: E::Update = from_document(doc)?;
-                    channel.send(Event::Update { id, update })?;
+                    send(&channel, Event::Update { id, update })?;
                 }
                 OperationType::Delete => {
                     let id = get_id_from_change_event::<E>(&evt)?;
-                    channel.send(Event::Delete(id))?;
+                    if !deleted.remove(&id) {
+                        send(&channel, Event::Delete(id))?;
+                    }
                 }
                 OperationType::Replace => {
                     let id = get_id_from_change_event::<E>(&evt)?;
                     let doc = evt.full_document.ok_or(MongoDBContractViolationError(
                         "MongoDB did not provide full document on replace event".to_owned(),
                     ))?;
-                    let update: E::Update = from_document(doc)?;
-                    channel.send(Event::Update { id, update })?;
+                    if E::SOFT_DELETE && is_deleted(&doc) {
+                        deleted.insert(id);
+                        continue;
+                    }
+                    let update: E::Update = from_document(self.upgrade::<E>(doc)?)?;
+                    send(&channel, Event::Update { id, update })?;
                 }
                 _ => {
                     return Err(MongoDBContractViolationError(format!(
@@ -115,12 +433,18 @@ impl MongoDBStore {
 
 impl Into<MongoDBStore> for Database {
     fn into(self) -> MongoDBStore {
-        MongoDBStore { db: self }
+        MongoDBStore {
+            db: self,
+            migrations: Default::default(),
+            tenant: None,
+            actor: None,
+            ttl_indexed: Default::default(),
+        }
     }
 }
 
 #[derive(Debug)]
-pub struct MongoDBContractViolationError(String);
+pub struct MongoDBContractViolationError(pub(crate) String);
 impl std::fmt::Display for MongoDBContractViolationError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_str(&self.0)
@@ -128,14 +452,108 @@ impl std::fmt::Display for MongoDBContractViolationError {
 }
 impl Error for MongoDBContractViolationError {}
 
+impl MultiTenant for MongoDBStore {
+    type Tenant = MongoDBStore;
+
+    /// A tenant's entities are kept in collections prefixed with its name,
+    /// like `acme.employees`. Tenants aren't nested, so a tenant's view can
+    /// give views of any other tenant.
+    fn for_tenant(&self, tenant: &str) -> MongoDBStore {
+        MongoDBStore {
+            tenant: Some(tenant.to_owned()),
+            ..self.clone()
+        }
+    }
+}
+
+impl ActingAs for MongoDBStore {
+    type Acting = MongoDBStore;
+
+    fn acting_as(&self, actor: &str) -> MongoDBStore {
+        MongoDBStore {
+            actor: Some(actor.to_owned()),
+            ..self.clone()
+        }
+    }
+}
+
+/// The collection for a type, prefixed with the tenant if there is one.
+pub(crate) fn collection_name(tenant: Option<&str>, name: &str) -> String {
+    match tenant {
+        Some(tenant) => format!("{}.{}", tenant, name),
+        None => name.to_owned(),
+    }
+}
+
 #[async_trait]
 impl Store for MongoDBStore {
     async fn create<E: Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
-        let collection = self.db.collection::<Document>(E::TYPE_NAME);
-        let mut doc = to_document(entity)?;
-        doc.insert("_id", to_bson(entity.get_id())?);
-        collection.insert_one(doc, None).await?;
-        Ok(())
+        let expires_at = E::TTL.map(|ttl| SystemTime::now() + ttl);
+        self.insert("create", entity, false, expires_at).await
+    }
+
+    async fn create_with_ttl<E: Entity>(
+        &self,
+        entity: &E,
+        ttl: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        let expires_at = SystemTime::now() + ttl;
+        self.insert("create_with_ttl", entity, false, Some(expires_at)).await
+    }
+
+    async fn insert_raw<E: Entity>(
+        &self,
+        entity: &E,
+        expires_at: Option<SystemTime>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.insert("insert_raw", entity, true, expires_at).await
+    }
+
+    async fn get_expiry<E: Entity>(
+        &self,
+        id: &E::ID,
+    ) -> Result<Option<SystemTime>, Box<dyn Error>> {
+        instrument(STORE, "get_expiry", E::TYPE_NAME, Some(id), async {
+            let collection = self.collection::<E, Document>();
+            let query = live::<E>(doc! { "_id": to_bson(id)? });
+            let options = FindOneOptions::builder()
+                .projection(doc! { EXPIRES_AT_FIELD: 1 })
+                .build();
+            let doc = collection
+                .find_one(query, options)
+                .await?
+                .ok_or(NotFoundError(id.clone()))?;
+            Ok(doc.get_datetime(EXPIRES_AT_FIELD).ok().map(|at| at.to_system_time()))
+        })
+        .await
+    }
+
+    async fn next_sequence<E: Entity>(&self) -> Result<u64, Box<dyn Error>> {
+        instrument(
+            STORE,
+            "next_sequence",
+            E::TYPE_NAME,
+            None::<&E::ID>,
+            async {
+                let counters = self.db.collection::<Document>(&self.counters_name());
+                let options = FindOneAndUpdateOptions::builder()
+                    .upsert(true)
+                    .return_document(ReturnDocument::After)
+                    .build();
+                let counter = counters
+                    .find_one_and_update(
+                        doc! { "_id": E::TYPE_NAME },
+                        doc! { "$inc": { "value": 1_i64 } },
+                        options,
+                    )
+                    .await?
+                    .ok_or(MongoDBContractViolationError(
+                        "MongoDB returned no counter after upserting it".to_owned(),
+                    ))?;
+                Ok(counter.get_i64("value")? as u64)
+            },
+        )
+        .await
     }
 
     async fn update<E: Entity>(
@@ -143,41 +561,225 @@ impl Store for MongoDBStore {
         id: &E::ID,
         update: &E::Update,
     ) -> Result<(), Box<dyn Error>> {
-        let collection = self.db.collection::<E>(E::TYPE_NAME);
-        let query = doc! { "_id": to_bson(id)? };
-        let update = vec![doc! {
-            "$set": to_document(&update)?
-        }];
-        collection.update_one(query, update, None).await?;
-        Ok(())
+        instrument(STORE, "update", E::TYPE_NAME, Some(id), async {
+            let collection = self.collection::<E, E>();
+            let mut query = live::<E>(doc! { "_id": to_bson(id)? });
+            if to_update_document(to_document(update)?)?.is_empty() {
+                return Ok(());
+            }
+            let mut update = update.clone();
+            E::stamp_updated(&mut update, &Stamp::now(self.actor.as_deref()));
+            let mut update = to_update_document(to_document(&update)?)?;
+            set_current_date::<E>(&mut update);
+            if E::VERSION > 1 {
+                query.insert(VERSION_FIELD, doc! { "$gte": E::VERSION });
+            }
+            let result = collection
+                .update_one(query.clone(), update.clone(), None)
+                .await?;
+            // An outdated document has to be upgraded before fields of the
+            // current schema can be set on it.
+            if result.matched_count == 0
+                && self.rewrite::<E>(doc! { "_id": to_bson(id)? }).await? > 0
+            {
+                collection.update_one(query, update, None).await?;
+            }
+            Ok(())
+        })
+        .await
     }
 
     async fn delete_all<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
-        self.delete_filtered::<E>(None).await
+        self.delete_matching::<E>("delete_all", None).await
     }
 
     async fn delete_by_id<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
-        let collection = self.db.collection::<E>(E::TYPE_NAME);
-        let query = doc! { "_id": to_bson(id)? };
-        collection.delete_one(query, None).await?;
-        Ok(())
+        instrument(STORE, "delete_by_id", E::TYPE_NAME, Some(id), async {
+            let collection = self.collection::<E, E>();
+            let query = doc! { "_id": to_bson(id)? };
+            if E::SOFT_DELETE {
+                collection
+                    .update_one(
+                        live::<E>(query),
+                        doc! { "$set": { DELETED_FIELD: true } },
+                        None,
+                    )
+                    .await?;
+                return Ok(());
+            }
+            collection.delete_one(query, None).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn restore<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        instrument(STORE, "restore", E::TYPE_NAME, Some(id), async {
+            let collection = self.collection::<E, E>();
+            let query = doc! { "_id": to_bson(id)?, DELETED_FIELD: true };
+            let result = collection
+                .update_one(query, doc! { "$unset": { DELETED_FIELD: "" } }, None)
+                .await?;
+            if result.matched_count == 0 {
+                return Err(NotFoundError(id.clone()).into());
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Removes the document whether or not it's marked deleted. Watches send
+    /// `Event::Delete` for it unless it was.
+    async fn purge<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        instrument(STORE, "purge", E::TYPE_NAME, Some(id), async {
+            let query = doc! { "_id": to_bson(id)? };
+            self.collection::<E, E>().delete_one(query, None).await?;
+            Ok(())
+        })
+        .await
     }
 
     async fn get_all<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
-        self.get_filtered(None).await
+        self.find("get_all", live::<E>(Document::new()), None).await
+    }
+
+    /// Pages in the order MongoDB sorts the `_id`s in.
+    async fn get_page<E: Entity>(
+        &self,
+        after: Option<&E::ID>,
+        limit: usize,
+    ) -> Result<Vec<E>, Box<dyn Error>> {
+        let filter = match after {
+            Some(after) => doc! { "_id": { "$gt": to_bson(after)? } },
+            None => Document::new(),
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .limit(i64::try_from(limit)?)
+            .build();
+        self.find("get_page", live::<E>(filter), Some(options)).await
+    }
+
+    async fn get_deleted<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        self.find("get_deleted", doc! { DELETED_FIELD: true }, None).await
     }
 
     async fn get_by_id<E: Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
-        let collection = self.db.collection::<E>(E::TYPE_NAME);
-        let query = doc! { "_id": to_bson(id)? };
-        collection
-            .find_one(query, None)
-            .await?
-            .ok_or(NotFoundError(id.clone()).into())
+        instrument(STORE, "get_by_id", E::TYPE_NAME, Some(id), async {
+            let collection = self.collection::<E, Document>();
+            let query = live::<E>(doc! { "_id": to_bson(id)? });
+            let doc = collection
+                .find_one(query, None)
+                .await?
+                .ok_or(NotFoundError(id.clone()))?;
+            self.decode(doc)
+        })
+        .await
+    }
+
+    /// Filters on the fields of the `_id` subdocument, so an index on them
+    /// serves the lookup.
+    async fn get_by_id_prefix<E: Entity, P: Serialize + Sync>(
+        &self,
+        prefix: &P,
+    ) -> Result<Vec<E>, Box<dyn Error>>
+    where
+        E::ID: CompositeId,
+    {
+        let values = match to_bson(prefix)? {
+            Bson::Array(values) => values,
+            value => vec![value],
+        };
+        let mut filter = Document::new();
+        for (field, value) in id_prefix::<E::ID, _>(values)? {
+            filter.insert(format!("_id.{}", field), value);
+        }
+        self.find("get_by_id_prefix", live::<E>(filter), None).await
     }
 
     async fn watch<E: Entity>(&self, channel: Sender<Event<E>>) -> Result<(), Box<dyn Error>> {
-        self.watch_filtered(channel, None).await
+        self.watch_changes("watch", channel, None, None).await
+    }
+
+    async fn watch_notifying<E: Entity>(
+        &self,
+        channel: Sender<Event<E>>,
+        ready: Arc<Notify>,
+    ) -> Result<(), Box<dyn Error>> {
+        let result = self
+            .watch_changes("watch", channel, None, Some(ready.clone()))
+            .await;
+        ready.notify_one();
+        result
+    }
+}
+
+fn send<E: Entity>(channel: &Sender<Event<E>>, event: Event<E>) -> Result<(), Box<dyn Error>> {
+    telemetry::event_sent(STORE, E::TYPE_NAME, telemetry::event_kind(&event));
+    channel.send(event)?;
+    Ok(())
+}
+
+/// Has MongoDB set the `updated_at` fields to its own time, rather than to
+/// the one they were stamped with.
+fn set_current_date<E: Entity>(update: &mut Document) {
+    let Ok(set) = update.get_document_mut("$set") else {
+        return;
+    };
+    let mut current_date = Document::new();
+    for field in E::UPDATED_AT_FIELDS {
+        if set.remove(*field).is_some() {
+            current_date.insert(*field, true);
+        }
+    }
+    if set.is_empty() {
+        update.remove("$set");
+    }
+    if !current_date.is_empty() {
+        update.insert("$currentDate", current_date);
+    }
+}
+
+/// Leaves deleted documents out of what `filter` matches, for types that
+/// are soft deleted.
+fn live<E: Entity>(mut filter: Document) -> Document {
+    if E::SOFT_DELETE {
+        filter.insert(DELETED_FIELD, doc! { "$ne": true });
+    }
+    filter
+}
+
+fn is_deleted(doc: &Document) -> bool {
+    doc.get_bool(DELETED_FIELD).unwrap_or(false)
+}
+
+/// How an update to a document of a `soft_delete` type changed whether it's
+/// deleted.
+enum SoftDeletion {
+    Deleted,
+    Restored,
+}
+
+fn soft_deletion(event: &ChangeStreamEvent<Document>) -> Option<SoftDeletion> {
+    let description = event.update_description.as_ref()?;
+    if description.updated_fields.contains_key(DELETED_FIELD) {
+        Some(SoftDeletion::Deleted)
+    } else if description
+        .removed_fields
+        .iter()
+        .any(|f| f == DELETED_FIELD)
+    {
+        Some(SoftDeletion::Restored)
+    } else {
+        None
+    }
+}
+
+fn stored_version(doc: &Document) -> u32 {
+    match doc.get(VERSION_FIELD) {
+        Some(Bson::Int32(version)) => *version as u32,
+        Some(Bson::Int64(version)) => *version as u32,
+        _ => 1,
     }
 }
 
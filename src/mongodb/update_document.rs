@@ -0,0 +1,150 @@
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::change_stream::event::UpdateDescription;
+use std::error::Error;
+use std::fmt::Formatter;
+
+#[derive(Debug)]
+pub struct UnsupportedUpdateError(String);
+impl std::fmt::Display for UnsupportedUpdateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+impl Error for UnsupportedUpdateError {}
+
+/// Translates a serialized update into a MongoDB update document. Plain
+/// fields are `$set` and nulls, such as `Patch::Clear`, are `$unset`, while
+/// the operations of `ListUpdate`, `MapUpdate` and `NumericUpdate` map onto
+/// their MongoDB operators.
+pub(crate) fn to_update_document(update: Document) -> Result<Document, Box<dyn Error>> {
+    let mut set = Document::new();
+    let mut unset = Document::new();
+    let mut push = Document::new();
+    let mut pull = Document::new();
+    let mut inc = Document::new();
+    for (field, val) in update {
+        let op = match &val {
+            Bson::Document(d) if d.len() == 1 => d
+                .iter()
+                .next()
+                .filter(|(k, _)| k.starts_with('$'))
+                .map(|(k, v)| (k.clone(), v.clone())),
+            _ => None,
+        };
+        match op {
+            None if val == Bson::Null => {
+                unset.insert(field, "");
+            }
+            None => {
+                set.insert(field, val);
+            }
+            Some((op, arg)) => match op.as_str() {
+                "$push" => {
+                    push.insert(field, doc! { "$each": arg });
+                }
+                "$pull" => {
+                    pull.insert(field, doc! { "$in": arg });
+                }
+                "$inc" => {
+                    inc.insert(field, arg);
+                }
+                "$insert" => {
+                    for (key, entry) in expect_document(&field, arg)? {
+                        set.insert(format!("{}.{}", field, key), entry);
+                    }
+                }
+                "$remove" => {
+                    for key in expect_array(&field, arg)? {
+                        match key {
+                            Bson::String(key) => unset.insert(format!("{}.{}", field, key), ""),
+                            other => {
+                                return Err(UnsupportedUpdateError(format!(
+                                    "Map keys must serialize as strings, but field {} has key {}.",
+                                    field, other
+                                ))
+                                .into())
+                            }
+                        };
+                    }
+                }
+                _ => {
+                    return Err(UnsupportedUpdateError(format!(
+                        "Unknown update operation {} on field {}.",
+                        op, field
+                    ))
+                    .into())
+                }
+            },
+        }
+    }
+
+    let mut update = Document::new();
+    for (op, fields) in [
+        ("$set", set),
+        ("$unset", unset),
+        ("$push", push),
+        ("$pull", pull),
+        ("$inc", inc),
+    ] {
+        if !fields.is_empty() {
+            update.insert(op, fields);
+        }
+    }
+    Ok(update)
+}
+
+/// Rebuilds a serialized update from a change stream's update description.
+/// Changes below the top level, such as an element pushed onto an array or
+/// an entry removed from a map, are reported as a replacement of the whole
+/// top-level field with its value from `full_document`. Removed top-level
+/// fields are reported as null.
+pub(crate) fn from_update_description(
+    description: UpdateDescription,
+    full_document: Option<&Document>,
+) -> Document {
+    let mut update = Document::new();
+    let replace_from_full = |path: &str, update: &mut Document| {
+        let top = path.split('.').next().unwrap_or(path);
+        if let Some(val) = full_document.and_then(|d| d.get(top)) {
+            update.insert(top, val.clone());
+        }
+    };
+    for (path, val) in description.updated_fields {
+        if path.contains('.') {
+            replace_from_full(&path, &mut update);
+        } else {
+            update.insert(path, val);
+        }
+    }
+    for path in description.removed_fields {
+        if path.contains('.') {
+            replace_from_full(&path, &mut update);
+        } else {
+            update.insert(path, Bson::Null);
+        }
+    }
+    for truncated in description.truncated_arrays.unwrap_or_default() {
+        replace_from_full(&truncated.field, &mut update);
+    }
+    update
+}
+
+fn expect_document(field: &str, val: Bson) -> Result<Document, UnsupportedUpdateError> {
+    match val {
+        Bson::Document(d) => Ok(d),
+        other => Err(UnsupportedUpdateError(format!(
+            "Expected a document of entries for field {}, got {}.",
+            field, other
+        ))),
+    }
+}
+
+fn expect_array(field: &str, val: Bson) -> Result<Vec<Bson>, UnsupportedUpdateError> {
+    match val {
+        Bson::Array(a) => Ok(a),
+        other => Err(UnsupportedUpdateError(format!(
+            "Expected an array of keys for field {}, got {}.",
+            field, other
+        ))),
+    }
+}
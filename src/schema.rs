@@ -0,0 +1,65 @@
+use std::{collections::HashMap, error::Error, fmt::Formatter, sync::Arc};
+
+use serde_json::Value;
+
+use crate::Entity;
+
+type MigrationFn = Arc<dyn Fn(Value) -> Result<Value, Box<dyn Error>> + Send + Sync>;
+
+/// Functions that upgrade stored entities from older schema versions, one
+/// version at a time, so that they still deserialize after their type
+/// changes.
+#[derive(Clone, Default)]
+pub struct SchemaMigrations {
+    steps: HashMap<&'static str, HashMap<u32, MigrationFn>>,
+}
+
+impl SchemaMigrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers how to upgrade an `E` from version `from` to `from + 1`.
+    pub fn register<E: Entity>(
+        mut self,
+        from: u32,
+        migrate: impl Fn(Value) -> Result<Value, Box<dyn Error>> + Send + Sync + 'static,
+    ) -> Self {
+        self.steps
+            .entry(E::TYPE_NAME)
+            .or_default()
+            .insert(from, Arc::new(migrate));
+        self
+    }
+
+    /// Upgrades a serialized `E` from version `from` to `E::VERSION`.
+    pub fn upgrade<E: Entity>(&self, mut value: Value, from: u32) -> Result<Value, Box<dyn Error>> {
+        for version in from..E::VERSION {
+            let migrate = self
+                .steps
+                .get(E::TYPE_NAME)
+                .and_then(|steps| steps.get(&version))
+                .ok_or(MissingMigrationError {
+                    type_name: E::TYPE_NAME,
+                    from: version,
+                })?;
+            value = migrate(value)?;
+        }
+        Ok(value)
+    }
+}
+
+#[derive(Debug)]
+pub struct MissingMigrationError {
+    pub type_name: &'static str,
+    pub from: u32,
+}
+impl std::fmt::Display for MissingMigrationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "No migration registered for {} from version {}",
+            self.type_name, self.from
+        ))
+    }
+}
+impl Error for MissingMigrationError {}
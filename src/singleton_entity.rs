@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::fmt::Debug;
 
 use serde::{de::DeserializeOwned, Serialize, Deserialize};
@@ -75,7 +76,7 @@ impl<S: Singleton> Entity for SingletonEntity<S> {
   type ID = String;
   type Update = SingletonEntityUpdate<S>;
   const TYPE_NAME: &'static str = S::TYPE_NAME;
-  fn get_id(&self) -> &Self::ID {
-    &self.1
+  fn get_id(&self) -> Cow<'_, Self::ID> {
+    Cow::Borrowed(&self.1)
   }
 }
\ No newline at end of file
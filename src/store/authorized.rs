@@ -0,0 +1,234 @@
+use std::{error::Error, fmt::Formatter, sync::Arc, time::{Duration, SystemTime}};
+
+use async_trait::async_trait;
+use tokio::sync::broadcast::{self, error::SendError, Sender};
+use tokio::sync::Notify;
+
+use crate::{Entity, Event, Store};
+
+/// Something a principal can do to entities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Read,
+    Create,
+    Update,
+    Delete,
+    Watch,
+}
+
+/// Decides what each principal is allowed to do.
+pub trait Policy: Send + Sync + 'static {
+    type Principal: Send + Sync + 'static;
+
+    /// Whether `principal` may take `action` on the `E` with the given `id`,
+    /// or on every `E` when there's no `id`. Watches are only asked about
+    /// every `E`, and each event they deliver is asked about as a read of its
+    /// entity.
+    fn allows<E: Entity>(
+        &self,
+        principal: &Self::Principal,
+        action: Action,
+        id: Option<&E::ID>,
+    ) -> bool;
+}
+
+/// Acts for a principal, passing calls on to the inner store only where the
+/// policy allows them. Reading every entity of a type returns only the ones
+/// the principal may read, and watches leave out events for the rest.
+pub struct AuthorizedStore<S: Store, P: Policy> {
+    inner: Arc<S>,
+    policy: Arc<P>,
+    principal: P::Principal,
+    capacity: usize,
+}
+
+impl<S: Store, P: Policy> AuthorizedStore<S, P> {
+    pub fn new(inner: Arc<S>, policy: Arc<P>, principal: P::Principal) -> Self {
+        Self {
+            inner,
+            policy,
+            principal,
+            capacity: 1024,
+        }
+    }
+
+    /// How many events a watch may buffer before they're checked.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// The same store and policy, acting for another principal.
+    pub fn for_principal(&self, principal: P::Principal) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            policy: self.policy.clone(),
+            principal,
+            capacity: self.capacity,
+        }
+    }
+
+    pub fn principal(&self) -> &P::Principal {
+        &self.principal
+    }
+
+    pub fn inner(&self) -> &Arc<S> {
+        &self.inner
+    }
+
+    fn allows<E: Entity>(&self, action: Action, id: Option<&E::ID>) -> bool {
+        self.policy.allows::<E>(&self.principal, action, id)
+    }
+
+    fn check<E: Entity>(
+        &self,
+        action: Action,
+        id: Option<&E::ID>,
+    ) -> Result<(), UnauthorizedError> {
+        if self.allows::<E>(action, id) {
+            Ok(())
+        } else {
+            Err(UnauthorizedError {
+                action,
+                type_name: E::TYPE_NAME,
+                id: id.map(|id| format!("{:?}", id)),
+            })
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Store, P: Policy> Store for AuthorizedStore<S, P> {
+    async fn create<E: Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
+        self.check::<E>(Action::Create, Some(&*entity.get_id()))?;
+        self.inner.create(entity).await
+    }
+
+    async fn create_with_ttl<E: Entity>(
+        &self,
+        entity: &E,
+        ttl: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check::<E>(Action::Create, Some(&*entity.get_id()))?;
+        self.inner.create_with_ttl(entity, ttl).await
+    }
+
+    async fn insert_raw<E: Entity>(
+        &self,
+        entity: &E,
+        expires_at: Option<SystemTime>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check::<E>(Action::Create, Some(&*entity.get_id()))?;
+        self.inner.insert_raw(entity, expires_at).await
+    }
+
+    /// Only the `create` of `insert_new` is checked, since the ID it will be
+    /// created under isn't known before this.
+    async fn next_sequence<E: Entity>(&self) -> Result<u64, Box<dyn Error>> {
+        self.inner.next_sequence::<E>().await
+    }
+
+    async fn update<E: Entity>(
+        &self,
+        id: &E::ID,
+        update: &E::Update,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check::<E>(Action::Update, Some(id))?;
+        self.inner.update::<E>(id, update).await
+    }
+
+    async fn delete_all<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
+        self.check::<E>(Action::Delete, None)?;
+        self.inner.delete_all::<E>().await
+    }
+
+    async fn delete_by_id<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.check::<E>(Action::Delete, Some(id))?;
+        self.inner.delete_by_id::<E>(id).await
+    }
+
+    /// Restoring an entity takes the same permission as creating it.
+    async fn restore<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.check::<E>(Action::Create, Some(id))?;
+        self.inner.restore::<E>(id).await
+    }
+
+    async fn purge<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.check::<E>(Action::Delete, Some(id))?;
+        self.inner.purge::<E>(id).await
+    }
+
+    async fn get_all<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        let mut entities = self.inner.get_all::<E>().await?;
+        if !self.allows::<E>(Action::Read, None) {
+            entities.retain(|e| self.allows::<E>(Action::Read, Some(&*e.get_id())));
+        }
+        Ok(entities)
+    }
+
+    async fn get_deleted<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        let mut entities = self.inner.get_deleted::<E>().await?;
+        if !self.allows::<E>(Action::Read, None) {
+            entities.retain(|e| self.allows::<E>(Action::Read, Some(&*e.get_id())));
+        }
+        Ok(entities)
+    }
+
+    async fn get_by_id<E: Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
+        self.check::<E>(Action::Read, Some(id))?;
+        self.inner.get_by_id(id).await
+    }
+
+    async fn get_expiry<E: Entity>(&self, id: &E::ID) -> Result<Option<SystemTime>, Box<dyn Error>> {
+        self.check::<E>(Action::Read, Some(id))?;
+        self.inner.get_expiry::<E>(id).await
+    }
+
+    async fn watch<E: Entity>(&self, channel: Sender<Event<E>>) -> Result<(), Box<dyn Error>> {
+        self.watch_notifying(channel, Arc::new(Notify::new())).await
+    }
+
+    async fn watch_notifying<E: Entity>(&self, channel: Sender<Event<E>>, ready: Arc<Notify>) -> Result<(), Box<dyn Error>> {
+        if let Err(e) = self.check::<E>(Action::Watch, None) {
+            ready.notify_one();
+            return Err(e.into());
+        }
+        let (tx, mut rx) = broadcast::channel::<Event<E>>(self.capacity);
+        let forward = async {
+            while let Ok(event) = rx.recv().await {
+                if self.allows::<E>(Action::Read, Some(&*event.id())) {
+                    channel.send(event)?;
+                }
+            }
+            Ok::<_, SendError<Event<E>>>(())
+        };
+        tokio::select! {
+            watched = self.inner.watch_notifying(tx, ready) => watched,
+            forwarded = forward => Ok(forwarded?),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnauthorizedError {
+    pub action: Action,
+    pub type_name: &'static str,
+    /// The ID of the entity, unless the action was on every entity of the
+    /// type.
+    pub id: Option<String>,
+}
+impl std::fmt::Display for UnauthorizedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.id {
+            Some(id) => f.write_fmt(format_args!(
+                "Not allowed to {:?} {} {}",
+                self.action, self.type_name, id
+            )),
+            None => f.write_fmt(format_args!(
+                "Not allowed to {:?} {}",
+                self.action, self.type_name
+            )),
+        }
+    }
+}
+impl Error for UnauthorizedError {}
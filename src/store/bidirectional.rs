@@ -0,0 +1,276 @@
+use std::{collections::{HashMap, HashSet}, error::Error, sync::Arc, time::SystemTime};
+
+use serde_json::Value;
+use tokio::sync::{broadcast, broadcast::error::RecvError, mpsc, Notify};
+
+use super::get_if_exists;
+use crate::telemetry;
+use crate::{Diff, Entity, Event, Store};
+
+const STORE: &str = "bidirectional";
+
+type Timestamp<E> = Box<dyn Fn(&E) -> SystemTime + Send + Sync>;
+type Resolver<E> = Box<dyn Fn(&E, &E) -> E + Send + Sync>;
+
+/// How to settle an entity that changed on both sides since they were last
+/// in sync. A change always wins over a deletion, so nothing is lost.
+pub enum ConflictPolicy<E> {
+    /// Keeps the side with the later timestamp, as read from the entity.
+    /// Ties go to the first store.
+    LastWriterWins(Timestamp<E>),
+    PreferFirst,
+    PreferSecond,
+    /// Builds the value to keep from the first and second store's values.
+    Resolve(Resolver<E>),
+}
+
+impl<E: Entity> ConflictPolicy<E> {
+    fn resolve(&self, a: &E, b: &E) -> E {
+        match self {
+            Self::LastWriterWins(timestamp) if timestamp(b) > timestamp(a) => b.clone(),
+            Self::LastWriterWins(_) | Self::PreferFirst => a.clone(),
+            Self::PreferSecond => b.clone(),
+            Self::Resolve(resolve) => resolve(a, b),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Origin {
+    A,
+    B,
+}
+
+/// What a watch of either store sent the sync.
+enum Watched<E: Entity> {
+    Event(Event<E>),
+    /// Events were missed, so everything has to be checked again.
+    Lagged,
+    Ended,
+}
+
+/// Keeps two stores in sync with each other, starting by reconciling
+/// everything they already hold once both are being watched. Runs until
+/// either store stops sending events.
+///
+/// Events are tagged with the store they came from, and the sync counts its
+/// own writes to each store, so that the events those writes cause there
+/// aren't echoed back. Rather than replaying the other events, each is taken
+/// as a sign that its entity changed, and the entity is compared on both
+/// sides against how it was when they were last in sync, so an event that
+/// can't be applied as-is, like an update to an entity the other side never
+/// got, doesn't stop the sync. Fields the stores maintain, like
+/// `#[entity(updated_at)]`, are left out of the comparison, since each side
+/// sets its own. An entity that fails to sync, including one that can't be
+/// compared, is reported and checked again on its next event, and the sync
+/// carries on with the others.
+pub async fn sync_bidirectional<E: Entity + Diff<E::Update>, A: Store, B: Store>(
+    a: Arc<A>,
+    b: Arc<B>,
+    policy: ConflictPolicy<E>,
+    capacity: usize,
+) -> Result<(), Box<dyn Error>> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let (a_ready, b_ready) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+    let watches = [
+        spawn_watch::<E, A>(a.clone(), Origin::A, tx.clone(), a_ready.clone(), capacity),
+        spawn_watch::<E, B>(b.clone(), Origin::B, tx, b_ready.clone(), capacity),
+    ];
+    a_ready.notified().await;
+    b_ready.notified().await;
+
+    let mut sync = Syncer {
+        a,
+        b,
+        policy,
+        synced: HashMap::new(),
+        echoes: HashMap::new(),
+    };
+    sync.reconcile_all().await;
+    while let Some((origin, watched)) = rx.recv().await {
+        match watched {
+            Watched::Event(event) => {
+                if !sync.is_echo(origin, &event.id()) {
+                    sync.reconcile(&event.id()).await;
+                }
+            }
+            Watched::Lagged => {
+                // The missed events may have been echoes.
+                sync.echoes.retain(|(from, _), _| *from != origin);
+                sync.reconcile_all().await;
+            }
+            Watched::Ended => break,
+        }
+    }
+    for watch in watches {
+        watch.abort();
+    }
+    Ok(())
+}
+
+fn spawn_watch<E: Entity, S: Store>(
+    store: Arc<S>,
+    origin: Origin,
+    tx: mpsc::UnboundedSender<(Origin, Watched<E>)>,
+    ready: Arc<Notify>,
+    capacity: usize,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let (watch_tx, mut watch_rx) = broadcast::channel(capacity);
+        let watch = tokio::spawn(async move {
+            let result = store.watch_notifying::<E>(watch_tx, ready.clone()).await;
+            ready.notify_one();
+            if let Err(e) = result {
+                telemetry::failed_in_background(STORE, E::TYPE_NAME, "watch", &*e);
+            }
+        });
+        loop {
+            let watched = match watch_rx.recv().await {
+                Ok(event) => Watched::Event(event),
+                Err(RecvError::Lagged(_)) => Watched::Lagged,
+                Err(RecvError::Closed) => Watched::Ended,
+            };
+            let ended = matches!(watched, Watched::Ended);
+            if tx.send((origin, watched)).is_err() || ended {
+                break;
+            }
+        }
+        watch.abort();
+    })
+}
+
+struct Syncer<E: Entity, A: Store, B: Store> {
+    a: Arc<A>,
+    b: Arc<B>,
+    policy: ConflictPolicy<E>,
+    /// Each entity as of the last time both sides held the same thing, with
+    /// `Null` for not existing. Entities that failed to sync are left out,
+    /// as they may be out of step.
+    synced: HashMap<E::ID, Value>,
+    /// How many of the sync's own writes to each side are yet to come back
+    /// as events.
+    echoes: HashMap<(Origin, E::ID), usize>,
+}
+
+impl<E: Entity + Diff<E::Update>, A: Store, B: Store> Syncer<E, A, B> {
+    /// Reconciles every entity either side holds, or that was synced
+    /// before. A side that can't be listed is reported, and only its
+    /// entities the other side or an earlier sync knows of are reconciled.
+    async fn reconcile_all(&mut self) {
+        let mut ids: HashSet<E::ID> = self.synced.keys().cloned().collect();
+        match self.a.get_all::<E>().await {
+            Ok(entities) => ids.extend(entities.iter().map(|e| e.get_id().into_owned())),
+            Err(e) => telemetry::failed_in_background(STORE, E::TYPE_NAME, "reconcile_all", &*e),
+        }
+        match self.b.get_all::<E>().await {
+            Ok(entities) => ids.extend(entities.iter().map(|e| e.get_id().into_owned())),
+            Err(e) => telemetry::failed_in_background(STORE, E::TYPE_NAME, "reconcile_all", &*e),
+        }
+        for id in &ids {
+            self.reconcile(id).await;
+        }
+    }
+
+    async fn reconcile(&mut self, id: &E::ID) {
+        match self.try_reconcile(id).await {
+            Ok(kept) => {
+                self.synced.insert(id.clone(), kept);
+            }
+            Err(e) => {
+                telemetry::failed_in_background(STORE, E::TYPE_NAME, "reconcile", &*e);
+                self.synced.remove(id);
+            }
+        }
+    }
+
+    /// Whether an event is one of the sync's own writes coming back,
+    /// counting it off if so.
+    fn is_echo(&mut self, origin: Origin, id: &E::ID) -> bool {
+        let key = (origin, id.clone());
+        match self.echoes.get_mut(&key) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    self.echoes.remove(&key);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Brings both sides to the same value, returning it.
+    async fn try_reconcile(&mut self, id: &E::ID) -> Result<Value, Box<dyn Error>> {
+        let in_a = get_if_exists::<E>(&*self.a, id).await?;
+        let in_b = get_if_exists::<E>(&*self.b, id).await?;
+        let (a_val, b_val) = (to_value(&in_a)?, to_value(&in_b)?);
+        let synced = self.synced.get(id).cloned().unwrap_or(Value::Null);
+        let kept = if a_val == b_val {
+            a_val
+        } else if a_val == synced {
+            write::<E, A, B>(&self.a, &self.b, id, in_a, in_b).await?;
+            self.expect_echo(Origin::A, id);
+            b_val
+        } else if b_val == synced {
+            write::<E, B, A>(&self.b, &self.a, id, in_b, in_a).await?;
+            self.expect_echo(Origin::B, id);
+            a_val
+        } else {
+            let kept = match (&in_a, &in_b) {
+                (Some(a), Some(b)) => Some(self.policy.resolve(a, b)),
+                (Some(a), None) => Some(a.clone()),
+                (None, b) => b.clone(),
+            };
+            let kept_val = to_value(&kept)?;
+            if kept_val != a_val {
+                write::<E, A, B>(&self.a, &self.b, id, in_a, kept.clone()).await?;
+                self.expect_echo(Origin::A, id);
+            }
+            if kept_val != b_val {
+                write::<E, B, A>(&self.b, &self.a, id, in_b, kept).await?;
+                self.expect_echo(Origin::B, id);
+            }
+            kept_val
+        };
+        Ok(kept)
+    }
+
+    fn expect_echo(&mut self, origin: Origin, id: &E::ID) {
+        *self.echoes.entry((origin, id.clone())).or_default() += 1;
+    }
+}
+
+/// Writes `target` to `store`. An entity `store` doesn't have yet comes from
+/// `other`, so it's created as `other` holds it, expiring when it does.
+async fn write<E: Entity + Diff<E::Update>, S: Store, O: Store>(
+    store: &S,
+    other: &O,
+    id: &E::ID,
+    current: Option<E>,
+    target: Option<E>,
+) -> Result<(), Box<dyn Error>> {
+    match (current, target) {
+        (_, None) => store.delete_by_id::<E>(id).await,
+        (None, Some(target)) => {
+            let expires_at = other.get_expiry::<E>(id).await?;
+            store.insert_raw(&target, expires_at).await
+        }
+        (Some(current), Some(target)) => store.update::<E>(id, &E::diff(&current, &target)).await,
+    }
+}
+
+// Entities are compared by value through JSON, whose objects keep their keys
+// sorted, so maps compare the same whatever order they're in. Nonexistence is
+// `Null`.
+fn to_value<E: Entity>(entity: &Option<E>) -> Result<Value, serde_json::Error> {
+    let Some(entity) = entity else {
+        return Ok(Value::Null);
+    };
+    let mut value = serde_json::to_value(entity)?;
+    if let Value::Object(fields) = &mut value {
+        for field in E::MAINTAINED_FIELDS {
+            fields.remove(*field);
+        }
+    }
+    Ok(value)
+}
@@ -0,0 +1,316 @@
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    error::Error,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use tokio::sync::broadcast::{self, error::RecvError, Sender};
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+
+use crate::in_mem::InMemStore;
+use crate::{telemetry, Entity, Event, NotFoundError, Singleton, SingletonEvent, Store};
+
+const STORE: &str = "cached";
+
+/// Serves reads from an `InMemStore` kept in step with another store by
+/// watching it, falling back to the other store on a miss. Writes go
+/// straight to the other store, and singletons aren't cached.
+pub struct CachedStore<Inner: Store> {
+    inner: Arc<Inner>,
+    cache: Arc<InMemStore>,
+    retain: usize,
+    types: Arc<Mutex<HashMap<TypeId, CachedType>>>,
+    // Held while reading from the inner store and writing the result to the
+    // cache, so a slow refresh can't overwrite a newer one.
+    refresh_lock: Arc<Mutex<()>>,
+}
+
+struct CachedType {
+    tasks: [JoinHandle<()>; 2],
+    /// Whether every entity of the type is cached, so `get_all` can be
+    /// served from the cache.
+    warm: bool,
+}
+
+impl<Inner: Store> CachedStore<Inner> {
+    pub fn new(inner: Arc<Inner>, retain: usize) -> Self {
+        Self {
+            inner,
+            cache: Arc::new(InMemStore::new(retain)),
+            retain,
+            types: Arc::new(Mutex::new(HashMap::new())),
+            refresh_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn inner(&self) -> &Arc<Inner> {
+        &self.inner
+    }
+
+    /// Caches every entity of a type, so reads of it never need the inner
+    /// store. Its entities are read once the inner store signals through
+    /// `watch_notifying` that it's watching them, so that changes made while
+    /// reading them aren't missed. If the watch ends, the cached entities are
+    /// dropped and the type is watched again on its next use.
+    pub async fn warm_up<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
+        self.ensure_watching::<E>().await;
+        let _refresh = self.refresh_lock.lock().await;
+        let entities = self.inner.get_all::<E>().await?;
+        self.cache.delete_all::<E>().await?;
+        for entity in &entities {
+            self.cache.insert_raw(entity, None).await?;
+        }
+        if let Some(cached) = self.types.lock().await.get_mut(&TypeId::of::<E>()) {
+            cached.warm = true;
+        }
+        Ok(())
+    }
+
+    async fn ensure_watching<E: Entity>(&self) {
+        if self.types.lock().await.contains_key(&TypeId::of::<E>()) {
+            return;
+        }
+        let _refresh = self.refresh_lock.lock().await;
+        let mut types = self.types.lock().await;
+        if types.contains_key(&TypeId::of::<E>()) {
+            return;
+        }
+        // Anything cached since an earlier watch of the type ended may have
+        // gone stale.
+        let _ = self.cache.delete_all::<E>().await;
+        let (tx, mut rx) = broadcast::channel(self.retain);
+        let ready = Arc::new(Notify::new());
+        let inner = self.inner.clone();
+        let watching = ready.clone();
+        let watch = tokio::spawn(async move {
+            let result = inner.watch_notifying::<E>(tx, watching.clone()).await;
+            watching.notify_one();
+            if let Err(e) = result {
+                telemetry::failed_in_background(STORE, E::TYPE_NAME, "watch", &*e);
+            }
+        });
+        let inner = self.inner.clone();
+        let cache = self.cache.clone();
+        let refresh_lock = self.refresh_lock.clone();
+        let state = self.types.clone();
+        let apply = tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => {
+                        let _refresh = refresh_lock.lock().await;
+                        if !refresh::<Inner, E>(&inner, &cache, &event.id()).await {
+                            cool::<E>(&state).await;
+                        }
+                    }
+                    // Some changes were missed, so nothing cached can be
+                    // trusted anymore.
+                    Err(RecvError::Lagged(_)) => {
+                        let _refresh = refresh_lock.lock().await;
+                        let _ = cache.delete_all::<E>().await;
+                        cool::<E>(&state).await;
+                    }
+                    // The watch ended, so the type has to be watched again
+                    // before anything can be cached.
+                    Err(RecvError::Closed) => {
+                        let _refresh = refresh_lock.lock().await;
+                        let _ = cache.delete_all::<E>().await;
+                        state.lock().await.remove(&TypeId::of::<E>());
+                        break;
+                    }
+                }
+            }
+        });
+        types.insert(
+            TypeId::of::<E>(),
+            CachedType {
+                tasks: [watch, apply],
+                warm: false,
+            },
+        );
+        // Holding `types` until the watch is ready keeps other calls for the
+        // type waiting too.
+        ready.notified().await;
+    }
+
+    async fn is_warm<E: Entity>(&self) -> bool {
+        let types = self.types.lock().await;
+        types.get(&TypeId::of::<E>()).is_some_and(|c| c.warm)
+    }
+
+    async fn refresh<E: Entity>(&self, id: &E::ID) {
+        let _refresh = self.refresh_lock.lock().await;
+        if !refresh::<Inner, E>(&self.inner, &self.cache, id).await {
+            cool::<E>(&self.types).await;
+        }
+    }
+}
+
+/// Re-reads an entity from the inner store into the cache, treating events
+/// as invalidations so that replaying one can't apply an update twice.
+/// Returns false if the entity couldn't be read, leaving it uncached.
+async fn refresh<Inner: Store, E: Entity>(inner: &Inner, cache: &InMemStore, id: &E::ID) -> bool {
+    let fetched = match inner.get_by_id::<E>(id).await {
+        Ok(entity) => Ok(Some(entity)),
+        Err(e) if e.is::<NotFoundError<E::ID>>() => Ok(None),
+        Err(_) => Err(()),
+    };
+    match fetched {
+        Ok(Some(entity)) => cache.insert_raw(&entity, None).await.is_ok(),
+        Ok(None) => {
+            let _ = cache.delete_by_id::<E>(id).await;
+            true
+        }
+        Err(()) => {
+            let _ = cache.delete_by_id::<E>(id).await;
+            false
+        }
+    }
+}
+
+async fn cool<E: Entity>(types: &Mutex<HashMap<TypeId, CachedType>>) {
+    if let Some(cached) = types.lock().await.get_mut(&TypeId::of::<E>()) {
+        cached.warm = false;
+    }
+}
+
+impl<Inner: Store> Drop for CachedStore<Inner> {
+    fn drop(&mut self) {
+        if let Ok(types) = self.types.try_lock() {
+            for task in types.values().flat_map(|c| &c.tasks) {
+                task.abort();
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<Inner: Store> Store for CachedStore<Inner> {
+    async fn create<E: Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
+        self.ensure_watching::<E>().await;
+        self.inner.create(entity).await?;
+        self.refresh::<E>(&entity.get_id()).await;
+        Ok(())
+    }
+
+    /// The cache hears of the expiry through its watch of the inner store.
+    async fn create_with_ttl<E: Entity>(&self, entity: &E, ttl: Duration) -> Result<(), Box<dyn Error>> {
+        self.ensure_watching::<E>().await;
+        self.inner.create_with_ttl(entity, ttl).await?;
+        self.refresh::<E>(&entity.get_id()).await;
+        Ok(())
+    }
+
+    async fn insert_raw<E: Entity>(&self, entity: &E, expires_at: Option<SystemTime>) -> Result<(), Box<dyn Error>> {
+        self.ensure_watching::<E>().await;
+        self.inner.insert_raw(entity, expires_at).await?;
+        self.refresh::<E>(&entity.get_id()).await;
+        Ok(())
+    }
+
+    async fn create_singleton<S: Singleton>(&self, entity: &S) -> Result<(), Box<dyn Error>> {
+        self.inner.create_singleton(entity).await
+    }
+
+    async fn next_sequence<E: Entity>(&self) -> Result<u64, Box<dyn Error>> {
+        self.inner.next_sequence::<E>().await
+    }
+
+    async fn update<E: Entity>(
+        &self,
+        id: &E::ID,
+        update: &E::Update,
+    ) -> Result<(), Box<dyn Error>> {
+        self.ensure_watching::<E>().await;
+        self.inner.update::<E>(id, update).await?;
+        self.refresh::<E>(id).await;
+        Ok(())
+    }
+
+    async fn update_singleton<S: Singleton>(&self, update: &S::Update) -> Result<(), Box<dyn Error>> {
+        self.inner.update_singleton::<S>(update).await
+    }
+
+    async fn delete_all<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
+        self.ensure_watching::<E>().await;
+        self.inner.delete_all::<E>().await?;
+        let _refresh = self.refresh_lock.lock().await;
+        self.cache.delete_all::<E>().await
+    }
+
+    async fn delete_by_id<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.ensure_watching::<E>().await;
+        self.inner.delete_by_id::<E>(id).await?;
+        self.refresh::<E>(id).await;
+        Ok(())
+    }
+
+    async fn restore<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.ensure_watching::<E>().await;
+        self.inner.restore::<E>(id).await?;
+        self.refresh::<E>(id).await;
+        Ok(())
+    }
+
+    async fn purge<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.ensure_watching::<E>().await;
+        self.inner.purge::<E>(id).await?;
+        self.refresh::<E>(id).await;
+        Ok(())
+    }
+
+    async fn delete_singleton<S: Singleton>(&self) -> Result<(), Box<dyn Error>> {
+        self.inner.delete_singleton::<S>().await
+    }
+
+    async fn get_all<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        if !self.is_warm::<E>().await {
+            self.warm_up::<E>().await?;
+        }
+        self.cache.get_all::<E>().await
+    }
+
+    /// Deleted entities aren't cached.
+    async fn get_deleted<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        self.inner.get_deleted::<E>().await
+    }
+
+    async fn get_by_id<E: Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
+        self.ensure_watching::<E>().await;
+        if let Ok(entity) = self.cache.get_by_id::<E>(id).await {
+            return Ok(entity);
+        }
+        let _refresh = self.refresh_lock.lock().await;
+        let entity = self.inner.get_by_id::<E>(id).await?;
+        self.cache.insert_raw(&entity, None).await?;
+        Ok(entity)
+    }
+
+    /// Expiries aren't cached.
+    async fn get_expiry<E: Entity>(&self, id: &E::ID) -> Result<Option<SystemTime>, Box<dyn Error>> {
+        self.inner.get_expiry::<E>(id).await
+    }
+
+    async fn get_singleton<S: Singleton>(&self) -> Result<S, Box<dyn Error>> {
+        self.inner.get_singleton::<S>().await
+    }
+
+    async fn watch<E: Entity>(&self, channel: Sender<Event<E>>) -> Result<(), Box<dyn Error>> {
+        self.inner.watch(channel).await
+    }
+
+    async fn watch_notifying<E: Entity>(&self, channel: Sender<Event<E>>, ready: Arc<Notify>) -> Result<(), Box<dyn Error>> {
+        self.inner.watch_notifying(channel, ready).await
+    }
+
+    async fn watch_singleton<S: Singleton>(
+        self: Arc<Self>,
+        channel: Sender<SingletonEvent<S>>,
+        capacity: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        self.inner.clone().watch_singleton(channel, capacity).await
+    }
+}
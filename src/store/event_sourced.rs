@@ -0,0 +1,259 @@
+use std::{collections::HashMap, error::Error, marker::PhantomData, sync::Arc};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast::Sender, Mutex, Notify};
+use typemap_rev::{TypeMap, TypeMapKey};
+
+use crate::telemetry;
+use crate::{Entity, Event, NotFoundError, Store};
+
+const STORE: &str = "event_sourced";
+
+/// The state of every entity of a type after the first `position` events of
+/// that type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Snapshot<E: Entity> {
+    pub position: u64,
+    pub entities: Vec<E>,
+}
+
+/// An append-only log of events, kept separately for each entity type.
+#[async_trait]
+pub trait EventLog: Send + Sync + 'static {
+    /// Appends the event, returning how many events of its type are in the
+    /// log afterwards.
+    async fn append<E: Entity>(&self, event: &Event<E>) -> Result<u64, Box<dyn Error>>;
+    /// Reads the events of a type in order, skipping the first `from`.
+    async fn read<E: Entity>(&self, from: u64) -> Result<Vec<Event<E>>, Box<dyn Error>>;
+    async fn save_snapshot<E: Entity>(&self, snapshot: &Snapshot<E>) -> Result<(), Box<dyn Error>>;
+    /// Loads the latest snapshot for a type, if there is one.
+    async fn load_snapshot<E: Entity>(&self) -> Result<Option<Snapshot<E>>, Box<dyn Error>>;
+}
+
+/// A store whose log of events is the source of truth. Reads are served from
+/// projections built by replaying the log, which start from the latest
+/// snapshot when there is one.
+pub struct EventSourcedStore<L: EventLog> {
+    log: L,
+    retain: usize,
+    snapshot_every: Option<u64>,
+    projections: Mutex<TypeMap>,
+}
+
+struct Projection<E: Entity> {
+    channel: Sender<Event<E>>,
+    entities: HashMap<E::ID, E>,
+    position: u64,
+    /// Where a snapshot was last saved, or tried to be.
+    snapshot_position: u64,
+}
+
+struct ProjectionKey<E: Entity>(PhantomData<E>);
+impl<E: Entity> TypeMapKey for ProjectionKey<E> {
+    type Value = Projection<E>;
+}
+
+impl<E: Entity> Projection<E> {
+    fn apply(&mut self, event: &Event<E>) {
+        match event {
+            Event::Create(e) => {
+                self.entities.insert(e.get_id().into_owned(), e.clone());
+            }
+            Event::Update { id, update } => {
+                if let Some(e) = self.entities.get_mut(id) {
+                    e.update(update);
+                }
+            }
+            Event::Delete(id) => {
+                self.entities.remove(id);
+            }
+        }
+        self.position += 1;
+    }
+}
+
+impl<L: EventLog> EventSourcedStore<L> {
+    pub fn new(log: L, retain: usize) -> Self {
+        Self {
+            log,
+            retain,
+            snapshot_every: None,
+            projections: Mutex::new(TypeMap::new()),
+        }
+    }
+
+    /// Saves a snapshot of a type's entities after every `events` events of
+    /// that type.
+    pub fn snapshot_every(mut self, events: u64) -> Self {
+        self.snapshot_every = Some(events).filter(|&e| e > 0);
+        self
+    }
+
+    pub fn log(&self) -> &L {
+        &self.log
+    }
+
+    /// Throws away the projection for a type and replays its whole log,
+    /// ignoring any snapshots.
+    pub async fn rebuild<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
+        let mut projections = self.projections.lock().await;
+        let mut projection = Projection {
+            channel: match projections.remove::<ProjectionKey<E>>() {
+                Some(p) => p.channel,
+                None => Sender::new(self.retain),
+            },
+            entities: HashMap::new(),
+            position: 0,
+            snapshot_position: 0,
+        };
+        for event in self.log.read::<E>(0).await? {
+            projection.apply(&event);
+        }
+        projection.snapshot_position = projection.position;
+        projections.insert::<ProjectionKey<E>>(projection);
+        Ok(())
+    }
+
+    async fn projection<'a, E: Entity>(
+        &self,
+        projections: &'a mut TypeMap,
+    ) -> Result<&'a mut Projection<E>, Box<dyn Error>> {
+        if !projections.contains_key::<ProjectionKey<E>>() {
+            let mut projection = Projection {
+                channel: Sender::new(self.retain),
+                entities: HashMap::new(),
+                position: 0,
+                snapshot_position: 0,
+            };
+            if let Some(snapshot) = self.log.load_snapshot::<E>().await? {
+                projection.entities = snapshot
+                    .entities
+                    .into_iter()
+                    .map(|e| (e.get_id().into_owned(), e))
+                    .collect();
+                projection.position = snapshot.position;
+                projection.snapshot_position = snapshot.position;
+            }
+            for event in self.log.read::<E>(projection.position).await? {
+                projection.apply(&event);
+            }
+            projections.insert::<ProjectionKey<E>>(projection);
+        }
+        Ok(projections
+            .get_mut::<ProjectionKey<E>>()
+            .expect("Projection was just inserted."))
+    }
+
+    /// Appends the event to the log before applying it, so nothing is ever
+    /// visible that isn't in the log. Once it's in the log the event has
+    /// happened, so a snapshot that can't be saved doesn't fail the write,
+    /// and is tried again after another interval.
+    async fn commit<E: Entity>(
+        &self,
+        projection: &mut Projection<E>,
+        event: Event<E>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.log.append(&event).await?;
+        projection.apply(&event);
+        if let Some(every) = self.snapshot_every {
+            if projection.position - projection.snapshot_position >= every {
+                let snapshot = Snapshot {
+                    position: projection.position,
+                    entities: projection.entities.values().cloned().collect(),
+                };
+                if let Err(e) = self.log.save_snapshot(&snapshot).await {
+                    telemetry::failed_in_background(STORE, E::TYPE_NAME, "save_snapshot", &*e);
+                }
+                projection.snapshot_position = projection.position;
+            }
+        }
+        if projection.channel.receiver_count() > 0 {
+            projection.channel.send(event)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<L: EventLog> Store for EventSourcedStore<L> {
+    async fn create<E: Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
+        let mut projections = self.projections.lock().await;
+        let projection = self.projection::<E>(&mut projections).await?;
+        self.commit(projection, Event::Create(entity.clone())).await
+    }
+
+    async fn update<E: Entity>(
+        &self,
+        id: &E::ID,
+        update: &E::Update,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut projections = self.projections.lock().await;
+        let projection = self.projection::<E>(&mut projections).await?;
+        if !projection.entities.contains_key(id) {
+            return Err(NotFoundError(id.clone()).into());
+        }
+        let event = Event::Update {
+            id: id.clone(),
+            update: update.clone(),
+        };
+        self.commit(projection, event).await
+    }
+
+    async fn delete_all<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
+        let mut projections = self.projections.lock().await;
+        let projection = self.projection::<E>(&mut projections).await?;
+        let ids: Vec<E::ID> = projection.entities.keys().cloned().collect();
+        for id in ids {
+            self.commit(projection, Event::Delete(id)).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_by_id<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        let mut projections = self.projections.lock().await;
+        let projection = self.projection::<E>(&mut projections).await?;
+        // Like other stores, deleting something that isn't there succeeds,
+        // but there's nothing to log.
+        if !projection.entities.contains_key(id) {
+            return Ok(());
+        }
+        self.commit(projection, Event::Delete(id.clone())).await
+    }
+
+    async fn get_all<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        let mut projections = self.projections.lock().await;
+        let projection = self.projection::<E>(&mut projections).await?;
+        Ok(projection.entities.values().cloned().collect())
+    }
+
+    async fn get_by_id<E: Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
+        let mut projections = self.projections.lock().await;
+        let projection = self.projection::<E>(&mut projections).await?;
+        projection
+            .entities
+            .get(id)
+            .cloned()
+            .ok_or(NotFoundError(id.clone()).into())
+    }
+
+    async fn watch<E: Entity>(&self, channel: Sender<Event<E>>) -> Result<(), Box<dyn Error>> {
+        self.watch_notifying(channel, Arc::new(Notify::new())).await
+    }
+
+    async fn watch_notifying<E: Entity>(&self, channel: Sender<Event<E>>, ready: Arc<Notify>) -> Result<(), Box<dyn Error>> {
+        let subscribed = {
+            let mut projections = self.projections.lock().await;
+            self.projection::<E>(&mut projections)
+                .await
+                .map(|projection| projection.channel.subscribe())
+        };
+        ready.notify_one();
+        let mut ch = subscribed?;
+        while let Ok(e) = ch.recv().await {
+            channel.send(e)?;
+        }
+        Ok(())
+    }
+}
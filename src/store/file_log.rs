@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+
+use crate::event_sourced::{EventLog, Snapshot};
+use crate::{Entity, Event};
+
+/// Keeps each type's events in `{TYPE_NAME}.events.jsonl` in a directory,
+/// one JSON event per line, and its latest snapshot in
+/// `{TYPE_NAME}.snapshot.json`.
+pub struct FileEventLog {
+    dir: PathBuf,
+    // How many events each type's file holds, so appending doesn't have to
+    // read the whole file.
+    lengths: Mutex<HashMap<&'static str, u64>>,
+}
+
+impl FileEventLog {
+    pub async fn new(dir: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        fs::create_dir_all(dir.as_ref()).await?;
+        Ok(Self {
+            dir: dir.as_ref().to_owned(),
+            lengths: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn events_path<E: Entity>(&self) -> PathBuf {
+        self.dir.join(format!("{}.events.jsonl", E::TYPE_NAME))
+    }
+
+    fn snapshot_path<E: Entity>(&self) -> PathBuf {
+        self.dir.join(format!("{}.snapshot.json", E::TYPE_NAME))
+    }
+
+    async fn read_lines<E: Entity>(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        match fs::read_to_string(self.events_path::<E>()).await {
+            Ok(contents) => Ok(contents.lines().map(str::to_owned).collect()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl EventLog for FileEventLog {
+    async fn append<E: Entity>(&self, event: &Event<E>) -> Result<u64, Box<dyn Error>> {
+        let mut lengths = self.lengths.lock().await;
+        let length = match lengths.get(E::TYPE_NAME) {
+            Some(&length) => length,
+            None => self.read_lines::<E>().await?.len() as u64,
+        };
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.events_path::<E>())
+            .await?;
+        file.write_all(&line).await?;
+        file.sync_data().await?;
+        lengths.insert(E::TYPE_NAME, length + 1);
+        Ok(length + 1)
+    }
+
+    async fn read<E: Entity>(&self, from: u64) -> Result<Vec<Event<E>>, Box<dyn Error>> {
+        let _lengths = self.lengths.lock().await;
+        self.read_lines::<E>()
+            .await?
+            .iter()
+            .skip(from as usize)
+            .map(|line| serde_json::from_str(line).map_err(|e| e.into()))
+            .collect()
+    }
+
+    async fn save_snapshot<E: Entity>(&self, snapshot: &Snapshot<E>) -> Result<(), Box<dyn Error>> {
+        // Written aside and then moved into place, so a crash can't leave a
+        // partial snapshot behind.
+        let path = self.snapshot_path::<E>();
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec(snapshot)?).await?;
+        fs::rename(&temp, &path).await?;
+        Ok(())
+    }
+
+    async fn load_snapshot<E: Entity>(&self) -> Result<Option<Snapshot<E>>, Box<dyn Error>> {
+        match fs::read(self.snapshot_path::<E>()).await {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
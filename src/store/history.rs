@@ -0,0 +1,72 @@
+use std::{error::Error, time::SystemTime};
+
+use async_trait::async_trait;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+use crate::telemetry;
+use crate::{Entity, Event, NotFoundError};
+
+const STORE: &str = "history";
+
+/// An event as recorded by a `HistorySink`.
+#[derive(Debug, Clone)]
+pub struct HistoryRecord<E: Entity> {
+    /// Increases with every event recorded for the entity's type.
+    pub sequence: u64,
+    pub timestamp: SystemTime,
+    pub event: Event<E>,
+}
+
+/// Somewhere to record the events of a store, so that past states of its
+/// entities can be looked up later.
+#[async_trait]
+pub trait HistorySink: Send + Sync + 'static {
+    async fn record<E: Entity>(
+        &self,
+        event: &Event<E>,
+        timestamp: SystemTime,
+    ) -> Result<HistoryRecord<E>, Box<dyn Error>>;
+
+    /// Gets the recorded events for the entity, oldest first.
+    async fn get_history<E: Entity>(&self, id: &E::ID)
+        -> Result<Vec<HistoryRecord<E>>, Box<dyn Error>>;
+
+    /// Rebuilds the entity as it was at `timestamp` from its recorded events.
+    async fn get_by_id_at<E: Entity>(
+        &self,
+        id: &E::ID,
+        timestamp: SystemTime,
+    ) -> Result<E, Box<dyn Error>> {
+        let mut state: Option<E> = None;
+        for record in self.get_history::<E>(id).await? {
+            if record.timestamp > timestamp {
+                break;
+            }
+            match record.event {
+                Event::Create(e) => state = Some(e),
+                Event::Update { update, .. } => {
+                    if let Some(e) = state.as_mut() {
+                        e.update(&update);
+                    }
+                }
+                Event::Delete(_) => state = None,
+            }
+        }
+        state.ok_or(NotFoundError(id.clone()).into())
+    }
+
+    /// Records every event from the channel as it arrives, e.g. from
+    /// `Store::watch`, until it closes. Events missed by falling behind the
+    /// channel are reported and left out of the history.
+    async fn record_all<E: Entity>(&self, mut channel: Receiver<Event<E>>) -> Result<(), Box<dyn Error>> {
+        loop {
+            match channel.recv().await {
+                Ok(event) => {
+                    self.record(&event, SystemTime::now()).await?;
+                }
+                Err(RecvError::Lagged(missed)) => telemetry::lagged(STORE, E::TYPE_NAME, missed),
+                Err(RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+}
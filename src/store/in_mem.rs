@@ -1,24 +1,214 @@
-use std::{collections::HashMap, error::Error, sync::Arc};
+use std::{collections::HashMap, error::Error, marker::PhantomData, sync::Arc, time::{Duration, SystemTime}};
 
 use async_trait::async_trait;
-use tokio::sync::{broadcast::Sender, Mutex};
+use tokio::sync::broadcast::error::{RecvError, SendError};
+use tokio::sync::{broadcast::Receiver, broadcast::Sender, Mutex, Notify};
+use tokio::time::{sleep_until, Instant};
 use typemap_rev::{TypeMap, TypeMapKey, Entry};
 
-use crate::{Entity, Event, NotFoundError, Store, Singleton, SingletonEvent};
+use crate::event_sourced::{EventLog, Snapshot};
+use crate::history::{HistoryRecord, HistorySink};
+use crate::telemetry::{self, instrument};
+use crate::{ActingAs, Entity, Event, MultiTenant, NotFoundError, Stamp, Store, Singleton, SingletonEvent};
+
+const STORE: &str = "in_mem";
 
 #[derive(Clone)]
 pub struct InMemStore {
     retain: usize,
     stores: Arc<Mutex<TypeMap>>,
-    singleton_stores: Arc<Mutex<TypeMap>>
+    singleton_stores: Arc<Mutex<TypeMap>>,
+    tenants: Arc<std::sync::Mutex<HashMap<String, TenantMaps>>>,
+    actor: Option<String>,
 }
 
+/// A tenant's entity and singleton stores.
+type TenantMaps = (Arc<Mutex<TypeMap>>, Arc<Mutex<TypeMap>>);
+
 impl InMemStore {
     pub fn new(retain: usize) -> Self {
         Self {
             retain,
             stores: Arc::new(Mutex::new(TypeMap::new())),
-            singleton_stores: Arc::new(Mutex::new(TypeMap::new()))
+            singleton_stores: Arc::new(Mutex::new(TypeMap::new())),
+            tenants: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            actor: None,
+        }
+    }
+}
+
+impl MultiTenant for InMemStore {
+    type Tenant = InMemStore;
+
+    /// Every tenant has its own type maps, which are separate from the ones
+    /// of the store it came from. Tenants aren't nested, so a tenant's view
+    /// can give views of any other tenant.
+    fn for_tenant(&self, tenant: &str) -> InMemStore {
+        let mut tenants = self.tenants.lock().unwrap();
+        let (stores, singleton_stores) = tenants
+            .entry(tenant.to_owned())
+            .or_insert_with(|| {
+                (
+                    Arc::new(Mutex::new(TypeMap::new())),
+                    Arc::new(Mutex::new(TypeMap::new())),
+                )
+            })
+            .clone();
+        Self {
+            retain: self.retain,
+            stores,
+            singleton_stores,
+            tenants: self.tenants.clone(),
+            actor: self.actor.clone(),
+        }
+    }
+}
+
+impl InMemStore {
+    /// Stamps the entity as created unless it's being inserted as it is.
+    async fn insert<E: Entity>(
+        &self,
+        operation: &'static str,
+        entity: &E,
+        stamp: bool,
+        deadline: Option<Instant>,
+    ) -> Result<(), Box<dyn Error>> {
+        instrument(STORE, operation, E::TYPE_NAME, Some(&*entity.get_id()), async {
+            let mut entity = entity.clone();
+            if stamp {
+                entity.stamp_created(&Stamp::now(self.actor.as_deref()));
+            }
+            let mut stores = self.stores.lock().await;
+            self.expire_at::<E>(&mut stores, &entity.get_id(), deadline);
+            if let Some(trash) = stores.get_mut::<DeletedKey<E>>() {
+                trash.remove(&*entity.get_id());
+            }
+            let (channel, map) = stores
+                .entry::<EntityWrapper<E>>()
+                .or_insert((Sender::new(self.retain), HashMap::default()));
+            map.insert(entity.get_id().into_owned(), EntityWrapper(entity.clone()));
+            if channel.receiver_count() > 0 {
+                channel.send(Event::Create(entity))?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Has the entity deleted at `deadline`, starting a reaper for the type
+    /// if none is running, or keeps it for good without a `deadline`.
+    fn expire_at<E: Entity>(&self, stores: &mut TypeMap, id: &E::ID, deadline: Option<Instant>) {
+        let Some(deadline) = deadline else {
+            if let Some(expiries) = stores.get_mut::<ExpiryKey<E>>() {
+                expiries.deadlines.remove(id);
+            }
+            return;
+        };
+        let expiries = stores.entry::<ExpiryKey<E>>().or_insert_with(|| Expiries {
+            deadlines: HashMap::new(),
+            reaper: None,
+        });
+        expiries.deadlines.insert(id.clone(), deadline);
+        match &expiries.reaper {
+            Some(wake) => wake.notify_one(),
+            None => {
+                let wake = Arc::new(Notify::new());
+                expiries.reaper = Some(wake.clone());
+                tokio::spawn(self.clone().reap::<E>(wake));
+            }
+        }
+    }
+
+    /// Deletes entities of `E` as they expire, sleeping until the next one
+    /// does, or until woken up for a new deadline. It stops once none are
+    /// left to expire.
+    async fn reap<E: Entity>(self, wake: Arc<Notify>) {
+        loop {
+            let next = {
+                let mut stores = self.stores.lock().await;
+                let Some(expiries) = stores.get_mut::<ExpiryKey<E>>() else {
+                    return;
+                };
+                let now = Instant::now();
+                let expired: Vec<E::ID> = expiries
+                    .deadlines
+                    .iter()
+                    .filter(|(_, deadline)| **deadline <= now)
+                    .map(|(id, _)| id.clone())
+                    .collect();
+                for id in &expired {
+                    expiries.deadlines.remove(id);
+                }
+                let next = expiries.deadlines.values().min().copied();
+                if next.is_none() {
+                    expiries.reaper = None;
+                }
+                if let Some(trash) = stores.get_mut::<DeletedKey<E>>() {
+                    for id in &expired {
+                        trash.remove(id);
+                    }
+                }
+                if let Some((channel, map)) = stores.get_mut::<EntityWrapper<E>>() {
+                    for id in expired {
+                        if map.remove(&id).is_some() && channel.receiver_count() > 0 {
+                            let _ = channel.send(Event::Delete(id));
+                        }
+                    }
+                }
+                match next {
+                    Some(next) => next,
+                    None => return,
+                }
+            };
+            tokio::select! {
+                _ = sleep_until(next) => {}
+                _ = wake.notified() => {}
+            }
+        }
+    }
+
+    /// Sends watchers the changes to `E`s, notifying `ready` once they're
+    /// subscribed.
+    async fn watch_entities<E: Entity>(
+        &self,
+        channel: Sender<Event<E>>,
+        ready: Option<Arc<Notify>>,
+    ) -> Result<(), Box<dyn Error>> {
+        instrument(STORE, "watch", E::TYPE_NAME, None::<&E::ID>, async {
+            let mut ch = {
+                let mut stores = self.stores.lock().await;
+                let (channel, _) = stores
+                    .entry::<EntityWrapper<E>>()
+                    .or_insert((Sender::new(self.retain), HashMap::default()));
+                let ch = channel.subscribe();
+                telemetry::watchers(STORE, E::TYPE_NAME, channel.receiver_count());
+                ch
+            };
+            if let Some(ready) = ready {
+                ready.notify_one();
+            }
+            let forwarded = forward(&mut ch, E::TYPE_NAME, |e| {
+                telemetry::event_sent(STORE, E::TYPE_NAME, telemetry::event_kind(&e));
+                channel.send(e)
+            })
+            .await;
+            drop(ch);
+            if let Some((channel, _)) = self.stores.lock().await.get::<EntityWrapper<E>>() {
+                telemetry::watchers(STORE, E::TYPE_NAME, channel.receiver_count());
+            }
+            Ok(forwarded?)
+        })
+        .await
+    }
+}
+
+impl ActingAs for InMemStore {
+    type Acting = InMemStore;
+
+    fn acting_as(&self, actor: &str) -> InMemStore {
+        Self {
+            actor: Some(actor.to_owned()),
+            ..self.clone()
         }
     }
 }
@@ -29,6 +219,32 @@ impl<E: Entity> TypeMapKey for EntityWrapper<E> {
     type Value = (Sender<Event<E>>, HashMap<E::ID, Self>);
 }
 
+/// When the entities of `E` that expire do so.
+struct ExpiryKey<E: Entity>(PhantomData<E>);
+impl<E: Entity> TypeMapKey for ExpiryKey<E> {
+    type Value = Expiries<E>;
+}
+
+struct Expiries<E: Entity> {
+    deadlines: HashMap<E::ID, Instant>,
+    /// Wakes the type's reaper, if it's running, to an earlier deadline.
+    reaper: Option<Arc<Notify>>,
+}
+
+/// The soft-deleted entities of `E`, kept apart from the others so that
+/// only `restore`, `purge` and `get_deleted` see them.
+struct DeletedKey<E: Entity>(PhantomData<E>);
+impl<E: Entity> TypeMapKey for DeletedKey<E> {
+    type Value = HashMap<E::ID, E>;
+}
+
+/// The counter sequential IDs of `E` come from, kept apart from its
+/// entities so that deleting them doesn't reset it.
+struct SequenceKey<E: Entity>(PhantomData<E>);
+impl<E: Entity> TypeMapKey for SequenceKey<E> {
+    type Value = u64;
+}
+
 struct SingletonWrapper<S: Singleton>(S);
 impl<S: Singleton> TypeMapKey for SingletonWrapper<S> {
     type Value = (Sender<SingletonEvent<S>>, Option<Self>);
@@ -37,36 +253,69 @@ impl<S: Singleton> TypeMapKey for SingletonWrapper<S> {
 #[async_trait]
 impl Store for InMemStore {
     async fn create<E: Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
-        let mut stores = self.stores.lock().await;
-        let (channel, map) = stores
-            .entry::<EntityWrapper<E>>()
-            .or_insert((Sender::new(self.retain), HashMap::default()));
-        map.insert(entity.get_id().clone(), EntityWrapper(entity.clone()));
-        if channel.receiver_count() > 0 {
-            channel.send(Event::Create(entity.clone()))?;
-        }
-        Ok(())
+        self.insert("create", entity, true, E::TTL.map(|ttl| Instant::now() + ttl)).await
+    }
+
+    async fn create_with_ttl<E: Entity>(&self, entity: &E, ttl: Duration) -> Result<(), Box<dyn Error>> {
+        self.insert("create_with_ttl", entity, true, Some(Instant::now() + ttl)).await
+    }
+
+    async fn insert_raw<E: Entity>(&self, entity: &E, expires_at: Option<SystemTime>) -> Result<(), Box<dyn Error>> {
+        let deadline = expires_at.map(|at| {
+            Instant::now() + at.duration_since(SystemTime::now()).unwrap_or_default()
+        });
+        self.insert("insert_raw", entity, false, deadline).await
+    }
+
+    async fn get_expiry<E: Entity>(&self, id: &E::ID) -> Result<Option<SystemTime>, Box<dyn Error>> {
+        instrument(STORE, "get_expiry", E::TYPE_NAME, Some(id), async {
+            let stores = self.stores.lock().await;
+            let exists = stores
+                .get::<EntityWrapper<E>>()
+                .is_some_and(|(_, map)| map.contains_key(id));
+            if !exists {
+                return Err(NotFoundError(id.clone()).into());
+            }
+            let deadline = stores.get::<ExpiryKey<E>>().and_then(|e| e.deadlines.get(id));
+            Ok(deadline.map(|deadline| {
+                SystemTime::now() + deadline.saturating_duration_since(Instant::now())
+            }))
+        })
+        .await
     }
 
     async fn create_singleton<S: Singleton>(&self, entity: &S) -> Result<(), Box<dyn Error>> {
-        let mut sings = self.singleton_stores.lock().await;
-        let e = sings.entry::<SingletonWrapper<S>>();
-        let channel = match e {
-            Entry::Occupied(mut e) => {
-                let (channel, s) = e.get_mut();
-                s.replace(SingletonWrapper(entity.clone()));
-                channel.clone()
-            }
-            Entry::Vacant(v) => {
-                let channel = Sender::new(self.retain);
-                v.insert((channel.clone(), Some(SingletonWrapper(entity.clone()))));
-                channel
+        instrument(STORE, "create_singleton", S::TYPE_NAME, Some(S::ENTITY_ID), async {
+            let mut sings = self.singleton_stores.lock().await;
+            let e = sings.entry::<SingletonWrapper<S>>();
+            let channel = match e {
+                Entry::Occupied(mut e) => {
+                    let (channel, s) = e.get_mut();
+                    s.replace(SingletonWrapper(entity.clone()));
+                    channel.clone()
+                }
+                Entry::Vacant(v) => {
+                    let channel = Sender::new(self.retain);
+                    v.insert((channel.clone(), Some(SingletonWrapper(entity.clone()))));
+                    channel
+                }
+            };
+            if channel.receiver_count() > 0 {
+                channel.send(SingletonEvent::Create(entity.clone()))?;
             }
-        };
-        if channel.receiver_count() > 0 {
-            channel.send(SingletonEvent::Create(entity.clone()))?;
-        }
-        Ok(())
+            Ok(())
+        })
+        .await
+    }
+
+    async fn next_sequence<E: Entity>(&self) -> Result<u64, Box<dyn Error>> {
+        instrument(STORE, "next_sequence", E::TYPE_NAME, None::<&E::ID>, async {
+            let mut stores = self.stores.lock().await;
+            let counter = stores.entry::<SequenceKey<E>>().or_insert(0);
+            *counter += 1;
+            Ok(*counter)
+        })
+        .await
     }
 
     async fn update<E: Entity>(
@@ -74,119 +323,349 @@ impl Store for InMemStore {
         id: &E::ID,
         update: &E::Update,
     ) -> Result<(), Box<dyn Error>> {
-        let mut stores = self.stores.lock().await;
-        let (channel, map) = stores
-            .get_mut::<EntityWrapper<E>>()
-            .ok_or(NotFoundError(id.clone()))?;
-        let current = map.get_mut(id).ok_or(NotFoundError(id.clone()))?;
-        current.0.update(update);
-        if channel.receiver_count() > 0 {
-            channel.send(Event::Update {
-                id: id.clone(),
-                update: update.clone(),
-            })?;
-        }
-        Ok(())
+        instrument(STORE, "update", E::TYPE_NAME, Some(id), async {
+            let mut stores = self.stores.lock().await;
+            let (channel, map) = stores
+                .get_mut::<EntityWrapper<E>>()
+                .ok_or(NotFoundError(id.clone()))?;
+            let current = map.get_mut(id).ok_or(NotFoundError(id.clone()))?;
+            let mut update = update.clone();
+            E::stamp_updated(&mut update, &Stamp::now(self.actor.as_deref()));
+            current.0.update(&update);
+            if channel.receiver_count() > 0 {
+                channel.send(Event::Update {
+                    id: id.clone(),
+                    update,
+                })?;
+            }
+            Ok(())
+        })
+        .await
     }
 
     async fn update_singleton<S: Singleton>(&self, update: &S::Update) -> Result<(), Box<dyn Error>> {
-        let mut sings = self.singleton_stores.lock().await;
-        let (channel, current_opt) = sings.get_mut::<SingletonWrapper<S>>().ok_or(NotFoundError(S::ENTITY_ID))?;
-        let current = current_opt.as_mut().ok_or(NotFoundError(S::ENTITY_ID))?;
-        current.0.update(update);
-        if channel.receiver_count() > 0 {
-            channel.send(SingletonEvent::Update(update.clone()))?;
-        }
-        Ok(())
+        instrument(STORE, "update_singleton", S::TYPE_NAME, Some(S::ENTITY_ID), async {
+            let mut sings = self.singleton_stores.lock().await;
+            let (channel, current_opt) = sings.get_mut::<SingletonWrapper<S>>().ok_or(NotFoundError(S::ENTITY_ID))?;
+            let current = current_opt.as_mut().ok_or(NotFoundError(S::ENTITY_ID))?;
+            current.0.update(update);
+            if channel.receiver_count() > 0 {
+                channel.send(SingletonEvent::Update(update.clone()))?;
+            }
+            Ok(())
+        })
+        .await
     }
 
     async fn delete_all<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
-        let mut stores = self.stores.lock().await;
-        let entry = stores.remove::<EntityWrapper<E>>();
-        if let Some((channel, map)) = entry {
-            if channel.receiver_count() > 0 {
-                for id in map.keys() {
-                    channel.send(Event::Delete(id.clone()))?;
+        instrument(STORE, "delete_all", E::TYPE_NAME, None::<&E::ID>, async {
+            let mut stores = self.stores.lock().await;
+            let entry = stores.remove::<EntityWrapper<E>>();
+            if let Some((channel, map)) = entry {
+                if channel.receiver_count() > 0 {
+                    for id in map.keys() {
+                        channel.send(Event::Delete(id.clone()))?;
+                    }
+                }
+                if E::SOFT_DELETE {
+                    let trash = stores.entry::<DeletedKey<E>>().or_insert_with(HashMap::new);
+                    trash.extend(map.into_iter().map(|(id, w)| (id, w.0)));
                 }
             }
-        }
-        Ok(())
+            Ok(())
+        })
+        .await
     }
 
     async fn delete_by_id<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
-        let mut stores = self.stores.lock().await;
-        let (channel, map) = stores
-            .get_mut::<EntityWrapper<E>>()
-            .ok_or(NotFoundError(id.clone()))?;
-        map.remove(id);
-        if channel.receiver_count() > 0 {
-            channel.send(Event::Delete(id.clone()))?;
-        }
-        Ok(())
+        instrument(STORE, "delete_by_id", E::TYPE_NAME, Some(id), async {
+            let mut stores = self.stores.lock().await;
+            let (channel, map) = stores
+                .get_mut::<EntityWrapper<E>>()
+                .ok_or(NotFoundError(id.clone()))?;
+            let removed = map.remove(id);
+            if channel.receiver_count() > 0 {
+                channel.send(Event::Delete(id.clone()))?;
+            }
+            if let (true, Some(removed)) = (E::SOFT_DELETE, removed) {
+                let trash = stores.entry::<DeletedKey<E>>().or_insert_with(HashMap::new);
+                trash.insert(id.clone(), removed.0);
+            }
+            Ok(())
+        })
+        .await
     }
 
-    async fn delete_singleton<S: Singleton>(&self) -> Result<(), Box<dyn Error>> {
-        let mut sings = self.singleton_stores.lock().await;
-        if let Entry::Occupied(mut e) = sings.entry::<SingletonWrapper<S>>() {
-            let (channel, _) = e.get_mut();
+    async fn restore<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        instrument(STORE, "restore", E::TYPE_NAME, Some(id), async {
+            let mut stores = self.stores.lock().await;
+            let entity = stores
+                .get_mut::<DeletedKey<E>>()
+                .and_then(|trash| trash.remove(id))
+                .ok_or(NotFoundError(id.clone()))?;
+            let (channel, map) = stores
+                .entry::<EntityWrapper<E>>()
+                .or_insert((Sender::new(self.retain), HashMap::default()));
+            map.insert(id.clone(), EntityWrapper(entity.clone()));
             if channel.receiver_count() > 0 {
-                channel.send(SingletonEvent::Delete)?;
+                channel.send(Event::Create(entity))?;
             }
-            e.remove();
-        }
-        Ok(())
+            Ok(())
+        })
+        .await
+    }
+
+    async fn purge<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        instrument(STORE, "purge", E::TYPE_NAME, Some(id), async {
+            let mut stores = self.stores.lock().await;
+            if let Some(trash) = stores.get_mut::<DeletedKey<E>>() {
+                trash.remove(id);
+            }
+            if let Some((channel, map)) = stores.get_mut::<EntityWrapper<E>>() {
+                if map.remove(id).is_some() && channel.receiver_count() > 0 {
+                    channel.send(Event::Delete(id.clone()))?;
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_singleton<S: Singleton>(&self) -> Result<(), Box<dyn Error>> {
+        instrument(STORE, "delete_singleton", S::TYPE_NAME, Some(S::ENTITY_ID), async {
+            let mut sings = self.singleton_stores.lock().await;
+            if let Entry::Occupied(mut e) = sings.entry::<SingletonWrapper<S>>() {
+                let (channel, _) = e.get_mut();
+                if channel.receiver_count() > 0 {
+                    channel.send(SingletonEvent::Delete)?;
+                }
+                e.remove();
+            }
+            Ok(())
+        })
+        .await
     }
 
     async fn get_all<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
-        let stores = self.stores.lock().await;
-        match stores.get::<EntityWrapper<E>>() {
-            Some((_, map)) => Ok(map.values().cloned().map(|w| w.0).collect()),
-            None => Ok(Vec::default()),
-        }
+        instrument(STORE, "get_all", E::TYPE_NAME, None::<&E::ID>, async {
+            let stores = self.stores.lock().await;
+            match stores.get::<EntityWrapper<E>>() {
+                Some((_, map)) => Ok(map.values().cloned().map(|w| w.0).collect()),
+                None => Ok(Vec::default()),
+            }
+        })
+        .await
+    }
+
+    /// Only the entities on the page are cloned.
+    async fn get_page<E: Entity>(&self, after: Option<&E::ID>, limit: usize) -> Result<Vec<E>, Box<dyn Error>> {
+        instrument(STORE, "get_page", E::TYPE_NAME, after, async {
+            let after = after.map(serde_json::to_string).transpose()?;
+            let stores = self.stores.lock().await;
+            let Some((_, map)) = stores.get::<EntityWrapper<E>>() else {
+                return Ok(Vec::new());
+            };
+            let mut keyed = Vec::new();
+            for id in map.keys() {
+                let key = serde_json::to_string(id)?;
+                if after.as_ref().is_none_or(|after| key > *after) {
+                    keyed.push((key, id));
+                }
+            }
+            keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Ok(keyed.into_iter().take(limit).map(|(_, id)| map[id].0.clone()).collect())
+        })
+        .await
+    }
+
+    async fn get_deleted<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        instrument(STORE, "get_deleted", E::TYPE_NAME, None::<&E::ID>, async {
+            let stores = self.stores.lock().await;
+            match stores.get::<DeletedKey<E>>() {
+                Some(trash) => Ok(trash.values().cloned().collect()),
+                None => Ok(Vec::default()),
+            }
+        })
+        .await
     }
 
     async fn get_by_id<E: Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
-        let stores = self.stores.lock().await;
-        let (_, map) = stores
-            .get::<EntityWrapper<E>>()
-            .ok_or(NotFoundError(id.clone()))?;
-        map.get(id)
-            .ok_or(NotFoundError(id.clone()))
-            .cloned()
-            .map(|w| w.0)
-            .map_err(|e| e.into())
+        instrument(STORE, "get_by_id", E::TYPE_NAME, Some(id), async {
+            let stores = self.stores.lock().await;
+            let (_, map) = stores
+                .get::<EntityWrapper<E>>()
+                .ok_or(NotFoundError(id.clone()))?;
+            map.get(id)
+                .ok_or(NotFoundError(id.clone()))
+                .cloned()
+                .map(|w| w.0)
+                .map_err(|e| e.into())
+        })
+        .await
     }
 
     async fn get_singleton<S: Singleton>(&self) -> Result<S, Box<dyn Error>> {
-        let sings = self.singleton_stores.lock().await;
-        let (_, opt_s) = sings.get::<SingletonWrapper<S>>().ok_or(NotFoundError(S::ENTITY_ID))?;
-        let s = opt_s.as_ref().ok_or(NotFoundError(S::ENTITY_ID))?;
-        Ok(s.0.clone())
+        instrument(STORE, "get_singleton", S::TYPE_NAME, Some(S::ENTITY_ID), async {
+            let sings = self.singleton_stores.lock().await;
+            let (_, opt_s) = sings.get::<SingletonWrapper<S>>().ok_or(NotFoundError(S::ENTITY_ID))?;
+            let s = opt_s.as_ref().ok_or(NotFoundError(S::ENTITY_ID))?;
+            Ok(s.0.clone())
+        })
+        .await
     }
 
     async fn watch<E: Entity>(&self, channel: Sender<Event<E>>) -> Result<(), Box<dyn Error>> {
-        let mut ch = {
-            let mut stores = self.stores.lock().await;
-            let (channel, _) = stores
-                .entry::<EntityWrapper<E>>()
-                .or_insert((Sender::new(self.retain), HashMap::default()));
-            channel.subscribe()
-        };
-        while let Ok(e) = ch.recv().await {
-            channel.send(e)?;
-        }
-        Ok(())
+        self.watch_entities(channel, None).await
+    }
+
+    async fn watch_notifying<E: Entity>(&self, channel: Sender<Event<E>>, ready: Arc<Notify>) -> Result<(), Box<dyn Error>> {
+        self.watch_entities(channel, Some(ready)).await
     }
 
     async fn watch_singleton<S: Singleton>(self: Arc<Self>, channel: Sender<SingletonEvent<S>>, _: usize) -> Result<(), Box<dyn Error>> {
-        let mut ch = {
-            let mut sings = self.singleton_stores.lock().await;
-            let (channel, _) = sings.entry::<SingletonWrapper<S>>().or_insert((Sender::new(self.retain), None));
-            channel.subscribe()
-        };
-        while let Ok(e) = ch.recv().await {
-            channel.send(e)?;
+        instrument(STORE, "watch_singleton", S::TYPE_NAME, Some(S::ENTITY_ID), async {
+            let mut ch = {
+                let mut sings = self.singleton_stores.lock().await;
+                let (channel, _) = sings.entry::<SingletonWrapper<S>>().or_insert((Sender::new(self.retain), None));
+                let ch = channel.subscribe();
+                telemetry::watchers(STORE, S::TYPE_NAME, channel.receiver_count());
+                ch
+            };
+            let forwarded = forward(&mut ch, S::TYPE_NAME, |e| {
+                let kind = match &e {
+                    SingletonEvent::Create(_) => "create",
+                    SingletonEvent::Update(_) => "update",
+                    SingletonEvent::Delete => "delete",
+                };
+                telemetry::event_sent(STORE, S::TYPE_NAME, kind);
+                channel.send(e)
+            })
+            .await;
+            drop(ch);
+            if let Some((channel, _)) = self.singleton_stores.lock().await.get::<SingletonWrapper<S>>() {
+                telemetry::watchers(STORE, S::TYPE_NAME, channel.receiver_count());
+            }
+            Ok(forwarded?)
+        })
+        .await
+    }
+}
+
+/// Forwards events from a store's channel until it closes or lags.
+async fn forward<T: Clone, R>(
+    ch: &mut Receiver<T>,
+    type_name: &'static str,
+    mut send: impl FnMut(T) -> Result<R, SendError<T>>,
+) -> Result<(), SendError<T>> {
+    loop {
+        match ch.recv().await {
+            Ok(e) => {
+                send(e)?;
+            }
+            Err(RecvError::Lagged(missed)) => {
+                telemetry::lagged(STORE, type_name, missed);
+                return Ok(());
+            }
+            Err(RecvError::Closed) => return Ok(()),
         }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct InMemHistorySink {
+    histories: Arc<Mutex<TypeMap>>,
+}
+
+impl InMemHistorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+struct HistoryKey<E: Entity>(PhantomData<E>);
+impl<E: Entity> TypeMapKey for HistoryKey<E> {
+    type Value = (u64, HashMap<E::ID, Vec<HistoryRecord<E>>>);
+}
+
+#[async_trait]
+impl HistorySink for InMemHistorySink {
+    async fn record<E: Entity>(
+        &self,
+        event: &Event<E>,
+        timestamp: SystemTime,
+    ) -> Result<HistoryRecord<E>, Box<dyn Error>> {
+        let mut histories = self.histories.lock().await;
+        let (sequence, records) = histories
+            .entry::<HistoryKey<E>>()
+            .or_insert_with(|| (0, HashMap::new()));
+        *sequence += 1;
+        let record = HistoryRecord {
+            sequence: *sequence,
+            timestamp,
+            event: event.clone(),
+        };
+        let records = records.entry(event.id().into_owned()).or_default();
+        // Events recorded out of order still come back in time order.
+        let at = records.partition_point(|r| r.timestamp <= timestamp);
+        records.insert(at, record.clone());
+        Ok(record)
+    }
+
+    async fn get_history<E: Entity>(
+        &self,
+        id: &E::ID,
+    ) -> Result<Vec<HistoryRecord<E>>, Box<dyn Error>> {
+        let histories = self.histories.lock().await;
+        Ok(histories
+            .get::<HistoryKey<E>>()
+            .and_then(|(_, records)| records.get(id))
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct InMemEventLog {
+    logs: Arc<Mutex<TypeMap>>,
+}
+
+impl InMemEventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+struct LogKey<E: Entity>(PhantomData<E>);
+impl<E: Entity> TypeMapKey for LogKey<E> {
+    type Value = (Vec<Event<E>>, Option<Snapshot<E>>);
+}
+
+#[async_trait]
+impl EventLog for InMemEventLog {
+    async fn append<E: Entity>(&self, event: &Event<E>) -> Result<u64, Box<dyn Error>> {
+        let mut logs = self.logs.lock().await;
+        let (events, _) = logs.entry::<LogKey<E>>().or_insert_with(Default::default);
+        events.push(event.clone());
+        Ok(events.len() as u64)
+    }
+
+    async fn read<E: Entity>(&self, from: u64) -> Result<Vec<Event<E>>, Box<dyn Error>> {
+        let logs = self.logs.lock().await;
+        Ok(logs
+            .get::<LogKey<E>>()
+            .map(|(events, _)| events.iter().skip(from as usize).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn save_snapshot<E: Entity>(&self, snapshot: &Snapshot<E>) -> Result<(), Box<dyn Error>> {
+        let mut logs = self.logs.lock().await;
+        let (_, latest) = logs.entry::<LogKey<E>>().or_insert_with(Default::default);
+        *latest = Some(snapshot.clone());
         Ok(())
     }
+
+    async fn load_snapshot<E: Entity>(&self) -> Result<Option<Snapshot<E>>, Box<dyn Error>> {
+        let logs = self.logs.lock().await;
+        Ok(logs.get::<LogKey<E>>().and_then(|(_, latest)| latest.clone()))
+    }
 }
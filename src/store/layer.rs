@@ -0,0 +1,327 @@
+use std::{
+    error::Error,
+    fmt::Formatter,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use tokio::sync::{broadcast::Sender, Notify};
+
+use crate::{Entity, Event, Store};
+
+/// Wraps a store in another, like a validating, caching or intercepting one,
+/// so that stores can be built up in layers.
+pub trait Layer<S: Store> {
+    type Store: Store;
+    fn layer(&self, inner: Arc<S>) -> Self::Store;
+}
+
+impl<S: Store, T: Store, F: Fn(Arc<S>) -> T> Layer<S> for F {
+    type Store = T;
+    fn layer(&self, inner: Arc<S>) -> T {
+        self(inner)
+    }
+}
+
+/// Stacks layers onto a store, e.g.
+/// `InMemStore::new(8).with_layer(|s| CachedStore::new(s, 8))`.
+pub trait Layered: Store + Sized {
+    fn with_layer<L: Layer<Self>>(self, layer: L) -> L::Store {
+        layer.layer(Arc::new(self))
+    }
+}
+impl<S: Store> Layered for S {}
+
+/// A call made to an `Intercepted` store. Singleton calls arrive as calls
+/// for their `SingletonEntity`.
+#[derive(Debug, Clone)]
+pub enum Call<E: Entity> {
+    Create(E),
+    CreateWithTtl { entity: E, ttl: Duration },
+    InsertRaw { entity: E, expires_at: Option<SystemTime> },
+    Update { id: E::ID, update: E::Update },
+    DeleteAll,
+    DeleteById(E::ID),
+    Restore(E::ID),
+    Purge(E::ID),
+    GetAll,
+    GetDeleted,
+    GetById(E::ID),
+    GetExpiry(E::ID),
+    Watch(Sender<Event<E>>),
+}
+
+impl<E: Entity> Call<E> {
+    /// The name of the `Store` method called.
+    pub fn operation(&self) -> &'static str {
+        match self {
+            Call::Create(_) => "create",
+            Call::CreateWithTtl { .. } => "create_with_ttl",
+            Call::InsertRaw { .. } => "insert_raw",
+            Call::Update { .. } => "update",
+            Call::DeleteAll => "delete_all",
+            Call::DeleteById(_) => "delete_by_id",
+            Call::Restore(_) => "restore",
+            Call::Purge(_) => "purge",
+            Call::GetAll => "get_all",
+            Call::GetDeleted => "get_deleted",
+            Call::GetById(_) => "get_by_id",
+            Call::GetExpiry(_) => "get_expiry",
+            Call::Watch(_) => "watch",
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        E::TYPE_NAME
+    }
+}
+
+/// What a successful call returned.
+#[derive(Debug, Clone)]
+pub enum Outcome<E: Entity> {
+    Done,
+    Entities(Vec<E>),
+    Entity(E),
+    Expiry(Option<SystemTime>),
+}
+
+/// Hooks run around every call to an `Intercepted` store.
+#[async_trait]
+pub trait Interceptor: Send + Sync + 'static {
+    /// Runs before the call reaches the inner store. It may change the
+    /// call's arguments, but not which method it calls, and an error
+    /// rejects the call.
+    async fn before<E: Entity>(&self, _call: &mut Call<E>) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Runs with the result of the call, which it may change. A watch only
+    /// finishes when it stops watching.
+    fn after<E: Entity>(&self, _call: &Call<E>, _result: &mut Result<Outcome<E>, Box<dyn Error>>) {}
+}
+
+/// Runs an `Interceptor`'s hooks around every call to the inner store.
+pub struct Intercepted<S: Store, I: Interceptor> {
+    inner: Arc<S>,
+    interceptor: I,
+}
+
+impl<S: Store, I: Interceptor> Intercepted<S, I> {
+    pub fn new(inner: Arc<S>, interceptor: I) -> Self {
+        Self { inner, interceptor }
+    }
+
+    pub fn inner(&self) -> &Arc<S> {
+        &self.inner
+    }
+
+    pub fn interceptor(&self) -> &I {
+        &self.interceptor
+    }
+
+    async fn intercept<E: Entity>(&self, call: Call<E>) -> Result<Outcome<E>, Box<dyn Error>> {
+        self.intercept_notifying(call, None).await
+    }
+
+    /// Intercepts a call, notifying `ready` once a watch it makes is ready or
+    /// if the call ends before then.
+    async fn intercept_notifying<E: Entity>(
+        &self,
+        mut call: Call<E>,
+        ready: Option<Arc<Notify>>,
+    ) -> Result<Outcome<E>, Box<dyn Error>> {
+        if let Err(e) = self.interceptor.before(&mut call).await {
+            if let Some(ready) = ready {
+                ready.notify_one();
+            }
+            return Err(e);
+        }
+        let mut result = self.dispatch(&call, ready).await;
+        self.interceptor.after(&call, &mut result);
+        result
+    }
+
+    async fn dispatch<E: Entity>(&self, call: &Call<E>, ready: Option<Arc<Notify>>) -> Result<Outcome<E>, Box<dyn Error>> {
+        Ok(match call {
+            Call::Create(entity) => {
+                self.inner.create(entity).await?;
+                Outcome::Done
+            }
+            Call::CreateWithTtl { entity, ttl } => {
+                self.inner.create_with_ttl(entity, *ttl).await?;
+                Outcome::Done
+            }
+            Call::InsertRaw { entity, expires_at } => {
+                self.inner.insert_raw(entity, *expires_at).await?;
+                Outcome::Done
+            }
+            Call::Update { id, update } => {
+                self.inner.update::<E>(id, update).await?;
+                Outcome::Done
+            }
+            Call::DeleteAll => {
+                self.inner.delete_all::<E>().await?;
+                Outcome::Done
+            }
+            Call::DeleteById(id) => {
+                self.inner.delete_by_id::<E>(id).await?;
+                Outcome::Done
+            }
+            Call::Restore(id) => {
+                self.inner.restore::<E>(id).await?;
+                Outcome::Done
+            }
+            Call::Purge(id) => {
+                self.inner.purge::<E>(id).await?;
+                Outcome::Done
+            }
+            Call::GetAll => Outcome::Entities(self.inner.get_all().await?),
+            Call::GetDeleted => Outcome::Entities(self.inner.get_deleted().await?),
+            Call::GetById(id) => Outcome::Entity(self.inner.get_by_id(id).await?),
+            Call::GetExpiry(id) => Outcome::Expiry(self.inner.get_expiry::<E>(id).await?),
+            Call::Watch(channel) => {
+                match ready {
+                    Some(ready) => self.inner.watch_notifying(channel.clone(), ready).await?,
+                    None => self.inner.watch(channel.clone()).await?,
+                }
+                Outcome::Done
+            }
+        })
+    }
+
+    async fn intercept_done<E: Entity>(&self, call: Call<E>) -> Result<(), Box<dyn Error>> {
+        match self.intercept(call).await? {
+            Outcome::Done => Ok(()),
+            _ => Err(UnexpectedOutcomeError("nothing").into()),
+        }
+    }
+}
+
+/// Intercepts calls with a clone of an `Interceptor`.
+#[derive(Clone)]
+pub struct InterceptorLayer<I: Interceptor + Clone>(pub I);
+
+impl<S: Store, I: Interceptor + Clone> Layer<S> for InterceptorLayer<I> {
+    type Store = Intercepted<S, I>;
+    fn layer(&self, inner: Arc<S>) -> Intercepted<S, I> {
+        Intercepted::new(inner, self.0.clone())
+    }
+}
+
+#[async_trait]
+impl<S: Store, I: Interceptor> Store for Intercepted<S, I> {
+    async fn create<E: Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
+        self.intercept_done(Call::Create(entity.clone())).await
+    }
+
+    async fn create_with_ttl<E: Entity>(
+        &self,
+        entity: &E,
+        ttl: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        self.intercept_done(Call::CreateWithTtl {
+            entity: entity.clone(),
+            ttl,
+        })
+        .await
+    }
+
+    async fn insert_raw<E: Entity>(
+        &self,
+        entity: &E,
+        expires_at: Option<SystemTime>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.intercept_done(Call::InsertRaw {
+            entity: entity.clone(),
+            expires_at,
+        })
+        .await
+    }
+
+    /// Not intercepted, since it touches no entity. The `create` of
+    /// `insert_new` is.
+    async fn next_sequence<E: Entity>(&self) -> Result<u64, Box<dyn Error>> {
+        self.inner.next_sequence::<E>().await
+    }
+
+    async fn update<E: Entity>(
+        &self,
+        id: &E::ID,
+        update: &E::Update,
+    ) -> Result<(), Box<dyn Error>> {
+        self.intercept_done::<E>(Call::Update {
+            id: id.clone(),
+            update: update.clone(),
+        })
+        .await
+    }
+
+    async fn delete_all<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
+        self.intercept_done::<E>(Call::DeleteAll).await
+    }
+
+    async fn delete_by_id<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.intercept_done::<E>(Call::DeleteById(id.clone())).await
+    }
+
+    async fn restore<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.intercept_done::<E>(Call::Restore(id.clone())).await
+    }
+
+    async fn purge<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.intercept_done::<E>(Call::Purge(id.clone())).await
+    }
+
+    async fn get_all<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        match self.intercept(Call::GetAll).await? {
+            Outcome::Entities(entities) => Ok(entities),
+            _ => Err(UnexpectedOutcomeError("entities").into()),
+        }
+    }
+
+    async fn get_deleted<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        match self.intercept(Call::GetDeleted).await? {
+            Outcome::Entities(entities) => Ok(entities),
+            _ => Err(UnexpectedOutcomeError("entities").into()),
+        }
+    }
+
+    async fn get_by_id<E: Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
+        match self.intercept::<E>(Call::GetById(id.clone())).await? {
+            Outcome::Entity(entity) => Ok(entity),
+            _ => Err(UnexpectedOutcomeError("an entity").into()),
+        }
+    }
+
+    async fn get_expiry<E: Entity>(&self, id: &E::ID) -> Result<Option<SystemTime>, Box<dyn Error>> {
+        match self.intercept::<E>(Call::GetExpiry(id.clone())).await? {
+            Outcome::Expiry(expires_at) => Ok(expires_at),
+            _ => Err(UnexpectedOutcomeError("an expiry").into()),
+        }
+    }
+
+    async fn watch<E: Entity>(&self, channel: Sender<Event<E>>) -> Result<(), Box<dyn Error>> {
+        self.intercept_done(Call::Watch(channel)).await
+    }
+
+    async fn watch_notifying<E: Entity>(&self, channel: Sender<Event<E>>, ready: Arc<Notify>) -> Result<(), Box<dyn Error>> {
+        match self.intercept_notifying(Call::Watch(channel), Some(ready)).await? {
+            Outcome::Done => Ok(()),
+            _ => Err(UnexpectedOutcomeError("nothing").into()),
+        }
+    }
+}
+
+/// An interceptor changed a call's outcome to one its method can't return.
+#[derive(Debug)]
+pub struct UnexpectedOutcomeError(&'static str);
+impl std::fmt::Display for UnexpectedOutcomeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "An interceptor returned the wrong outcome, expected {}",
+            self.0
+        ))
+    }
+}
+impl Error for UnexpectedOutcomeError {}
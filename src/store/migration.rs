@@ -0,0 +1,300 @@
+use std::{collections::HashMap, error::Error, future::Future, marker::PhantomData, sync::Arc};
+
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, broadcast::error::RecvError, mpsc};
+use tokio::task::JoinHandle;
+
+use super::get_if_exists;
+use crate::{Entity, Store};
+
+/// Copies every entity of a type from one store to another, replacing any
+/// that are already there. Returns how many were copied.
+pub async fn copy_all<E: Entity>(from: &impl Store, to: &impl Store) -> Result<usize, Box<dyn Error>> {
+    let entities = from.get_all::<E>().await?;
+    for entity in &entities {
+        put(from, to, entity).await?;
+    }
+    Ok(entities.len())
+}
+
+// Not every store lets `create` overwrite, so anything already there is
+// deleted first. The copy keeps the fields `from` filled in, and expires
+// when the original does.
+async fn put<E: Entity>(from: &impl Store, to: &impl Store, entity: &E) -> Result<(), Box<dyn Error>> {
+    let id = entity.get_id();
+    let expires_at = from.get_expiry::<E>(&id).await?;
+    if get_if_exists::<E>(to, &id).await?.is_some() {
+        to.delete_by_id::<E>(&id).await?;
+    }
+    to.insert_raw(entity, expires_at).await
+}
+
+/// Makes the copy of an entity in `to` match `from`, whatever happened to
+/// it since.
+async fn refresh<E: Entity>(from: &impl Store, to: &impl Store, id: &E::ID) -> Result<(), Box<dyn Error>> {
+    let current = get_if_exists::<E>(from, id).await?;
+    match current {
+        Some(entity) => put(from, to, &entity).await,
+        None => to.delete_by_id::<E>(id).await,
+    }
+}
+
+/// Copies the entities up to `last` again that the target doesn't have as
+/// the source does, reading them in batches.
+async fn recheck_through<E: Entity, From: Store, To: Store>(
+    migration: &Migration<From, To>,
+    last: &E::ID,
+) -> Result<(), Box<dyn Error>> {
+    // `last` itself may be gone, so the first ID after it marks the end.
+    let end = migration.from.get_page::<E>(Some(last), 1).await?;
+    let end = end.first().map(|entity| entity.get_id().into_owned());
+    let mut after = None;
+    loop {
+        let batch = migration.from.get_page::<E>(after.as_ref(), migration.batch_size).await?;
+        for entity in &batch {
+            let id = entity.get_id();
+            if end.as_ref() == Some(&*id) {
+                return Ok(());
+            }
+            let copied = match get_if_exists::<E>(&*migration.to, &id).await? {
+                Some(copy) => serde_json::to_value(copy)? == serde_json::to_value(entity)?,
+                None => false,
+            };
+            if !copied {
+                put(&*migration.from, &*migration.to, entity).await?;
+            }
+        }
+        match batch.last() {
+            Some(entity) if batch.len() == migration.batch_size => {
+                after = Some(entity.get_id().into_owned());
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// How far a migration got, by the last ID copied for each type. IDs are
+/// copied in the source store's order of them, as `Store::get_page` reads
+/// them, so a resumed migration carries on from the next one. It can be
+/// serialized to be kept between runs.
+///
+/// Entities the source gained or changed before the checkpoint between runs
+/// are copied when resuming, after checking each one copied so far against
+/// the target. Entities deleted from the source between runs stay in the
+/// target.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    copied_through: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub type_name: &'static str,
+    /// How many entities of the type have been copied in this run, not
+    /// counting ones copied again when resuming.
+    pub copied: usize,
+    pub checkpoint: Checkpoint,
+}
+
+type ProgressFn = Box<dyn Fn(&Progress) + Send + Sync>;
+type Refresh<From, To> = Box<dyn FnOnce(Arc<From>, Arc<To>) -> BoxFuture<'static, Result<(), Box<dyn Error>>> + Send>;
+
+/// Moves entities of the registered types from one store to another in
+/// batches.
+pub struct Migration<From: Store, To: Store> {
+    from: Arc<From>,
+    to: Arc<To>,
+    batch_size: usize,
+    capacity: usize,
+    steps: Vec<Box<dyn MigrationStep<From, To>>>,
+    on_progress: Option<ProgressFn>,
+}
+
+impl<From: Store, To: Store> Migration<From, To> {
+    pub fn new(from: Arc<From>, to: Arc<To>) -> Self {
+        Self {
+            from,
+            to,
+            batch_size: 100,
+            capacity: 1024,
+            steps: Vec::new(),
+            on_progress: None,
+        }
+    }
+
+    /// Copies entities of type `E` too, after any types added before it.
+    pub fn entity<E: Entity>(mut self) -> Self {
+        self.steps.push(Box::new(EntityStep::<E>(PhantomData)));
+        self
+    }
+
+    /// How many entities to read from the source at a time.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// How many events to buffer from each watched type while following.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Called after every batch, e.g. to save the checkpoint.
+    pub fn on_progress(mut self, on_progress: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    /// Copies everything not yet copied according to the checkpoint,
+    /// returning the checkpoint once done.
+    pub async fn run(&self, mut checkpoint: Checkpoint) -> Result<Checkpoint, Box<dyn Error>> {
+        for step in &self.steps {
+            step.copy(self, &mut checkpoint).await?;
+        }
+        Ok(checkpoint)
+    }
+
+    /// Like `run`, but then keeps applying changes made to the source until
+    /// `cutover` completes, so the source can stay in use throughout. Stop
+    /// writing to the source and give its last changes time to be watched
+    /// before completing `cutover`, since only changes that have already
+    /// arrived are applied after it.
+    pub async fn run_until(
+        &self,
+        checkpoint: Checkpoint,
+        cutover: impl Future<Output = ()> + Send,
+    ) -> Result<Checkpoint, Box<dyn Error>> {
+        // Watching starts before copying, so that nothing that changes while
+        // copying is missed. Changes are applied by re-reading the entity,
+        // so ones the copy already has are harmless.
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let watches: Vec<_> = self
+            .steps
+            .iter()
+            .map(|step| step.follow(self.from.clone(), self.capacity, tx.clone()))
+            .collect();
+        drop(tx);
+        tokio::task::yield_now().await;
+
+        let result = async {
+            let checkpoint = self.run(checkpoint).await?;
+            tokio::pin!(cutover);
+            loop {
+                tokio::select! {
+                    biased;
+                    refresh = rx.recv() => match refresh {
+                        Some(refresh) => refresh(self.from.clone(), self.to.clone()).await?,
+                        None => break,
+                    },
+                    _ = &mut cutover => {
+                        while let Ok(refresh) = rx.try_recv() {
+                            refresh(self.from.clone(), self.to.clone()).await?;
+                        }
+                        break;
+                    }
+                }
+            }
+            Ok(checkpoint)
+        }
+        .await;
+        for watch in watches {
+            watch.abort();
+        }
+        result
+    }
+
+    fn report(&self, progress: Progress) {
+        if let Some(on_progress) = &self.on_progress {
+            on_progress(&progress);
+        }
+    }
+}
+
+#[async_trait]
+trait MigrationStep<From: Store, To: Store>: Send + Sync {
+    async fn copy(&self, migration: &Migration<From, To>, checkpoint: &mut Checkpoint) -> Result<(), Box<dyn Error>>;
+
+    fn follow(
+        &self,
+        from: Arc<From>,
+        capacity: usize,
+        refreshes: mpsc::UnboundedSender<Refresh<From, To>>,
+    ) -> JoinHandle<()>;
+}
+
+struct EntityStep<E>(PhantomData<fn() -> E>);
+
+#[async_trait]
+impl<E: Entity, From: Store, To: Store> MigrationStep<From, To> for EntityStep<E> {
+    async fn copy(&self, migration: &Migration<From, To>, checkpoint: &mut Checkpoint) -> Result<(), Box<dyn Error>> {
+        let mut after = match checkpoint.copied_through.get(E::TYPE_NAME) {
+            Some(last) => Some(serde_json::from_str::<E::ID>(last)?),
+            None => None,
+        };
+        if let Some(last) = &after {
+            recheck_through::<E, _, _>(migration, last).await?;
+        }
+
+        let mut copied = 0;
+        loop {
+            let batch = migration.from.get_page::<E>(after.as_ref(), migration.batch_size).await?;
+            let Some(last) = batch.last() else {
+                break;
+            };
+            for entity in &batch {
+                put(&*migration.from, &*migration.to, entity).await?;
+            }
+            copied += batch.len();
+            let last = last.get_id().into_owned();
+            checkpoint
+                .copied_through
+                .insert(E::TYPE_NAME.to_owned(), serde_json::to_string(&last)?);
+            after = Some(last);
+            migration.report(Progress {
+                type_name: E::TYPE_NAME,
+                copied,
+                checkpoint: checkpoint.clone(),
+            });
+            if batch.len() < migration.batch_size {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn follow(
+        &self,
+        from: Arc<From>,
+        capacity: usize,
+        refreshes: mpsc::UnboundedSender<Refresh<From, To>>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let (tx, mut rx) = broadcast::channel(capacity);
+            let watch = tokio::spawn(async move {
+                let _ = from.watch::<E>(tx).await;
+            });
+            loop {
+                let refresh: Refresh<From, To> = match rx.recv().await {
+                    Ok(event) => {
+                        let id = event.id().into_owned();
+                        Box::new(move |from, to| {
+                            Box::pin(async move { refresh::<E>(&*from, &*to, &id).await })
+                        })
+                    }
+                    // Changes were missed, so the whole type is copied again.
+                    Err(RecvError::Lagged(_)) => Box::new(|from, to| {
+                        Box::pin(async move { copy_all::<E>(&*from, &*to).await.map(|_| ()) })
+                    }),
+                    Err(RecvError::Closed) => break,
+                };
+                if refreshes.send(refresh).is_err() {
+                    break;
+                }
+            }
+            watch.abort();
+        })
+    }
+}
@@ -1,33 +1,171 @@
-use crate::{Entity, Event, SingletonEntity, Singleton, SingletonEntityUpdate, SingletonEvent};
+use crate::entity::id_prefix;
+use crate::{CompositeId, Diff, Entity, Event, GeneratedId, IdGenerator, SequenceUnsupportedError, SingletonEntity, Singleton, SingletonEntityUpdate, SingletonEvent};
 use async_trait::async_trait;
-use std::{error::Error, fmt::Debug, sync::Arc};
+use serde::Serialize;
+use std::{error::Error, fmt::Debug, sync::Arc, task::Poll, time::{Duration, SystemTime}};
 use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::Notify;
 
 #[cfg(feature = "in-mem")]
 pub mod in_mem;
 
+#[cfg(feature = "in-mem")]
+pub mod cached;
+
+pub mod undo;
+
+pub mod history;
+
+pub mod event_sourced;
+
+pub mod replicated;
+
+pub mod bidirectional;
+
+pub mod migration;
+
+pub mod validating;
+
+pub mod layer;
+
+pub mod authorized;
+
+#[cfg(feature = "file-log")]
+pub mod file_log;
+
 #[async_trait]
 pub trait Store: Send + Sync + 'static {
     async fn create<E: Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>>;
     async fn create_singleton<S: Singleton>(&self, entity: &S) -> Result<(), Box<dyn Error>> {
         self.create(&SingletonEntity::new(entity.clone())).await
     }
+    /// Creates `entity` under a newly generated ID, replacing whatever ID it
+    /// had, and returns that ID.
+    async fn insert_new<E: GeneratedId>(&self, mut entity: E) -> Result<E::ID, Box<dyn Error>> {
+        let id = match E::ID_GENERATOR {
+            IdGenerator::Random(generate) => generate(),
+            IdGenerator::Sequence(from_sequence) => from_sequence(self.next_sequence::<E>().await?)?,
+        };
+        entity.set_id(id.clone());
+        self.create(&entity).await?;
+        Ok(id)
+    }
+    /// Creates `entity` to expire once `ttl` has passed, rather than after
+    /// the `TTL` of its type. Stores delete expired entities by themselves,
+    /// sending watchers `Event::Delete`.
+    async fn create_with_ttl<E: Entity>(&self, _entity: &E, _ttl: Duration) -> Result<(), Box<dyn Error>> {
+        Err(ExpiryUnsupportedError { type_name: E::TYPE_NAME }.into())
+    }
+    /// Creates `entity` as it is, keeping the fields the store would fill in
+    /// itself, like `#[entity(created_at)]`, and expiring it at `expires_at`
+    /// rather than after the `TTL` of its type. Replaces any entity with its
+    /// ID. For copying entities from another store, together with
+    /// `get_expiry`.
+    async fn insert_raw<E: Entity>(&self, entity: &E, expires_at: Option<SystemTime>) -> Result<(), Box<dyn Error>> {
+        match expires_at {
+            Some(at) => {
+                let ttl = at.duration_since(SystemTime::now()).unwrap_or_default();
+                self.create_with_ttl(entity, ttl).await
+            }
+            None => self.create(entity).await,
+        }
+    }
+    /// When the entity will expire, if it will.
+    async fn get_expiry<E: Entity>(&self, _id: &E::ID) -> Result<Option<SystemTime>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// Advances the counter sequential IDs of `E` are made from, returning
+    /// its new value. Values are never handed out twice, even after the
+    /// entities are deleted.
+    async fn next_sequence<E: Entity>(&self) -> Result<u64, Box<dyn Error>> {
+        Err(SequenceUnsupportedError { type_name: E::TYPE_NAME }.into())
+    }
     async fn update<E: Entity>(&self, id: &E::ID, update: &E::Update)
         -> Result<(), Box<dyn Error>>;
     async fn update_singleton<S: Singleton>(&self, update: &S::Update) -> Result<(), Box<dyn Error>> {
         self.update::<SingletonEntity<S>>(&S::ENTITY_ID.to_owned(), &SingletonEntityUpdate(update.clone())).await
     }
+    async fn save<E: Entity + Diff<E::Update>>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
+        let id = entity.get_id();
+        let current = self.get_by_id::<E>(&id).await?;
+        self.update::<E>(&id, &E::diff(&current, entity)).await
+    }
+    async fn save_singleton<S: Singleton + Diff<S::Update>>(&self, singleton: &S) -> Result<(), Box<dyn Error>> {
+        let current = self.get_singleton::<S>().await?;
+        self.update_singleton::<S>(&S::diff(&current, singleton)).await
+    }
     async fn delete_all<E: Entity>(&self) -> Result<(), Box<dyn Error>>;
     async fn delete_by_id<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>>;
     async fn delete_singleton<S: Singleton>(&self) -> Result<(), Box<dyn Error>> {
         self.delete_by_id::<SingletonEntity<S>>(&S::ENTITY_ID.to_owned()).await
     }
+    /// Brings back a soft-deleted entity of a `#[entity(soft_delete)]` type,
+    /// sending watchers `Event::Create`.
+    async fn restore<E: Entity>(&self, _id: &E::ID) -> Result<(), Box<dyn Error>> {
+        Err(SoftDeleteUnsupportedError { type_name: E::TYPE_NAME }.into())
+    }
+    /// Deletes an entity for good, whether or not it was soft deleted.
+    /// Watchers are sent `Event::Delete` if it wasn't.
+    async fn purge<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.delete_by_id::<E>(id).await
+    }
     async fn get_all<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>>;
+    /// Gets the soft-deleted entities of a `#[entity(soft_delete)]` type,
+    /// which the other getters leave out.
+    async fn get_deleted<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        Err(SoftDeleteUnsupportedError { type_name: E::TYPE_NAME }.into())
+    }
     async fn get_by_id<E: Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>>;
+    /// Gets the entities whose IDs start with `prefix`, the values of their
+    /// leading fields as a tuple, or as a single value for just the first.
+    async fn get_by_id_prefix<E: Entity, P: Serialize + Sync>(&self, prefix: &P) -> Result<Vec<E>, Box<dyn Error>>
+    where
+        E::ID: CompositeId,
+    {
+        let prefix = id_prefix::<E::ID, _>(prefix_values(serde_json::to_value(prefix)?))?;
+        let mut matching = Vec::new();
+        for entity in self.get_all::<E>().await? {
+            let id = serde_json::to_value(&*entity.get_id())?;
+            if prefix.iter().all(|(field, value)| id.get(field) == Some(value)) {
+                matching.push(entity);
+            }
+        }
+        Ok(matching)
+    }
+    /// Gets up to `limit` entities, in the store's own order of their IDs,
+    /// starting after the one with ID `after`, whether or not it still
+    /// exists, or from the first without it. By default the order is that
+    /// of the IDs' JSON form, and every entity is read for each page.
+    async fn get_page<E: Entity>(&self, after: Option<&E::ID>, limit: usize) -> Result<Vec<E>, Box<dyn Error>> {
+        let after = after.map(serde_json::to_string).transpose()?;
+        let mut keyed = Vec::new();
+        for entity in self.get_all::<E>().await? {
+            let key = serde_json::to_string(&*entity.get_id())?;
+            if after.as_ref().is_none_or(|after| key > *after) {
+                keyed.push((key, entity));
+            }
+        }
+        keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(keyed.into_iter().take(limit).map(|(_, entity)| entity).collect())
+    }
     async fn get_singleton<S: Singleton>(&self) -> Result<S, Box<dyn Error>> {
         self.get_by_id::<SingletonEntity<S>>(&S::ENTITY_ID.to_owned()).await.map(|se| se.0)
     }
     async fn watch<E: Entity>(&self, channel: Sender<Event<E>>) -> Result<(), Box<dyn Error>>;
+    /// Like `watch`, but notifies `ready` once changes are being watched, so
+    /// that none made after that are missed, or if the watch ends before
+    /// then. By default the watch is taken to be ready once it's been polled,
+    /// which only holds for stores that subscribe without waiting on anything.
+    async fn watch_notifying<E: Entity>(&self, channel: Sender<Event<E>>, ready: Arc<Notify>) -> Result<(), Box<dyn Error>> {
+        let watch = self.watch(channel);
+        tokio::pin!(watch);
+        if let Poll::Ready(result) = futures_util::poll!(&mut watch) {
+            ready.notify_one();
+            return result;
+        }
+        ready.notify_one();
+        watch.await
+    }
     async fn watch_singleton<S: Singleton>(self: Arc<Self>, channel: Sender<SingletonEvent<S>>, capacity: usize) -> Result<(), Box<dyn Error>> {
         let (tx, mut rx) = tokio::sync::broadcast::channel(capacity);
         let clone = self.clone();
@@ -52,6 +190,38 @@ pub trait Store: Send + Sync + 'static {
     }
 }
 
+/// Stores that can keep the entities of separate tenants apart.
+pub trait MultiTenant: Store {
+    type Tenant: Store;
+    /// A view of the store holding only `tenant`'s entities, which watches
+    /// only see changes to. Views of the same tenant share their entities.
+    fn for_tenant(&self, tenant: &str) -> Self::Tenant;
+}
+
+fn prefix_values(prefix: serde_json::Value) -> Vec<serde_json::Value> {
+    match prefix {
+        serde_json::Value::Array(values) => values,
+        value => vec![value],
+    }
+}
+
+/// Stores that fill in `#[entity(modified_by)]` fields.
+pub trait ActingAs: Store {
+    type Acting: Store;
+    /// A view of the store sharing its entities, which records `actor` as
+    /// the one making its changes.
+    fn acting_as(&self, actor: &str) -> Self::Acting;
+}
+
+/// Gets an entity, or `None` if the store doesn't have it.
+pub(crate) async fn get_if_exists<E: Entity>(store: &impl Store, id: &E::ID) -> Result<Option<E>, Box<dyn Error>> {
+    match store.get_by_id::<E>(id).await {
+        Ok(entity) => Ok(Some(entity)),
+        Err(e) if e.is::<NotFoundError<E::ID>>() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
 #[derive(Debug)]
 pub struct NotFoundError<T: Debug>(pub T);
 impl<T: Debug> std::fmt::Display for NotFoundError<T> {
@@ -60,3 +230,27 @@ impl<T: Debug> std::fmt::Display for NotFoundError<T> {
     }
 }
 impl<T: Debug> Error for NotFoundError<T> {}
+
+/// The store can't delete entities when they expire.
+#[derive(Debug)]
+pub struct ExpiryUnsupportedError {
+    pub type_name: &'static str,
+}
+impl std::fmt::Display for ExpiryUnsupportedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Store can't expire {}.", self.type_name)
+    }
+}
+impl Error for ExpiryUnsupportedError {}
+
+/// The store can't keep deleted entities around to restore.
+#[derive(Debug)]
+pub struct SoftDeleteUnsupportedError {
+    pub type_name: &'static str,
+}
+impl std::fmt::Display for SoftDeleteUnsupportedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Store can't soft delete {}.", self.type_name)
+    }
+}
+impl Error for SoftDeleteUnsupportedError {}
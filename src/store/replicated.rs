@@ -0,0 +1,432 @@
+use std::{
+    error::Error,
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use futures_util::future::BoxFuture;
+use tokio::sync::{broadcast::Sender, mpsc, watch, Mutex, Notify};
+
+use crate::telemetry;
+use crate::{CompositeId, Entity, Event, Singleton, SingletonEvent, Store};
+
+const STORE: &str = "replicated";
+
+type Write<S> = Box<dyn Fn(Arc<S>) -> BoxFuture<'static, Result<(), Box<dyn Error>>> + Send + Sync>;
+
+/// A write to mirror onto a secondary, which can be retried.
+struct Mirror<S> {
+    operation: &'static str,
+    type_name: &'static str,
+    id: Option<String>,
+    write: Write<S>,
+}
+
+impl<S> Mirror<S> {
+    fn new(
+        operation: &'static str,
+        type_name: &'static str,
+        id: Option<&dyn Debug>,
+        write: impl Fn(Arc<S>) -> BoxFuture<'static, Result<(), Box<dyn Error>>> + Send + Sync + 'static,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            operation,
+            type_name,
+            id: id.map(|id| format!("{:?}", id)),
+            write: Box::new(write),
+        })
+    }
+}
+
+/// How a secondary's failed writes are retried, waiting twice as long after
+/// each failure, up to `max_delay`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// How many times a write is tried before it's given up on.
+    pub attempts: u32,
+    /// How long to wait before the first retry.
+    pub delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+/// A write a secondary kept failing, which was given up on so that the
+/// writes after it could be mirrored.
+#[derive(Debug, Clone)]
+pub struct FailedMirror {
+    /// The secondary's position in the list the store was made with.
+    pub secondary: usize,
+    /// The name of the `Store` method that failed.
+    pub operation: &'static str,
+    pub type_name: &'static str,
+    /// The debug form of the ID written to, for writes to a single entity.
+    pub id: Option<String>,
+    /// The error from the last attempt.
+    pub error: String,
+}
+
+/// Writes to a primary store and mirrors each successful write onto the
+/// secondaries in the background. Reads and watches are served by the
+/// primary. A secondary gets its writes in the order the primary did, and a
+/// write that fails is retried by the `RetryPolicy` before any later ones.
+/// A write still failing after that is given up on, and kept to be
+/// collected with `take_failed`.
+pub struct ReplicatedStore<Primary: Store, Secondary: Store> {
+    primary: Arc<Primary>,
+    queues: Vec<mpsc::UnboundedSender<Arc<Mirror<Secondary>>>>,
+    pending: Arc<watch::Sender<usize>>,
+    failed: Arc<std::sync::Mutex<Vec<FailedMirror>>>,
+    // Held while writing to the primary and queueing the mirrors, so that
+    // queued writes are in the same order as the primary's.
+    order: Mutex<()>,
+}
+
+impl<Primary: Store, Secondary: Store> ReplicatedStore<Primary, Secondary> {
+    /// Starts a background task per secondary, so this must be called from
+    /// within a Tokio runtime.
+    pub fn new(primary: Arc<Primary>, secondaries: Vec<Arc<Secondary>>, retry: RetryPolicy) -> Self {
+        let pending = Arc::new(watch::channel(0).0);
+        let failed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let queues = secondaries
+            .into_iter()
+            .enumerate()
+            .map(|(index, secondary)| {
+                let (tx, mut rx) = mpsc::unbounded_channel::<Arc<Mirror<Secondary>>>();
+                let pending = pending.clone();
+                let failed = failed.clone();
+                let retry = retry.clone();
+                tokio::spawn(async move {
+                    while let Some(mirror) = rx.recv().await {
+                        if let Err(error) = mirror_with_retries(&mirror, &secondary, &retry).await {
+                            telemetry::failed_in_background(
+                                STORE,
+                                mirror.type_name,
+                                mirror.operation,
+                                &*error,
+                            );
+                            failed.lock().unwrap().push(FailedMirror {
+                                secondary: index,
+                                operation: mirror.operation,
+                                type_name: mirror.type_name,
+                                id: mirror.id.clone(),
+                                error: error.to_string(),
+                            });
+                        }
+                        pending.send_modify(|n| *n -= 1);
+                    }
+                });
+                tx
+            })
+            .collect();
+        Self {
+            primary,
+            queues,
+            pending,
+            failed,
+            order: Mutex::new(()),
+        }
+    }
+
+    pub fn primary(&self) -> &Arc<Primary> {
+        &self.primary
+    }
+
+    /// How many writes are waiting to be mirrored, counting each secondary
+    /// separately.
+    pub fn pending(&self) -> usize {
+        *self.pending.borrow()
+    }
+
+    /// Waits until every write so far has been mirrored or given up on.
+    pub async fn flush(&self) {
+        let mut pending = self.pending.subscribe();
+        let _ = pending.wait_for(|&n| n == 0).await;
+    }
+
+    /// Takes the writes given up on so far, oldest first.
+    pub fn take_failed(&self) -> Vec<FailedMirror> {
+        std::mem::take(&mut *self.failed.lock().unwrap())
+    }
+
+    async fn replicate(
+        &self,
+        write: BoxFuture<'_, Result<(), Box<dyn Error>>>,
+        mirror: Arc<Mirror<Secondary>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let _order = self.order.lock().await;
+        write.await?;
+        self.enqueue(mirror);
+        Ok(())
+    }
+
+    /// Like `replicate`, but mirrors the written entity as the primary
+    /// stored it, with the fields it filled in, like `#[entity(updated_at)]`,
+    /// and its expiry, falling back to `mirror` if it can't be read back.
+    async fn replicate_entity<E: Entity>(
+        &self,
+        id: &E::ID,
+        write: BoxFuture<'_, Result<(), Box<dyn Error>>>,
+        mirror: Arc<Mirror<Secondary>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let _order = self.order.lock().await;
+        write.await?;
+        let entity = self.primary.get_by_id::<E>(id).await.ok();
+        let expires_at = self.primary.get_expiry::<E>(id).await.ok();
+        let mirror = match (entity, expires_at) {
+            (Some(entity), Some(expires_at)) => {
+                Mirror::<Secondary>::new(mirror.operation, E::TYPE_NAME, Some(id), move |s| {
+                    let entity = entity.clone();
+                    Box::pin(async move { s.insert_raw(&entity, expires_at).await })
+                })
+            }
+            _ => mirror,
+        };
+        self.enqueue(mirror);
+        Ok(())
+    }
+
+    fn enqueue(&self, mirror: Arc<Mirror<Secondary>>) {
+        for queue in &self.queues {
+            self.pending.send_modify(|n| *n += 1);
+            if queue.send(mirror.clone()).is_err() {
+                self.pending.send_modify(|n| *n -= 1);
+            }
+        }
+    }
+}
+
+/// Tries a write until it succeeds or runs out of attempts, returning the
+/// last error if it never succeeded.
+async fn mirror_with_retries<S: Store>(
+    mirror: &Mirror<S>,
+    secondary: &Arc<S>,
+    retry: &RetryPolicy,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut delay = retry.delay;
+    let mut attempt = 1;
+    loop {
+        // Kept as a string, since the error can't be held across the wait.
+        let error = match (mirror.write)(secondary.clone()).await {
+            Ok(()) => return Ok(()),
+            Err(e) => e.to_string(),
+        };
+        if attempt >= retry.attempts {
+            return Err(error.into());
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(retry.max_delay);
+        attempt += 1;
+    }
+}
+
+#[async_trait]
+impl<Primary: Store, Secondary: Store> Store for ReplicatedStore<Primary, Secondary> {
+    async fn create<E: Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
+        let mirrored = entity.clone();
+        self.replicate_entity::<E>(
+            &entity.get_id(),
+            self.primary.create(entity),
+            Mirror::<Secondary>::new("create", E::TYPE_NAME, Some(&*entity.get_id()), move |s| {
+                let entity = mirrored.clone();
+                Box::pin(async move { s.create(&entity).await })
+            }),
+        )
+        .await
+    }
+
+    /// The secondaries expire their copies by themselves, at the time the
+    /// primary's expires.
+    async fn create_with_ttl<E: Entity>(&self, entity: &E, ttl: Duration) -> Result<(), Box<dyn Error>> {
+        let mirrored = entity.clone();
+        self.replicate_entity::<E>(
+            &entity.get_id(),
+            self.primary.create_with_ttl(entity, ttl),
+            Mirror::<Secondary>::new("create_with_ttl", E::TYPE_NAME, Some(&*entity.get_id()), move |s| {
+                let entity = mirrored.clone();
+                Box::pin(async move { s.create_with_ttl(&entity, ttl).await })
+            }),
+        )
+        .await
+    }
+
+    async fn insert_raw<E: Entity>(&self, entity: &E, expires_at: Option<SystemTime>) -> Result<(), Box<dyn Error>> {
+        let mirrored = entity.clone();
+        self.replicate(
+            self.primary.insert_raw(entity, expires_at),
+            Mirror::<Secondary>::new("insert_raw", E::TYPE_NAME, Some(&*entity.get_id()), move |s| {
+                let entity = mirrored.clone();
+                Box::pin(async move { s.insert_raw(&entity, expires_at).await })
+            }),
+        )
+        .await
+    }
+
+    /// Sequences come from the primary alone. The secondaries are sent the
+    /// entity with the ID it assigned.
+    async fn next_sequence<E: Entity>(&self) -> Result<u64, Box<dyn Error>> {
+        self.primary.next_sequence::<E>().await
+    }
+
+    async fn create_singleton<S: Singleton>(&self, entity: &S) -> Result<(), Box<dyn Error>> {
+        let mirrored = entity.clone();
+        self.replicate(
+            self.primary.create_singleton(entity),
+            Mirror::<Secondary>::new("create_singleton", S::TYPE_NAME, Some(&S::ENTITY_ID), move |s| {
+                let entity = mirrored.clone();
+                Box::pin(async move { s.create_singleton(&entity).await })
+            }),
+        )
+        .await
+    }
+
+    async fn update<E: Entity>(
+        &self,
+        id: &E::ID,
+        update: &E::Update,
+    ) -> Result<(), Box<dyn Error>> {
+        let mirrored = (id.clone(), update.clone());
+        self.replicate_entity::<E>(
+            id,
+            self.primary.update::<E>(id, update),
+            Mirror::<Secondary>::new("update", E::TYPE_NAME, Some(id), move |s| {
+                let (id, update) = mirrored.clone();
+                Box::pin(async move { s.update::<E>(&id, &update).await })
+            }),
+        )
+        .await
+    }
+
+    async fn update_singleton<S: Singleton>(&self, update: &S::Update) -> Result<(), Box<dyn Error>> {
+        let mirrored = update.clone();
+        self.replicate(
+            self.primary.update_singleton::<S>(update),
+            Mirror::<Secondary>::new("update_singleton", S::TYPE_NAME, Some(&S::ENTITY_ID), move |s| {
+                let update = mirrored.clone();
+                Box::pin(async move { s.update_singleton::<S>(&update).await })
+            }),
+        )
+        .await
+    }
+
+    async fn delete_all<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
+        self.replicate(
+            self.primary.delete_all::<E>(),
+            Mirror::<Secondary>::new("delete_all", E::TYPE_NAME, None, |s| {
+                Box::pin(async move { s.delete_all::<E>().await })
+            }),
+        )
+        .await
+    }
+
+    async fn delete_by_id<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        let mirrored = id.clone();
+        self.replicate(
+            self.primary.delete_by_id::<E>(id),
+            Mirror::<Secondary>::new("delete_by_id", E::TYPE_NAME, Some(id), move |s| {
+                let id = mirrored.clone();
+                Box::pin(async move { s.delete_by_id::<E>(&id).await })
+            }),
+        )
+        .await
+    }
+
+    async fn restore<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        let mirrored = id.clone();
+        self.replicate(
+            self.primary.restore::<E>(id),
+            Mirror::<Secondary>::new("restore", E::TYPE_NAME, Some(id), move |s| {
+                let id = mirrored.clone();
+                Box::pin(async move { s.restore::<E>(&id).await })
+            }),
+        )
+        .await
+    }
+
+    async fn purge<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        let mirrored = id.clone();
+        self.replicate(
+            self.primary.purge::<E>(id),
+            Mirror::<Secondary>::new("purge", E::TYPE_NAME, Some(id), move |s| {
+                let id = mirrored.clone();
+                Box::pin(async move { s.purge::<E>(&id).await })
+            }),
+        )
+        .await
+    }
+
+    async fn delete_singleton<S: Singleton>(&self) -> Result<(), Box<dyn Error>> {
+        self.replicate(
+            self.primary.delete_singleton::<S>(),
+            Mirror::<Secondary>::new("delete_singleton", S::TYPE_NAME, Some(&S::ENTITY_ID), |s| {
+                Box::pin(async move { s.delete_singleton::<S>().await })
+            }),
+        )
+        .await
+    }
+
+    async fn get_all<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        self.primary.get_all().await
+    }
+
+    async fn get_deleted<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        self.primary.get_deleted().await
+    }
+
+    async fn get_by_id_prefix<E: Entity, P: Serialize + Sync>(
+        &self,
+        prefix: &P,
+    ) -> Result<Vec<E>, Box<dyn Error>>
+    where
+        E::ID: CompositeId,
+    {
+        self.primary.get_by_id_prefix(prefix).await
+    }
+
+    async fn get_page<E: Entity>(
+        &self,
+        after: Option<&E::ID>,
+        limit: usize,
+    ) -> Result<Vec<E>, Box<dyn Error>> {
+        self.primary.get_page(after, limit).await
+    }
+
+    async fn get_by_id<E: Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
+        self.primary.get_by_id(id).await
+    }
+
+    async fn get_expiry<E: Entity>(&self, id: &E::ID) -> Result<Option<SystemTime>, Box<dyn Error>> {
+        self.primary.get_expiry::<E>(id).await
+    }
+
+    async fn get_singleton<S: Singleton>(&self) -> Result<S, Box<dyn Error>> {
+        self.primary.get_singleton().await
+    }
+
+    async fn watch<E: Entity>(&self, channel: Sender<Event<E>>) -> Result<(), Box<dyn Error>> {
+        self.primary.watch(channel).await
+    }
+
+    async fn watch_notifying<E: Entity>(&self, channel: Sender<Event<E>>, ready: Arc<Notify>) -> Result<(), Box<dyn Error>> {
+        self.primary.watch_notifying(channel, ready).await
+    }
+
+    async fn watch_singleton<S: Singleton>(
+        self: Arc<Self>,
+        channel: Sender<SingletonEvent<S>>,
+        capacity: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        self.primary.clone().watch_singleton(channel, capacity).await
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::sync::{broadcast::Sender, Mutex, Notify};
+use typemap_rev::{TypeMap, TypeMapKey};
+
+use crate::{Composable, CompositeId, Entity, Event, Store};
+
+/// Records the updates made through it to the entity types registered with
+/// `undoable`, so they can be undone and redone. Undoing and redoing are
+/// regular updates to the inner store, so watchers see them like any other
+/// update. Updates made to the inner store directly aren't recorded, and
+/// creating or deleting an entity forgets its recorded updates.
+pub struct UndoStore<S: Store> {
+    inner: Arc<S>,
+    depth: usize,
+    inverters: TypeMap,
+    histories: std::sync::Mutex<TypeMap>,
+}
+
+struct InverterKey<E>(PhantomData<E>);
+impl<E: Entity> TypeMapKey for InverterKey<E> {
+    type Value = fn(&E, &E::Update) -> E::Update;
+}
+
+struct Step<E: Entity> {
+    update: E::Update,
+    inverse: E::Update,
+}
+
+struct Stacks<E: Entity> {
+    undo: VecDeque<Step<E>>,
+    redo: Vec<Step<E>>,
+}
+
+impl<E: Entity> Default for Stacks<E> {
+    fn default() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+        }
+    }
+}
+
+// Each entity's stacks are locked separately, and held while its updates go
+// through, so that recording one entity's update doesn't hold up another's.
+struct HistoryKey<E: Entity>(PhantomData<E>);
+impl<E: Entity> TypeMapKey for HistoryKey<E> {
+    type Value = HashMap<E::ID, Arc<Mutex<Stacks<E>>>>;
+}
+
+impl<S: Store> UndoStore<S> {
+    /// Keeps up to `depth` updates per entity, forgetting the oldest first.
+    pub fn new(inner: Arc<S>, depth: usize) -> Self {
+        Self {
+            inner,
+            depth,
+            inverters: TypeMap::new(),
+            histories: std::sync::Mutex::new(TypeMap::new()),
+        }
+    }
+
+    /// Records updates to `E`s.
+    pub fn undoable<E: Entity + Composable<E::Update>>(mut self) -> Self {
+        self.inverters.insert::<InverterKey<E>>(E::inverse);
+        self
+    }
+
+    pub fn inner(&self) -> &Arc<S> {
+        &self.inner
+    }
+
+    /// Reverts the entity's latest recorded update, returning whether there
+    /// was one to revert.
+    pub async fn undo<E: Entity>(&self, id: &E::ID) -> Result<bool, Box<dyn Error>> {
+        let Some(stacks) = self.existing_stacks::<E>(id) else {
+            return Ok(false);
+        };
+        let mut stacks = stacks.lock().await;
+        let Some(step) = stacks.undo.pop_back() else {
+            return Ok(false);
+        };
+        if let Err(e) = self.inner.update::<E>(id, &step.inverse).await {
+            stacks.undo.push_back(step);
+            return Err(e);
+        }
+        stacks.redo.push(step);
+        Ok(true)
+    }
+
+    /// Reapplies the entity's latest undone update, returning whether there
+    /// was one to reapply.
+    pub async fn redo<E: Entity>(&self, id: &E::ID) -> Result<bool, Box<dyn Error>> {
+        let (Some(inverter), Some(stacks)) = (
+            self.inverters.get::<InverterKey<E>>(),
+            self.existing_stacks::<E>(id),
+        ) else {
+            return Ok(false);
+        };
+        let mut stacks = stacks.lock().await;
+        let Some(step) = stacks.redo.pop() else {
+            return Ok(false);
+        };
+        // The entity may have changed since the update was first made, so
+        // the inverse is captured again.
+        let inverse = match self.inner.get_by_id::<E>(id).await {
+            Ok(current) => inverter(&current, &step.update),
+            Err(e) => {
+                stacks.redo.push(step);
+                return Err(e);
+            }
+        };
+        if let Err(e) = self.inner.update::<E>(id, &step.update).await {
+            stacks.redo.push(step);
+            return Err(e);
+        }
+        self.push_undo(&mut stacks, step.update, inverse);
+        Ok(true)
+    }
+
+    /// Forgets the recorded updates for the entity.
+    pub fn clear<E: Entity>(&self, id: &E::ID) {
+        let mut histories = self.histories.lock().unwrap();
+        if let Some(history) = histories.get_mut::<HistoryKey<E>>() {
+            history.remove(id);
+        }
+    }
+
+    fn stacks<E: Entity>(&self, id: &E::ID) -> Arc<Mutex<Stacks<E>>> {
+        let mut histories = self.histories.lock().unwrap();
+        let history = histories.entry::<HistoryKey<E>>().or_default();
+        history.entry(id.clone()).or_default().clone()
+    }
+
+    fn existing_stacks<E: Entity>(&self, id: &E::ID) -> Option<Arc<Mutex<Stacks<E>>>> {
+        let histories = self.histories.lock().unwrap();
+        histories.get::<HistoryKey<E>>()?.get(id).cloned()
+    }
+
+    fn push_undo<E: Entity>(&self, stacks: &mut Stacks<E>, update: E::Update, inverse: E::Update) {
+        stacks.undo.push_back(Step { update, inverse });
+        while stacks.undo.len() > self.depth {
+            stacks.undo.pop_front();
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Store> Store for UndoStore<S> {
+    async fn create<E: Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
+        self.inner.create(entity).await?;
+        self.clear::<E>(&entity.get_id());
+        Ok(())
+    }
+
+    async fn create_with_ttl<E: Entity>(
+        &self,
+        entity: &E,
+        ttl: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        self.inner.create_with_ttl(entity, ttl).await?;
+        self.clear::<E>(&entity.get_id());
+        Ok(())
+    }
+
+    async fn insert_raw<E: Entity>(
+        &self,
+        entity: &E,
+        expires_at: Option<SystemTime>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.inner.insert_raw(entity, expires_at).await?;
+        self.clear::<E>(&entity.get_id());
+        Ok(())
+    }
+
+    async fn next_sequence<E: Entity>(&self) -> Result<u64, Box<dyn Error>> {
+        self.inner.next_sequence::<E>().await
+    }
+
+    /// Records the update so it can be undone, forgetting anything that
+    /// could have been redone.
+    async fn update<E: Entity>(
+        &self,
+        id: &E::ID,
+        update: &E::Update,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(inverter) = self.inverters.get::<InverterKey<E>>() else {
+            return self.inner.update::<E>(id, update).await;
+        };
+        let stacks = self.stacks::<E>(id);
+        let mut stacks = stacks.lock().await;
+        let current = self.inner.get_by_id::<E>(id).await?;
+        let inverse = inverter(&current, update);
+        self.inner.update::<E>(id, update).await?;
+        stacks.redo.clear();
+        self.push_undo(&mut stacks, update.clone(), inverse);
+        Ok(())
+    }
+
+    async fn delete_all<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
+        self.inner.delete_all::<E>().await?;
+        self.histories.lock().unwrap().remove::<HistoryKey<E>>();
+        Ok(())
+    }
+
+    async fn delete_by_id<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.inner.delete_by_id::<E>(id).await?;
+        self.clear::<E>(id);
+        Ok(())
+    }
+
+    async fn restore<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.inner.restore::<E>(id).await
+    }
+
+    async fn purge<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.inner.purge::<E>(id).await?;
+        self.clear::<E>(id);
+        Ok(())
+    }
+
+    async fn get_all<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        self.inner.get_all().await
+    }
+
+    async fn get_deleted<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        self.inner.get_deleted().await
+    }
+
+    async fn get_by_id_prefix<E: Entity, P: Serialize + Sync>(
+        &self,
+        prefix: &P,
+    ) -> Result<Vec<E>, Box<dyn Error>>
+    where
+        E::ID: CompositeId,
+    {
+        self.inner.get_by_id_prefix(prefix).await
+    }
+
+    async fn get_page<E: Entity>(
+        &self,
+        after: Option<&E::ID>,
+        limit: usize,
+    ) -> Result<Vec<E>, Box<dyn Error>> {
+        self.inner.get_page(after, limit).await
+    }
+
+    async fn get_by_id<E: Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
+        self.inner.get_by_id(id).await
+    }
+
+    async fn get_expiry<E: Entity>(&self, id: &E::ID) -> Result<Option<SystemTime>, Box<dyn Error>> {
+        self.inner.get_expiry::<E>(id).await
+    }
+
+    async fn watch<E: Entity>(&self, channel: Sender<Event<E>>) -> Result<(), Box<dyn Error>> {
+        self.inner.watch(channel).await
+    }
+
+    async fn watch_notifying<E: Entity>(&self, channel: Sender<Event<E>>, ready: Arc<Notify>) -> Result<(), Box<dyn Error>> {
+        self.inner.watch_notifying(channel, ready).await
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    error::Error,
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::sync::{broadcast::Sender, Notify};
+use typemap_rev::{TypeMap, TypeMapKey};
+
+use crate::{CompositeId, Entity, Event, Store, Validate, ValidationError};
+
+use super::get_if_exists;
+
+/// Rejects creates and updates of invalid entities before they reach the
+/// underlying store, for the entity types registered with `validate`. An
+/// update is checked against the entity as it was read just before the
+/// update, so a concurrent update can still slip in between the two.
+pub struct ValidatingStore<S: Store> {
+    inner: Arc<S>,
+    validators: TypeMap,
+}
+
+struct Validator<E: Entity> {
+    entity: fn(&E) -> Result<(), ValidationError>,
+    update: fn(&E, &E::Update) -> Result<(), ValidationError>,
+}
+
+struct ValidatorKey<E>(PhantomData<E>);
+impl<E: Entity> TypeMapKey for ValidatorKey<E> {
+    type Value = Validator<E>;
+}
+
+impl<S: Store> ValidatingStore<S> {
+    pub fn new(inner: Arc<S>) -> Self {
+        Self {
+            inner,
+            validators: TypeMap::new(),
+        }
+    }
+
+    /// Validates writes of `E`s.
+    pub fn validate<E: Validate>(mut self) -> Self {
+        self.validators.insert::<ValidatorKey<E>>(Validator {
+            entity: E::validate,
+            update: E::validate_update,
+        });
+        self
+    }
+
+    pub fn inner(&self) -> &Arc<S> {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<S: Store> Store for ValidatingStore<S> {
+    async fn create<E: Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
+        if let Some(validator) = self.validators.get::<ValidatorKey<E>>() {
+            (validator.entity)(entity)?;
+        }
+        self.inner.create(entity).await
+    }
+
+    async fn create_with_ttl<E: Entity>(
+        &self,
+        entity: &E,
+        ttl: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(validator) = self.validators.get::<ValidatorKey<E>>() {
+            (validator.entity)(entity)?;
+        }
+        self.inner.create_with_ttl(entity, ttl).await
+    }
+
+    async fn insert_raw<E: Entity>(
+        &self,
+        entity: &E,
+        expires_at: Option<SystemTime>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(validator) = self.validators.get::<ValidatorKey<E>>() {
+            (validator.entity)(entity)?;
+        }
+        self.inner.insert_raw(entity, expires_at).await
+    }
+
+    async fn next_sequence<E: Entity>(&self) -> Result<u64, Box<dyn Error>> {
+        self.inner.next_sequence::<E>().await
+    }
+
+    async fn update<E: Entity>(
+        &self,
+        id: &E::ID,
+        update: &E::Update,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(validator) = self.validators.get::<ValidatorKey<E>>() {
+            let current = get_if_exists::<E>(&*self.inner, id).await?;
+            // Updating a missing entity is left to the underlying store.
+            if let Some(current) = current {
+                (validator.update)(&current, update)?;
+            }
+        }
+        self.inner.update::<E>(id, update).await
+    }
+
+    async fn delete_all<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
+        self.inner.delete_all::<E>().await
+    }
+
+    async fn delete_by_id<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.inner.delete_by_id::<E>(id).await
+    }
+
+    async fn restore<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.inner.restore::<E>(id).await
+    }
+
+    async fn purge<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.inner.purge::<E>(id).await
+    }
+
+    async fn get_all<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        self.inner.get_all().await
+    }
+
+    async fn get_deleted<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        self.inner.get_deleted().await
+    }
+
+    async fn get_by_id_prefix<E: Entity, P: Serialize + Sync>(
+        &self,
+        prefix: &P,
+    ) -> Result<Vec<E>, Box<dyn Error>>
+    where
+        E::ID: CompositeId,
+    {
+        self.inner.get_by_id_prefix(prefix).await
+    }
+
+    async fn get_page<E: Entity>(
+        &self,
+        after: Option<&E::ID>,
+        limit: usize,
+    ) -> Result<Vec<E>, Box<dyn Error>> {
+        self.inner.get_page(after, limit).await
+    }
+
+    async fn get_by_id<E: Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
+        self.inner.get_by_id(id).await
+    }
+
+    async fn get_expiry<E: Entity>(&self, id: &E::ID) -> Result<Option<SystemTime>, Box<dyn Error>> {
+        self.inner.get_expiry::<E>(id).await
+    }
+
+    async fn watch<E: Entity>(&self, channel: Sender<Event<E>>) -> Result<(), Box<dyn Error>> {
+        self.inner.watch(channel).await
+    }
+
+    async fn watch_notifying<E: Entity>(&self, channel: Sender<Event<E>>, ready: Arc<Notify>) -> Result<(), Box<dyn Error>> {
+        self.inner.watch_notifying(channel, ready).await
+    }
+}
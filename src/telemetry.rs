@@ -0,0 +1,185 @@
+//! Tracing spans and metrics for store operations, when the `tracing` and
+//! `metrics` features are enabled. Without them these do nothing.
+//!
+//! Metrics are reported through the `metrics` crate, labelled with the
+//! `store` and the `entity` type name:
+//! - `live_entity_operations_total`, a counter also labelled with the
+//!   `operation` and its `outcome`, either `ok` or `error`.
+//! - `live_entity_operation_duration_seconds`, a histogram also labelled
+//!   with the `operation`.
+//! - `live_entity_watchers`, a gauge of the watches currently running.
+//! - `live_entity_events_total`, a counter of events sent to watchers, also
+//!   labelled with the event's `kind`.
+//! - `live_entity_lagged_total`, a counter of watches that fell behind their
+//!   store and missed events.
+
+use std::error::Error;
+#[cfg(any(feature = "in-mem", feature = "mongodb"))]
+use std::{fmt::Debug, future::Future};
+
+#[cfg(any(feature = "in-mem", feature = "mongodb"))]
+use crate::{Entity, Event};
+
+#[cfg(any(feature = "in-mem", feature = "mongodb"))]
+/// Runs a store operation, in a span with the entity's type name, ID and
+/// the operation's outcome, and counts and times it.
+pub(crate) async fn instrument<T, I: Debug + Sync + ?Sized>(
+    store: &'static str,
+    operation: &'static str,
+    type_name: &'static str,
+    id: Option<&I>,
+    op: impl Future<Output = Result<T, Box<dyn Error>>>,
+) -> Result<T, Box<dyn Error>> {
+    #[cfg(feature = "metrics")]
+    let started = std::time::Instant::now();
+
+    #[cfg(feature = "tracing")]
+    let result = {
+        use tracing::{field, Instrument};
+        let span = tracing::debug_span!(
+            "store_operation",
+            store,
+            operation,
+            entity = type_name,
+            id = id.map(field::debug),
+            outcome = field::Empty,
+            error = field::Empty,
+        );
+        let result = op.instrument(span.clone()).await;
+        match &result {
+            Ok(_) => span.record("outcome", "ok"),
+            Err(e) => span
+                .record("outcome", "error")
+                .record("error", field::display(e)),
+        };
+        result
+    };
+    #[cfg(not(feature = "tracing"))]
+    let result = {
+        let _ = id;
+        op.await
+    };
+
+    #[cfg(feature = "metrics")]
+    {
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        metrics::counter!(
+            "live_entity_operations_total",
+            "store" => store,
+            "entity" => type_name,
+            "operation" => operation,
+            "outcome" => outcome,
+        )
+        .increment(1);
+        metrics::histogram!(
+            "live_entity_operation_duration_seconds",
+            "store" => store,
+            "entity" => type_name,
+            "operation" => operation,
+        )
+        .record(started.elapsed().as_secs_f64());
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (store, operation, type_name);
+
+    result
+}
+
+#[cfg(feature = "in-mem")]
+/// Reports how many watches of a type a store has running.
+pub(crate) fn watchers(store: &'static str, type_name: &'static str, count: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!("live_entity_watchers", "store" => store, "entity" => type_name)
+        .set(count as f64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (store, type_name, count);
+}
+
+/// Counts the watches of a type running while it is held, for stores that
+/// can't count them otherwise.
+#[cfg(feature = "mongodb")]
+pub(crate) struct Watching {
+    #[cfg(feature = "metrics")]
+    gauge: metrics::Gauge,
+}
+
+#[cfg(feature = "mongodb")]
+impl Watching {
+    pub(crate) fn start(store: &'static str, type_name: &'static str) -> Self {
+        #[cfg(feature = "metrics")]
+        {
+            let gauge =
+                metrics::gauge!("live_entity_watchers", "store" => store, "entity" => type_name);
+            gauge.increment(1.0);
+            Self { gauge }
+        }
+        #[cfg(not(feature = "metrics"))]
+        {
+            let _ = (store, type_name);
+            Self {}
+        }
+    }
+}
+
+#[cfg(feature = "mongodb")]
+impl Drop for Watching {
+    fn drop(&mut self) {
+        #[cfg(feature = "metrics")]
+        self.gauge.decrement(1.0);
+    }
+}
+
+#[cfg(any(feature = "in-mem", feature = "mongodb"))]
+pub(crate) fn event_sent(store: &'static str, type_name: &'static str, kind: &'static str) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(
+        "live_entity_events_total",
+        "store" => store,
+        "entity" => type_name,
+        "kind" => kind,
+    )
+    .increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (store, type_name, kind);
+}
+
+#[cfg(any(feature = "in-mem", feature = "mongodb"))]
+pub(crate) fn event_kind<E: Entity>(event: &Event<E>) -> &'static str {
+    match event {
+        Event::Create(_) => "create",
+        Event::Update { .. } => "update",
+        Event::Delete(_) => "delete",
+    }
+}
+
+/// Reports that a watch fell behind its store and missed `missed` events.
+pub(crate) fn lagged(store: &'static str, type_name: &'static str, missed: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        store,
+        entity = type_name,
+        missed,
+        "Watch lagged behind the store"
+    );
+    #[cfg(feature = "metrics")]
+    metrics::counter!("live_entity_lagged_total", "store" => store, "entity" => type_name)
+        .increment(1);
+    #[cfg(not(feature = "tracing"))]
+    let _ = missed;
+    #[cfg(not(any(feature = "tracing", feature = "metrics")))]
+    let _ = (store, type_name);
+}
+
+/// Reports an error that a store carried on from rather than returning, like
+/// a snapshot that couldn't be saved.
+pub(crate) fn failed_in_background(
+    store: &'static str,
+    type_name: &'static str,
+    task: &'static str,
+    error: &dyn Error,
+) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(store, entity = type_name, task, error = %error, "Store task failed");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (store, type_name, task, error);
+}
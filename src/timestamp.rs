@@ -0,0 +1,147 @@
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Formatter;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A point in time with millisecond precision, as filled in by stores for
+/// `#[entity(created_at)]` and `#[entity(updated_at)]` fields.
+///
+/// It serializes as an extended JSON date, `{"$date": {"$numberLong": ...}}`,
+/// which MongoDB stores as a native date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(i64);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        SystemTime::now().into()
+    }
+
+    pub fn from_millis(millis: i64) -> Self {
+        Self(millis)
+    }
+
+    /// Milliseconds since the Unix epoch.
+    pub fn millis(&self) -> i64 {
+        self.0
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => Self(after.as_millis() as i64),
+            Err(before) => Self(-(before.duration().as_millis() as i64)),
+        }
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(timestamp: Timestamp) -> Self {
+        let offset = Duration::from_millis(timestamp.0.unsigned_abs());
+        if timestamp.0 >= 0 {
+            UNIX_EPOCH + offset
+        } else {
+            UNIX_EPOCH - offset
+        }
+    }
+}
+
+/// When a change was made and who made it, for stores to fill in the fields
+/// they maintain.
+#[derive(Debug, Clone)]
+pub struct Stamp {
+    pub at: Timestamp,
+    pub by: Option<String>,
+}
+
+impl Stamp {
+    /// A stamp for a change made now by `by`.
+    pub fn now(by: Option<&str>) -> Self {
+        Self {
+            at: Timestamp::now(),
+            by: by.map(str::to_owned),
+        }
+    }
+}
+
+const DATE: &str = "$date";
+const NUMBER_LONG: &str = "$numberLong";
+
+#[derive(Serialize)]
+struct NumberLong {
+    #[serde(rename = "$numberLong")]
+    millis: String,
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut date = serializer.serialize_struct(DATE, 1)?;
+        date.serialize_field(
+            DATE,
+            &NumberLong {
+                millis: self.0.to_string(),
+            },
+        )?;
+        date.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TimestampVisitor)
+    }
+}
+
+/// Reads the extended JSON forms of a date: milliseconds, either plain or
+/// as `{"$numberLong": ...}`, and, with MongoDB, RFC 3339 strings. A date
+/// can also be given as the milliseconds themselves.
+struct TimestampVisitor;
+
+impl<'de> Visitor<'de> for TimestampVisitor {
+    type Value = Timestamp;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("an extended JSON date")
+    }
+
+    fn visit_i64<E: de::Error>(self, millis: i64) -> Result<Timestamp, E> {
+        Ok(Timestamp(millis))
+    }
+
+    fn visit_u64<E: de::Error>(self, millis: u64) -> Result<Timestamp, E> {
+        i64::try_from(millis)
+            .map(Timestamp)
+            .map_err(|_| E::custom("timestamp out of range"))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Timestamp, E> {
+        parse_date_str(value).map_err(E::custom)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Timestamp, A::Error> {
+        let timestamp = match map.next_key::<String>()?.as_deref() {
+            Some(DATE) => map.next_value::<Timestamp>()?,
+            Some(NUMBER_LONG) => {
+                let millis = map.next_value::<String>()?;
+                Timestamp(millis.parse().map_err(de::Error::custom)?)
+            }
+            _ => return Err(de::Error::custom("expected $date or $numberLong")),
+        };
+        if map.next_key::<de::IgnoredAny>()?.is_some() {
+            return Err(de::Error::custom("unexpected field in extended JSON date"));
+        }
+        Ok(timestamp)
+    }
+}
+
+#[cfg(feature = "mongodb")]
+fn parse_date_str(value: &str) -> Result<Timestamp, Box<dyn std::error::Error>> {
+    let date = mongodb::bson::DateTime::parse_rfc3339_str(value)?;
+    Ok(Timestamp(date.timestamp_millis()))
+}
+
+#[cfg(not(feature = "mongodb"))]
+fn parse_date_str(value: &str) -> Result<Timestamp, String> {
+    Err(format!("can't parse date {}", value))
+}
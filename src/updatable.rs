@@ -11,3 +11,36 @@ impl<U: Clone> Updatable<Option<U>> for U {
         }
     }
 }
+
+/// `Diff` types can work out the update that turns one value into another.
+pub trait Diff<U>: Updatable<U> {
+    /// Get an update that only changes what differs between `old` and `new`.
+    fn diff(old: &Self, new: &Self) -> U;
+}
+
+impl<U: Clone + PartialEq> Diff<Option<U>> for U {
+    fn diff(old: &Self, new: &Self) -> Option<U> {
+        (old != new).then(|| new.clone())
+    }
+}
+
+/// Updates to `Composable` types can be merged together and undone.
+pub trait Composable<U>: Updatable<U> {
+    /// Combine two updates into one with the effect of applying `earlier`
+    /// and then `later`. Where that can't be expressed as a single update,
+    /// `later` wins.
+    fn merge(earlier: U, later: U) -> U;
+    /// Get the update that undoes `update`, where `self` is the value from
+    /// before `update` was applied.
+    fn inverse(&self, update: &U) -> U;
+}
+
+impl<U: Clone> Composable<Option<U>> for U {
+    fn merge(earlier: Option<U>, later: Option<U>) -> Option<U> {
+        later.or(earlier)
+    }
+
+    fn inverse(&self, update: &Option<U>) -> Option<U> {
+        update.as_ref().map(|_| self.clone())
+    }
+}
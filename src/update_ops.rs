@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::AddAssign;
+
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Composable, Diff, Updatable};
+
+/// An update to an `Option` field, which can leave it alone, set it, or
+/// clear it to `None`. `Clear` serializes as null, and `Unchanged` is meant
+/// to be skipped when serializing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Patch<T> {
+    /// Leave the value as it is.
+    #[default]
+    Unchanged,
+    /// Set the value to `Some`.
+    Set(T),
+    /// Set the value to `None`.
+    Clear,
+}
+
+impl<T> Patch<T> {
+    pub fn is_unchanged(&self) -> bool {
+        matches!(self, Self::Unchanged)
+    }
+}
+
+impl<T> From<Option<T>> for Patch<T> {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(val) => Self::Set(val),
+            None => Self::Clear,
+        }
+    }
+}
+
+/// An update to a `Vec` field. `Push` and `Pull` only touch the given
+/// items, so concurrent writers don't clobber each other.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "ListUpdateRepr<T>")]
+pub enum ListUpdate<T> {
+    /// Replace the whole list.
+    Set(Vec<T>),
+    /// Append the items to the end of the list.
+    Push(Vec<T>),
+    /// Remove every occurrence of the items from the list.
+    Pull(Vec<T>),
+}
+
+/// An update to a `HashMap` field. `Insert` and `Remove` only touch the
+/// given keys, so concurrent writers don't clobber each other.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "MapUpdateRepr<K, V>")]
+pub enum MapUpdate<K: Eq + Hash, V> {
+    /// Replace the whole map.
+    Set(HashMap<K, V>),
+    /// Insert the entries, overwriting any existing values for their keys.
+    Insert(HashMap<K, V>),
+    /// Remove the keys from the map.
+    Remove(Vec<K>),
+}
+
+/// An update to a numeric field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(from = "NumericUpdateRepr<T>")]
+pub enum NumericUpdate<T> {
+    /// Replace the value.
+    Set(T),
+    /// Add to the current value. Use a negative amount to decrement.
+    Increment(T),
+}
+
+impl<T: Clone> Updatable<Patch<T>> for Option<T> {
+    fn update(&mut self, with: &Patch<T>) {
+        match with {
+            Patch::Unchanged => {}
+            Patch::Set(val) => match self {
+                Some(current) => current.clone_from(val),
+                None => *self = Some(val.clone()),
+            },
+            Patch::Clear => *self = None,
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> Updatable<Option<ListUpdate<T>>> for Vec<T> {
+    fn update(&mut self, with: &Option<ListUpdate<T>>) {
+        match with {
+            Some(ListUpdate::Set(items)) => self.clone_from(items),
+            Some(ListUpdate::Push(items)) => self.extend(items.iter().cloned()),
+            Some(ListUpdate::Pull(items)) => self.retain(|i| !items.contains(i)),
+            None => {}
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Updatable<Option<MapUpdate<K, V>>> for HashMap<K, V> {
+    fn update(&mut self, with: &Option<MapUpdate<K, V>>) {
+        match with {
+            Some(MapUpdate::Set(entries)) => self.clone_from(entries),
+            Some(MapUpdate::Insert(entries)) => {
+                self.extend(entries.iter().map(|(k, v)| (k.clone(), v.clone())))
+            }
+            Some(MapUpdate::Remove(keys)) => {
+                for k in keys {
+                    self.remove(k);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+impl<T: Clone + AddAssign> Updatable<Option<NumericUpdate<T>>> for T {
+    fn update(&mut self, with: &Option<NumericUpdate<T>>) {
+        match with {
+            Some(NumericUpdate::Set(val)) => self.clone_from(val),
+            Some(NumericUpdate::Increment(by)) => *self += by.clone(),
+            None => {}
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> Diff<Patch<T>> for Option<T> {
+    fn diff(old: &Self, new: &Self) -> Patch<T> {
+        match new {
+            _ if old == new => Patch::Unchanged,
+            Some(val) => Patch::Set(val.clone()),
+            None => Patch::Clear,
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> Diff<Option<ListUpdate<T>>> for Vec<T> {
+    fn diff(old: &Self, new: &Self) -> Option<ListUpdate<T>> {
+        if old == new {
+            None
+        } else if new.starts_with(old) {
+            Some(ListUpdate::Push(new[old.len()..].to_vec()))
+        } else {
+            Some(ListUpdate::Set(new.clone()))
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone + PartialEq> Diff<Option<MapUpdate<K, V>>> for HashMap<K, V> {
+    fn diff(old: &Self, new: &Self) -> Option<MapUpdate<K, V>> {
+        let inserted: HashMap<K, V> = new
+            .iter()
+            .filter(|(k, v)| old.get(k) != Some(v))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        let removed: Vec<K> = old.keys().filter(|k| !new.contains_key(k)).cloned().collect();
+        match (inserted.is_empty(), removed.is_empty()) {
+            (true, true) => None,
+            (false, true) => Some(MapUpdate::Insert(inserted)),
+            (true, false) => Some(MapUpdate::Remove(removed)),
+            (false, false) => Some(MapUpdate::Set(new.clone())),
+        }
+    }
+}
+
+impl<T: Clone + PartialEq + AddAssign> Diff<Option<NumericUpdate<T>>> for T {
+    fn diff(old: &Self, new: &Self) -> Option<NumericUpdate<T>> {
+        (old != new).then(|| NumericUpdate::Set(new.clone()))
+    }
+}
+
+impl<T: Clone> Composable<Patch<T>> for Option<T> {
+    fn merge(earlier: Patch<T>, later: Patch<T>) -> Patch<T> {
+        match later {
+            Patch::Unchanged => earlier,
+            later => later,
+        }
+    }
+
+    fn inverse(&self, update: &Patch<T>) -> Patch<T> {
+        match update {
+            Patch::Unchanged => Patch::Unchanged,
+            _ => Patch::from(self.clone()),
+        }
+    }
+}
+
+// Merges two optional operations, using `merge` only if both are present.
+fn merge_ops<U>(earlier: Option<U>, later: Option<U>, merge: impl FnOnce(U, U) -> U) -> Option<U> {
+    match (earlier, later) {
+        (Some(earlier), Some(later)) => Some(merge(earlier, later)),
+        (earlier, later) => later.or(earlier),
+    }
+}
+
+impl<T: Clone + PartialEq> Composable<Option<ListUpdate<T>>> for Vec<T> {
+    fn merge(
+        earlier: Option<ListUpdate<T>>,
+        later: Option<ListUpdate<T>>,
+    ) -> Option<ListUpdate<T>> {
+        merge_ops(earlier, later, |earlier, later| match (earlier, later) {
+            (ListUpdate::Push(mut items), ListUpdate::Push(more)) => {
+                items.extend(more);
+                ListUpdate::Push(items)
+            }
+            (ListUpdate::Pull(mut items), ListUpdate::Pull(more)) => {
+                items.extend(more);
+                ListUpdate::Pull(items)
+            }
+            (ListUpdate::Set(mut items), later) => {
+                items.update(&Some(later));
+                ListUpdate::Set(items)
+            }
+            (_, later) => later,
+        })
+    }
+
+    fn inverse(&self, update: &Option<ListUpdate<T>>) -> Option<ListUpdate<T>> {
+        update.as_ref().map(|_| ListUpdate::Set(self.clone()))
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Composable<Option<MapUpdate<K, V>>> for HashMap<K, V> {
+    fn merge(
+        earlier: Option<MapUpdate<K, V>>,
+        later: Option<MapUpdate<K, V>>,
+    ) -> Option<MapUpdate<K, V>> {
+        merge_ops(earlier, later, |earlier, later| match (earlier, later) {
+            (MapUpdate::Insert(mut entries), MapUpdate::Insert(more)) => {
+                entries.extend(more);
+                MapUpdate::Insert(entries)
+            }
+            (MapUpdate::Remove(mut keys), MapUpdate::Remove(more)) => {
+                keys.extend(more);
+                MapUpdate::Remove(keys)
+            }
+            (MapUpdate::Set(mut entries), later) => {
+                entries.update(&Some(later));
+                MapUpdate::Set(entries)
+            }
+            (_, later) => later,
+        })
+    }
+
+    fn inverse(&self, update: &Option<MapUpdate<K, V>>) -> Option<MapUpdate<K, V>> {
+        let prior = |keys: Vec<&K>| -> HashMap<K, V> {
+            keys.into_iter()
+                .map(|k| (k.clone(), self[k].clone()))
+                .collect()
+        };
+        Some(match update.as_ref()? {
+            MapUpdate::Set(_) => MapUpdate::Set(self.clone()),
+            // Removing a key that wasn't there did nothing, so only the keys
+            // that were there need to come back.
+            MapUpdate::Remove(keys) => {
+                MapUpdate::Insert(prior(keys.iter().filter(|k| self.contains_key(k)).collect()))
+            }
+            MapUpdate::Insert(entries) => {
+                let (present, absent): (Vec<&K>, Vec<&K>) =
+                    entries.keys().partition(|k| self.contains_key(k));
+                if absent.is_empty() {
+                    MapUpdate::Insert(prior(present))
+                } else if present.is_empty() {
+                    MapUpdate::Remove(absent.into_iter().cloned().collect())
+                } else {
+                    MapUpdate::Set(self.clone())
+                }
+            }
+        })
+    }
+}
+
+impl<T: Clone + AddAssign> Composable<Option<NumericUpdate<T>>> for T {
+    fn merge(
+        earlier: Option<NumericUpdate<T>>,
+        later: Option<NumericUpdate<T>>,
+    ) -> Option<NumericUpdate<T>> {
+        merge_ops(earlier, later, |earlier, later| match (earlier, later) {
+            (NumericUpdate::Increment(mut by), NumericUpdate::Increment(more)) => {
+                by += more;
+                NumericUpdate::Increment(by)
+            }
+            (NumericUpdate::Set(mut val), NumericUpdate::Increment(by)) => {
+                val += by;
+                NumericUpdate::Set(val)
+            }
+            (_, later) => later,
+        })
+    }
+
+    fn inverse(&self, update: &Option<NumericUpdate<T>>) -> Option<NumericUpdate<T>> {
+        update.as_ref().map(|_| NumericUpdate::Set(self.clone()))
+    }
+}
+
+// `Set` serializes as the bare value so these updates look like a plain
+// replacement on the wire. The other operations serialize as a single-entry
+// map keyed by the matching MongoDB update operator.
+fn serialize_op<S: Serializer, T: Serialize + ?Sized>(
+    serializer: S,
+    op: &'static str,
+    val: &T,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(1))?;
+    map.serialize_entry(op, val)?;
+    map.end()
+}
+
+impl<T: Serialize> Serialize for Patch<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Set(val) => serializer.serialize_some(val),
+            Self::Unchanged | Self::Clear => serializer.serialize_none(),
+        }
+    }
+}
+
+// A missing value is `Unchanged` through `#[serde(default)]` on the field,
+// so anything that is present is either a new value or null.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Option::<T>::deserialize(deserializer).map(Self::from)
+    }
+}
+
+impl<T: Serialize> Serialize for ListUpdate<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Set(items) => items.serialize(serializer),
+            Self::Push(items) => serialize_op(serializer, "$push", items),
+            Self::Pull(items) => serialize_op(serializer, "$pull", items),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Serialize, V: Serialize> Serialize for MapUpdate<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Set(entries) => entries.serialize(serializer),
+            Self::Insert(entries) => serialize_op(serializer, "$insert", entries),
+            Self::Remove(keys) => serialize_op(serializer, "$remove", keys),
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for NumericUpdate<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Set(val) => val.serialize(serializer),
+            Self::Increment(by) => serialize_op(serializer, "$inc", by),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ListUpdateRepr<T> {
+    Push {
+        #[serde(rename = "$push")]
+        items: Vec<T>,
+    },
+    Pull {
+        #[serde(rename = "$pull")]
+        items: Vec<T>,
+    },
+    Set(Vec<T>),
+}
+
+impl<T> From<ListUpdateRepr<T>> for ListUpdate<T> {
+    fn from(value: ListUpdateRepr<T>) -> Self {
+        match value {
+            ListUpdateRepr::Push { items } => Self::Push(items),
+            ListUpdateRepr::Pull { items } => Self::Pull(items),
+            ListUpdateRepr::Set(items) => Self::Set(items),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MapUpdateRepr<K: Eq + Hash, V> {
+    Insert {
+        #[serde(rename = "$insert")]
+        entries: HashMap<K, V>,
+    },
+    Remove {
+        #[serde(rename = "$remove")]
+        keys: Vec<K>,
+    },
+    Set(HashMap<K, V>),
+}
+
+impl<K: Eq + Hash, V> From<MapUpdateRepr<K, V>> for MapUpdate<K, V> {
+    fn from(value: MapUpdateRepr<K, V>) -> Self {
+        match value {
+            MapUpdateRepr::Insert { entries } => Self::Insert(entries),
+            MapUpdateRepr::Remove { keys } => Self::Remove(keys),
+            MapUpdateRepr::Set(entries) => Self::Set(entries),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumericUpdateRepr<T> {
+    Increment {
+        #[serde(rename = "$inc")]
+        by: T,
+    },
+    Set(T),
+}
+
+impl<T> From<NumericUpdateRepr<T>> for NumericUpdate<T> {
+    fn from(value: NumericUpdateRepr<T>) -> Self {
+        match value {
+            NumericUpdateRepr::Increment { by } => Self::Increment(by),
+            NumericUpdateRepr::Set(val) => Self::Set(val),
+        }
+    }
+}
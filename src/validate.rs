@@ -0,0 +1,116 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    error::Error,
+    fmt::Formatter,
+};
+
+use crate::Entity;
+
+#[cfg(feature = "regex")]
+pub use regex;
+
+/// `Validate` entities can check their own data, and updates to it, before
+/// they are stored. It can be derived with `#[derive(Validate)]`.
+pub trait Validate: Entity {
+    fn validate(&self) -> Result<(), ValidationError>;
+    /// Check `update` against `self`, the entity it would apply to. By
+    /// default the updated entity only has to be valid itself.
+    fn validate_update(&self, update: &Self::Update) -> Result<(), ValidationError> {
+        let mut updated = self.clone();
+        updated.update(update);
+        updated.validate()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// The field that failed validation, if it was a single one.
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            field: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn for_field(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: Some(field.into()),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.field {
+            Some(field) => f.write_fmt(format_args!("Invalid {}: {}", field, self.message)),
+            None => f.write_fmt(format_args!("Invalid entity: {}", self.message)),
+        }
+    }
+}
+impl Error for ValidationError {}
+
+/// Values with a length, for the `length` and `non_empty` validation rules.
+/// Strings are measured in characters.
+pub trait HasLength {
+    fn length(&self) -> usize;
+}
+
+impl HasLength for str {
+    fn length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl HasLength for String {
+    fn length(&self) -> usize {
+        self.as_str().length()
+    }
+}
+
+impl<T> HasLength for [T] {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> HasLength for Vec<T> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> HasLength for VecDeque<T> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<K, V, S> HasLength for HashMap<K, V, S> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T, S> HasLength for HashSet<T, S> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<K, V> HasLength for BTreeMap<K, V> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> HasLength for BTreeSet<T> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
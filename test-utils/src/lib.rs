@@ -1 +1,2 @@
 pub mod storage_test;
+pub mod wait;
@@ -1,6 +1,15 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use live_entity::{derive::{Entity, Updatable}, Event, SingletonEvent, Store, Singleton};
+use live_entity::{
+    derive::{Entity, Singleton},
+    history::HistorySink,
+    undo::UndoStore,
+    Event, SingletonEvent, Store,
+};
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast::channel;
 
@@ -143,6 +152,17 @@ pub async fn test_storage_functions<T: Store + 'static>(storage: Arc<T>) {
     assert!(stock_items
         .iter()
         .any(|si| si.item_name == propane_accessory_id));
+    let first_page = storage
+        .get_page::<StockItem>(None, 1)
+        .await
+        .expect("Failed to get first page of stock items.");
+    assert_eq!(1, first_page.len());
+    let rest = storage
+        .get_page::<StockItem>(Some(&first_page[0].item_name), 10)
+        .await
+        .expect("Failed to get next page of stock items.");
+    assert_eq!(1, rest.len());
+    assert_ne!(first_page[0].item_name, rest[0].item_name);
     let retrieved_propane_accessory = storage
         .get_by_id(&propane_accessory_id)
         .await
@@ -176,18 +196,13 @@ pub async fn test_storage_functions<T: Store + 'static>(storage: Arc<T>) {
     storage.delete_all::<StockItem>().await.unwrap();
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Updatable, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, Singleton, Eq, PartialEq)]
+#[singleton(type_name = "pages", id = "home")]
 struct HomePage {
     header: String,
     body: String
 }
 
-impl Singleton for HomePage {
-    type Update = UpdatedHomePage;
-    const TYPE_NAME: &'static str = "pages";
-    const ENTITY_ID: &'static str = "home";
-}
-
 pub async fn test_storage_singleton_functions<T: Store + 'static>(storage: Arc<T>) {
     let hp = HomePage { header: "Welcome!".to_owned(), body: "Please stay long enough to see some ads".to_owned() };
     storage.create_singleton(&hp).await.expect("Failed to create singleton.");
@@ -222,4 +237,572 @@ pub async fn test_storage_singleton_functions<T: Store + 'static>(storage: Arc<T
     if storage.get_singleton::<HomePage>().await.is_ok() {
         panic!("Singleton was not deleted.")
     }
-}
\ No newline at end of file
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "carts"]
+struct Cart {
+    #[entity_id]
+    #[serde(rename = "_id")]
+    owner: String,
+    #[entity(list)]
+    items: Vec<String>,
+    #[entity(map)]
+    coupons: HashMap<String, u8>,
+    #[entity(increment)]
+    checkouts: i32,
+    note: Option<String>,
+}
+
+pub async fn test_storage_update_operations<T: Store + 'static>(storage: Arc<T>) {
+    storage
+        .delete_all::<Cart>()
+        .await
+        .expect("Failed to clear carts table");
+
+    let (tx, mut rx) = channel(1);
+    let clone_store = storage.clone();
+    tokio::spawn(async move {
+        clone_store
+            .watch::<Cart>(tx)
+            .await
+            .expect("Failed to initiate Cart watch.");
+    });
+    tokio::task::yield_now().await;
+
+    let owner = "Bobby Hill".to_owned();
+    let mut cart = Cart {
+        owner: owner.clone(),
+        items: vec!["Hot dog".to_owned(), "Ketchup".to_owned()],
+        coupons: HashMap::from([("FIRST".to_owned(), 10)]),
+        checkouts: 0,
+        note: None,
+    };
+    storage.create(&cart).await.expect("Failed to create cart.");
+    rx.recv().await.expect("Error receiving cart create event.");
+
+    let updates = [
+        UpdatedCart::default()
+            .push_items("Mustard".to_owned())
+            .increment_checkouts(2)
+            .note(Some("Leave by the door".to_owned())),
+        UpdatedCart::default()
+            .pull_items("Ketchup".to_owned())
+            .insert_coupons("SECOND".to_owned(), 5),
+        UpdatedCart::default().clear_note(),
+        UpdatedCart::default()
+            .remove_coupons("FIRST".to_owned())
+            .increment_checkouts(-1),
+    ];
+    for update in updates {
+        storage
+            .update::<Cart>(&owner, &update)
+            .await
+            .expect("Error applying update operations to cart.");
+        match rx.recv().await.expect("Error receiving cart update event.") {
+            Event::Update { id, update } => {
+                assert_eq!(owner, id);
+                live_entity::Updatable::update(&mut cart, &update);
+            }
+            _ => panic!("Received wrong type of event on cart update."),
+        }
+    }
+
+    let stored = storage
+        .get_by_id::<Cart>(&owner)
+        .await
+        .expect("Failed to retrieve cart.");
+    let expected_items = vec!["Hot dog".to_owned(), "Mustard".to_owned()];
+    let expected_coupons = HashMap::from([("SECOND".to_owned(), 5)]);
+    assert_eq!(expected_items, stored.items);
+    assert_eq!(expected_coupons, stored.coupons);
+    assert_eq!(1, stored.checkouts);
+    assert_eq!(None, stored.note);
+    assert_eq!(expected_items, cart.items);
+    assert_eq!(expected_coupons, cart.coupons);
+    assert_eq!(1, cart.checkouts);
+    assert_eq!(None, cart.note);
+
+    let mut edited = stored.clone();
+    edited.items.push("Buns".to_owned());
+    edited.note = Some("Ring twice".to_owned());
+    storage.save(&edited).await.expect("Failed to save cart.");
+    match rx.recv().await.expect("Error receiving cart save event.") {
+        Event::Update { id, update } => {
+            assert_eq!(owner, id);
+            assert_eq!(None, update.checkouts);
+            assert_eq!(None, update.coupons);
+            live_entity::Updatable::update(&mut cart, &update);
+        }
+        _ => panic!("Received wrong type of event on cart save."),
+    }
+    let stored = storage
+        .get_by_id::<Cart>(&owner)
+        .await
+        .expect("Failed to retrieve saved cart.");
+    assert_eq!(edited.items, stored.items);
+    assert_eq!(edited.note, stored.note);
+    assert_eq!(edited.items, cart.items);
+    assert_eq!(edited.note, cart.note);
+
+    storage.delete_all::<Cart>().await.unwrap();
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "memos"]
+#[entity(soft_delete)]
+struct Memo {
+    #[entity_id]
+    #[serde(rename = "_id")]
+    title: String,
+    body: String,
+}
+
+pub async fn test_storage_soft_delete<T: Store + 'static>(storage: Arc<T>) {
+    let reminder_id = "Reminder".to_owned();
+    let agenda_id = "Agenda".to_owned();
+    for id in [&reminder_id, &agenda_id] {
+        storage
+            .purge::<Memo>(id)
+            .await
+            .expect("Failed to clear memos table");
+    }
+
+    let (tx, mut rx) = channel(1);
+    let clone_store = storage.clone();
+    tokio::spawn(async move {
+        clone_store
+            .watch::<Memo>(tx)
+            .await
+            .expect("Failed to initiate Memo watch.");
+    });
+    tokio::task::yield_now().await;
+
+    let reminder = Memo {
+        title: reminder_id.clone(),
+        body: "Buy propane".to_owned(),
+    };
+    let agenda = Memo {
+        title: agenda_id.clone(),
+        body: "Alley".to_owned(),
+    };
+    for memo in [&reminder, &agenda] {
+        storage.create(memo).await.expect("Failed to create memo.");
+        rx.recv().await.expect("Error receiving memo create event.");
+    }
+
+    storage
+        .delete_by_id::<Memo>(&reminder_id)
+        .await
+        .expect("Failed to delete memo.");
+    match rx.recv().await.expect("Error receiving memo delete event.") {
+        Event::Delete(id) => assert_eq!(reminder_id, id),
+        _ => panic!("Received wrong type of event on memo delete."),
+    }
+    if storage.get_by_id::<Memo>(&reminder_id).await.is_ok() {
+        panic!("Deleted memo was not hidden.")
+    }
+    let memos = storage
+        .get_all::<Memo>()
+        .await
+        .expect("Failed to get memos.");
+    assert_eq!(
+        vec![agenda_id.clone()],
+        memos.into_iter().map(|m| m.title).collect::<Vec<_>>()
+    );
+    let deleted = storage
+        .get_deleted::<Memo>()
+        .await
+        .expect("Failed to get deleted memos.");
+    assert_eq!(
+        vec![reminder_id.clone()],
+        deleted.into_iter().map(|m| m.title).collect::<Vec<_>>()
+    );
+
+    storage
+        .restore::<Memo>(&reminder_id)
+        .await
+        .expect("Failed to restore memo.");
+    match rx.recv().await.expect("Error receiving memo restore event.") {
+        Event::Create(memo) => assert_eq!(reminder.body, memo.body),
+        _ => panic!("Received wrong type of event on memo restore."),
+    }
+    let restored = storage
+        .get_by_id::<Memo>(&reminder_id)
+        .await
+        .expect("Failed to retrieve restored memo.");
+    assert_eq!(reminder.body, restored.body);
+    assert!(storage
+        .get_deleted::<Memo>()
+        .await
+        .expect("Failed to get deleted memos.")
+        .is_empty());
+    if storage.restore::<Memo>(&reminder_id).await.is_ok() {
+        panic!("Restored a memo that wasn't deleted.")
+    }
+
+    // Purging a deleted memo isn't news to watchers, so the next event is
+    // for purging the live one.
+    storage
+        .delete_by_id::<Memo>(&reminder_id)
+        .await
+        .expect("Failed to delete memo.");
+    rx.recv().await.expect("Error receiving memo delete event.");
+    storage
+        .purge::<Memo>(&reminder_id)
+        .await
+        .expect("Failed to purge deleted memo.");
+    storage
+        .purge::<Memo>(&agenda_id)
+        .await
+        .expect("Failed to purge memo.");
+    match rx.recv().await.expect("Error receiving memo purge event.") {
+        Event::Delete(id) => assert_eq!(agenda_id, id),
+        _ => panic!("Received wrong type of event on memo purge."),
+    }
+    assert!(storage
+        .get_deleted::<Memo>()
+        .await
+        .expect("Failed to get deleted memos.")
+        .is_empty());
+    if storage.restore::<Memo>(&reminder_id).await.is_ok() {
+        panic!("Restored a purged memo.")
+    }
+
+    // A deleted memo makes way for a new one with its ID.
+    storage.create(&agenda).await.expect("Failed to create memo.");
+    rx.recv().await.expect("Error receiving memo create event.");
+    storage
+        .delete_by_id::<Memo>(&agenda_id)
+        .await
+        .expect("Failed to delete memo.");
+    rx.recv().await.expect("Error receiving memo delete event.");
+    let replacement = Memo {
+        title: agenda_id.clone(),
+        body: "Bowling".to_owned(),
+    };
+    storage
+        .create(&replacement)
+        .await
+        .expect("Failed to recreate deleted memo.");
+    match rx.recv().await.expect("Error receiving memo create event.") {
+        Event::Create(memo) => assert_eq!(replacement.body, memo.body),
+        _ => panic!("Received wrong type of event on memo recreation."),
+    }
+    assert!(storage
+        .get_deleted::<Memo>()
+        .await
+        .expect("Failed to get deleted memos.")
+        .is_empty());
+
+    storage.purge::<Memo>(&agenda_id).await.unwrap();
+}
+
+/// Checks that watchers are told of a live memo expiring, but not of a
+/// deleted one, which they were told of already. `within` is how long the
+/// store may take to remove expired entities.
+pub async fn test_storage_soft_delete_expiry<T: Store + 'static>(storage: Arc<T>, within: Duration) {
+    let live_id = "Expiring".to_owned();
+    let deleted_id = "Expiring deleted".to_owned();
+    let marker_id = "Marker".to_owned();
+    for id in [&live_id, &deleted_id, &marker_id] {
+        storage
+            .purge::<Memo>(id)
+            .await
+            .expect("Failed to clear memos table");
+    }
+
+    let (tx, mut rx) = channel(4);
+    let clone_store = storage.clone();
+    tokio::spawn(async move {
+        clone_store
+            .watch::<Memo>(tx)
+            .await
+            .expect("Failed to initiate Memo watch.");
+    });
+    tokio::task::yield_now().await;
+
+    for id in [&live_id, &deleted_id] {
+        let memo = Memo {
+            title: id.clone(),
+            body: "Soon gone".to_owned(),
+        };
+        storage
+            .create_with_ttl(&memo, Duration::from_secs(1))
+            .await
+            .expect("Failed to create expiring memo.");
+        rx.recv().await.expect("Error receiving memo create event.");
+    }
+    storage
+        .delete_by_id::<Memo>(&deleted_id)
+        .await
+        .expect("Failed to delete memo.");
+    rx.recv().await.expect("Error receiving memo delete event.");
+
+    match tokio::time::timeout(within, rx.recv())
+        .await
+        .expect("Memo never expired.")
+        .expect("Error receiving memo expiry event.")
+    {
+        Event::Delete(id) => assert_eq!(live_id, id),
+        _ => panic!("Received wrong type of event on memo expiry."),
+    }
+    tokio::time::timeout(within, async {
+        while !storage
+            .get_deleted::<Memo>()
+            .await
+            .expect("Failed to get deleted memos.")
+            .is_empty()
+        {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Deleted memo never expired.");
+
+    // Had the deleted memo's expiry been sent, it would come first.
+    let marker = Memo {
+        title: marker_id.clone(),
+        body: "Still here".to_owned(),
+    };
+    storage.create(&marker).await.expect("Failed to create memo.");
+    match rx.recv().await.expect("Error receiving memo create event.") {
+        Event::Create(memo) => assert_eq!(marker_id, memo.title),
+        _ => panic!("Watchers were told of a deleted memo expiring."),
+    }
+    storage.purge::<Memo>(&marker_id).await.unwrap();
+}
+
+pub async fn test_storage_undo_redo<T: Store + 'static>(storage: Arc<T>) {
+    storage
+        .delete_all::<Cart>()
+        .await
+        .expect("Failed to clear carts table");
+
+    let (tx, mut rx) = channel(1);
+    let clone_store = storage.clone();
+    tokio::spawn(async move {
+        clone_store
+            .watch::<Cart>(tx)
+            .await
+            .expect("Failed to initiate Cart watch.");
+    });
+    tokio::task::yield_now().await;
+
+    let owner = "Luanne Platter".to_owned();
+    let original = Cart {
+        owner: owner.clone(),
+        items: vec!["Puppets".to_owned()],
+        coupons: HashMap::new(),
+        checkouts: 3,
+        note: None,
+    };
+    storage.create(&original).await.expect("Failed to create cart.");
+    rx.recv().await.expect("Error receiving cart create event.");
+
+    let history = UndoStore::new(storage.clone(), 2).undoable::<Cart>();
+    let updates = [
+        UpdatedCart::default().push_items("Glue".to_owned()),
+        UpdatedCart::default()
+            .insert_coupons("BEAUTY".to_owned(), 20)
+            .increment_checkouts(1),
+        UpdatedCart::default().note(Some("For the Manger Babies".to_owned())),
+    ];
+    for update in &updates {
+        history
+            .update::<Cart>(&owner, update)
+            .await
+            .expect("Error updating cart through history.");
+        rx.recv().await.expect("Error receiving cart update event.");
+    }
+
+    let mut cart = storage.get_by_id::<Cart>(&owner).await.unwrap();
+    for _ in 0..2 {
+        assert!(history.undo::<Cart>(&owner).await.expect("Error undoing cart update."));
+        match rx.recv().await.expect("Error receiving cart undo event.") {
+            Event::Update { id, update } => {
+                assert_eq!(owner, id);
+                live_entity::Updatable::update(&mut cart, &update);
+            }
+            _ => panic!("Received wrong type of event on cart undo."),
+        }
+    }
+    // Only two updates are kept, so the first one can't be undone.
+    assert!(!history.undo::<Cart>(&owner).await.unwrap());
+    let stored = storage.get_by_id::<Cart>(&owner).await.unwrap();
+    let expected_items = vec!["Puppets".to_owned(), "Glue".to_owned()];
+    assert_eq!(expected_items, stored.items);
+    assert!(stored.coupons.is_empty());
+    assert_eq!(3, stored.checkouts);
+    assert_eq!(None, stored.note);
+    assert_eq!(expected_items, cart.items);
+    assert!(cart.coupons.is_empty());
+    assert_eq!(3, cart.checkouts);
+    assert_eq!(None, cart.note);
+
+    assert!(history.redo::<Cart>(&owner).await.expect("Error redoing cart update."));
+    rx.recv().await.expect("Error receiving cart redo event.");
+    let stored = storage.get_by_id::<Cart>(&owner).await.unwrap();
+    assert_eq!(Some(&20), stored.coupons.get("BEAUTY"));
+    assert_eq!(4, stored.checkouts);
+
+    // A new update forgets what could have been redone.
+    history
+        .update::<Cart>(&owner, &UpdatedCart::default().pull_items("Glue".to_owned()))
+        .await
+        .unwrap();
+    rx.recv().await.unwrap();
+    assert!(!history.redo::<Cart>(&owner).await.unwrap());
+    assert!(history.undo::<Cart>(&owner).await.unwrap());
+    rx.recv().await.unwrap();
+    let stored = storage.get_by_id::<Cart>(&owner).await.unwrap();
+    assert_eq!(expected_items, stored.items);
+
+    // Saving goes through the history's own update, so it's recorded too.
+    let mut edited = stored.clone();
+    edited.note = Some("Sell them to Peggy".to_owned());
+    history.save(&edited).await.expect("Failed to save cart through history.");
+    rx.recv().await.unwrap();
+    assert!(history.undo::<Cart>(&owner).await.unwrap());
+    rx.recv().await.unwrap();
+    assert_eq!(None, storage.get_by_id::<Cart>(&owner).await.unwrap().note);
+
+    // A recreated cart starts with nothing to undo.
+    history.delete_by_id::<Cart>(&owner).await.unwrap();
+    rx.recv().await.unwrap();
+    history.create(&original).await.unwrap();
+    rx.recv().await.unwrap();
+    assert!(!history.undo::<Cart>(&owner).await.unwrap());
+
+    storage.delete_all::<Cart>().await.unwrap();
+}
+
+pub async fn test_history_sink<T: Store + 'static, H: HistorySink>(storage: Arc<T>, sink: Arc<H>) {
+    storage
+        .delete_all::<Employee>()
+        .await
+        .expect("Failed to clear employees table");
+
+    let dale_id = "Dale Gribble".to_owned();
+    let dale = Employee {
+        name: dale_id.clone(),
+        age: 40,
+        children: 1,
+    };
+    // Recorded with explicit times so point-in-time reads are deterministic.
+    let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+    let events = [
+        (at(100), Event::Create(dale.clone())),
+        (
+            at(200),
+            Event::Update {
+                id: dale_id.clone(),
+                update: UpdatedEmployee::default().age(41),
+            },
+        ),
+        (
+            at(300),
+            Event::Update {
+                id: dale_id.clone(),
+                update: UpdatedEmployee::default().children(0),
+            },
+        ),
+        (at(400), Event::Delete(dale_id.clone())),
+    ];
+    let mut last_sequence = 0;
+    for (timestamp, event) in &events {
+        let record = sink
+            .record(event, *timestamp)
+            .await
+            .expect("Failed to record event.");
+        assert!(record.sequence > last_sequence);
+        last_sequence = record.sequence;
+    }
+
+    let history = sink
+        .get_history::<Employee>(&dale_id)
+        .await
+        .expect("Failed to get history.");
+    assert!(history.len() >= events.len());
+    let history = &history[history.len() - events.len()..];
+    assert!(matches!(history[0].event, Event::Create(_)));
+    assert!(matches!(history[3].event, Event::Delete(_)));
+    assert_eq!(at(200), history[1].timestamp);
+
+    assert!(sink.get_by_id_at::<Employee>(&dale_id, at(50)).await.is_err());
+    let created = sink.get_by_id_at::<Employee>(&dale_id, at(150)).await.unwrap();
+    assert_eq!(40, created.age);
+    let aged = sink.get_by_id_at::<Employee>(&dale_id, at(250)).await.unwrap();
+    assert_eq!(41, aged.age);
+    assert_eq!(1, aged.children);
+    let latest = sink.get_by_id_at::<Employee>(&dale_id, at(300)).await.unwrap();
+    assert_eq!(41, latest.age);
+    assert_eq!(0, latest.children);
+    assert!(sink.get_by_id_at::<Employee>(&dale_id, at(400)).await.is_err());
+
+    // Events from a watched store are recorded as they arrive.
+    let bill_id = "Bill Dauterive".to_owned();
+    let (tx, rx) = channel(4);
+    let watch_store = storage.clone();
+    tokio::spawn(async move {
+        watch_store
+            .watch::<Employee>(tx)
+            .await
+            .expect("Failed to initiate Employee watch.");
+    });
+    let record_sink = sink.clone();
+    tokio::spawn(async move {
+        record_sink
+            .record_all::<Employee>(rx)
+            .await
+            .expect("Failed to record employee events.");
+    });
+    tokio::task::yield_now().await;
+    let bill = Employee {
+        name: bill_id.clone(),
+        age: 42,
+        children: 0,
+    };
+    storage.create(&bill).await.expect("Failed to create employee.");
+    storage
+        .update::<Employee>(&bill_id, &UpdatedEmployee::default().age(43))
+        .await
+        .expect("Error updating employee.");
+    let mut recorded = Vec::new();
+    for _ in 0..100 {
+        recorded = sink.get_history::<Employee>(&bill_id).await.unwrap();
+        if recorded.len() >= 2 {
+            break;
+        }
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(2, recorded.len());
+    let now = sink
+        .get_by_id_at::<Employee>(&bill_id, SystemTime::now())
+        .await
+        .expect("Failed to rebuild employee from history.");
+    assert_eq!(43, now.age);
+
+    // Falling behind the channel loses the events missed, not the rest.
+    let boomhauer_id = "Boomhauer".to_owned();
+    let (tx, rx) = channel(1);
+    tx.send(Event::Create(Employee {
+        name: boomhauer_id.clone(),
+        age: 39,
+        children: 0,
+    }))
+    .unwrap();
+    tx.send(Event::Update {
+        id: boomhauer_id.clone(),
+        update: UpdatedEmployee::default().age(40),
+    })
+    .unwrap();
+    drop(tx);
+    sink.record_all::<Employee>(rx)
+        .await
+        .expect("Failed to record events after lagging.");
+    let recorded = sink.get_history::<Employee>(&boomhauer_id).await.unwrap();
+    assert!(matches!(recorded.last().unwrap().event, Event::Update { .. }));
+
+    storage.delete_all::<Employee>().await.unwrap();
+}
@@ -0,0 +1,13 @@
+use std::future::Future;
+
+/// Yields to other tasks until `check` passes, for changes that reach a
+/// store, cache or recorder in the background. Panics if it never does.
+pub async fn eventually<F: Future<Output = bool>>(mut check: impl FnMut() -> F) {
+    for _ in 0..100 {
+        if check().await {
+            return;
+        }
+        tokio::task::yield_now().await;
+    }
+    panic!("The check never passed.");
+}
@@ -0,0 +1,159 @@
+#![cfg(feature = "in-mem")]
+
+use std::sync::Arc;
+
+use live_entity::authorized::{Action, AuthorizedStore, Policy, UnauthorizedError};
+use live_entity::derive::Entity;
+use live_entity::in_mem::InMemStore;
+use live_entity::{Entity, Event, Store};
+use serde::{Deserialize, Serialize};
+use test_utils::storage_test::{
+    test_storage_functions, test_storage_singleton_functions, test_storage_soft_delete,
+    test_storage_update_operations,
+};
+use tokio::sync::broadcast;
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "diaries"]
+struct Diary {
+    #[entity_id]
+    owner: String,
+    entry: String,
+}
+
+fn diary(owner: &str, entry: &str) -> Diary {
+    Diary {
+        owner: owner.to_owned(),
+        entry: entry.to_owned(),
+    }
+}
+
+enum Role {
+    Admin,
+    Auditor,
+    Author(String),
+}
+
+/// Admins can do anything, auditors can read and watch anything, and
+/// authors can only touch their own diaries.
+struct Roles;
+
+impl Policy for Roles {
+    type Principal = Role;
+
+    fn allows<E: Entity>(&self, principal: &Role, action: Action, id: Option<&E::ID>) -> bool {
+        match principal {
+            Role::Admin => true,
+            Role::Auditor => matches!(action, Action::Read | Action::Watch),
+            Role::Author(name) => {
+                let id = id.map(|id| serde_json::to_value(id).unwrap());
+                E::TYPE_NAME == "diaries"
+                    && (action == Action::Watch || id.is_some_and(|id| id == name.as_str()))
+            }
+        }
+    }
+}
+
+fn unauthorized(err: Box<dyn std::error::Error>) -> (Action, Option<String>) {
+    let err = err.downcast_ref::<UnauthorizedError>().unwrap();
+    (err.action, err.id.clone())
+}
+
+#[tokio::test]
+async fn test_authorized_store() {
+    let inner = Arc::new(InMemStore::new(8));
+    let storage = Arc::new(AuthorizedStore::new(inner, Arc::new(Roles), Role::Admin));
+    test_storage_functions(storage.clone()).await;
+    test_storage_singleton_functions(storage.clone()).await;
+    test_storage_update_operations(storage.clone()).await;
+    test_storage_soft_delete(storage).await;
+}
+
+#[tokio::test]
+async fn test_authorized_store_enforces_policy() {
+    let inner = Arc::new(InMemStore::new(8));
+    let admin = AuthorizedStore::new(inner.clone(), Arc::new(Roles), Role::Admin);
+    let ann = admin.for_principal(Role::Author("ann".to_owned()));
+    let auditor = admin.for_principal(Role::Auditor);
+
+    ann.create(&diary("ann", "Dear diary")).await.unwrap();
+    admin.create(&diary("bob", "Dear diary")).await.unwrap();
+    let err = ann.create(&diary("bob", "Ha")).await.unwrap_err();
+    assert_eq!(
+        (Action::Create, Some("\"bob\"".to_owned())),
+        unauthorized(err)
+    );
+    let update = UpdatedDiary::default().entry("Ha".to_owned());
+    assert!(ann
+        .update::<Diary>(&"bob".to_owned(), &update)
+        .await
+        .is_err());
+    assert!(auditor
+        .update::<Diary>(&"bob".to_owned(), &update)
+        .await
+        .is_err());
+    assert!(ann.get_by_id::<Diary>(&"bob".to_owned()).await.is_err());
+    assert_eq!(
+        "Dear diary",
+        inner
+            .get_by_id::<Diary>(&"bob".to_owned())
+            .await
+            .unwrap()
+            .entry
+    );
+
+    assert_eq!(
+        vec![diary("ann", "")],
+        ann.get_all::<Diary>().await.unwrap()
+    );
+    assert_eq!(2, auditor.get_all::<Diary>().await.unwrap().len());
+
+    let err = ann.delete_all::<Diary>().await.unwrap_err();
+    assert_eq!((Action::Delete, None), unauthorized(err));
+    assert!(auditor
+        .delete_by_id::<Diary>(&"ann".to_owned())
+        .await
+        .is_err());
+    ann.delete_by_id::<Diary>(&"ann".to_owned()).await.unwrap();
+    assert_eq!(1, inner.get_all::<Diary>().await.unwrap().len());
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "secrets"]
+struct Secret {
+    #[entity_id]
+    id: u32,
+}
+
+#[tokio::test]
+async fn test_authorized_watch_filters_events() {
+    let inner = Arc::new(InMemStore::new(8));
+    let admin = AuthorizedStore::new(inner.clone(), Arc::new(Roles), Role::Admin);
+    let ann = Arc::new(admin.for_principal(Role::Author("ann".to_owned())));
+
+    let (tx, _rx) = broadcast::channel::<Event<Secret>>(8);
+    let err = ann.watch::<Secret>(tx).await.unwrap_err();
+    assert_eq!((Action::Watch, None), unauthorized(err));
+
+    let (tx, mut rx) = broadcast::channel(8);
+    let watching = ann.clone();
+    tokio::spawn(async move { watching.watch::<Diary>(tx).await.unwrap() });
+    tokio::task::yield_now().await;
+
+    admin.create(&diary("bob", "Dear diary")).await.unwrap();
+    admin.create(&diary("ann", "Dear diary")).await.unwrap();
+    let update = UpdatedDiary::default().entry("Today".to_owned());
+    admin
+        .update::<Diary>(&"bob".to_owned(), &update)
+        .await
+        .unwrap();
+    admin
+        .delete_by_id::<Diary>(&"ann".to_owned())
+        .await
+        .unwrap();
+
+    assert!(matches!(rx.recv().await.unwrap(), Event::Create(d) if d.owner == "ann"));
+    assert!(matches!(rx.recv().await.unwrap(), Event::Delete(id) if id == "ann"));
+    tokio::task::yield_now().await;
+    assert!(rx.try_recv().is_err());
+}
@@ -0,0 +1,282 @@
+#![cfg(feature = "in-mem")]
+
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use live_entity::bidirectional::{sync_bidirectional, ConflictPolicy};
+use live_entity::derive::Entity;
+use live_entity::in_mem::InMemStore;
+use live_entity::layer::{Call, Intercepted, Interceptor};
+use live_entity::{ActingAs, Entity, Store, Timestamp};
+use serde::{Deserialize, Serialize};
+use test_utils::wait::eventually;
+use tokio::sync::broadcast::{channel, error::TryRecvError};
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "recipes"]
+struct Recipe {
+    #[entity_id]
+    name: String,
+    #[entity(list)]
+    steps: Vec<String>,
+    edited_at: u64,
+}
+
+fn recipe(name: &str, steps: &[&str], edited_at: u64) -> Recipe {
+    Recipe {
+        name: name.to_owned(),
+        steps: steps.iter().map(|s| s.to_string()).collect(),
+        edited_at,
+    }
+}
+
+async fn steps(store: &impl Store, name: &str) -> Option<Vec<String>> {
+    let recipe = store.get_by_id::<Recipe>(&name.to_owned()).await.ok()?;
+    Some(recipe.steps)
+}
+
+/// Gives the sync time to echo its own writes, if it were going to.
+async fn quiet() {
+    tokio::time::sleep(Duration::from_millis(10)).await;
+}
+
+#[tokio::test]
+async fn test_sync_bidirectional() {
+    let a = Arc::new(InMemStore::new(16));
+    let b = Arc::new(InMemStore::new(16));
+    a.create(&recipe("Brisket", &["Smoke"], 1)).await.unwrap();
+    b.create(&recipe("Chili", &["Simmer"], 1)).await.unwrap();
+    a.create(&recipe("Pie", &["Bake"], 5)).await.unwrap();
+    b.create(&recipe("Pie", &["Bake", "Cool"], 9)).await.unwrap();
+
+    let policy = ConflictPolicy::LastWriterWins(Box::new(|r: &Recipe| {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(r.edited_at)
+    }));
+    let (sync_a, sync_b) = (a.clone(), b.clone());
+    let sync = tokio::spawn(async move {
+        sync_bidirectional::<Recipe, _, _>(sync_a, sync_b, policy, 16)
+            .await
+            .unwrap()
+    });
+    eventually(|| async {
+        a.get_all::<Recipe>().await.unwrap().len() == 3
+            && b.get_all::<Recipe>().await.unwrap().len() == 3
+            && steps(&*a, "Pie").await.is_some_and(|steps| steps.len() == 2)
+    })
+    .await;
+
+    // Each side gets what only the other had, and the later pie wins.
+    for store in [&a, &b] {
+        assert_eq!(3, store.get_all::<Recipe>().await.unwrap().len());
+    }
+    let pie = a.get_by_id::<Recipe>(&"Pie".to_owned()).await.unwrap();
+    assert_eq!(vec!["Bake", "Cool"], pie.steps);
+
+    let (tx, mut a_events) = channel(16);
+    let watched = a.clone();
+    tokio::spawn(async move { watched.watch::<Recipe>(tx).await.unwrap() });
+    tokio::task::yield_now().await;
+
+    b.update::<Recipe>(
+        &"Chili".to_owned(),
+        &UpdatedRecipe::default().push_steps("Serve".to_owned()),
+    )
+    .await
+    .unwrap();
+    eventually(|| async { steps(&*a, "Chili").await.is_some_and(|steps| steps.len() == 2) }).await;
+    quiet().await;
+    let chili = a.get_by_id::<Recipe>(&"Chili".to_owned()).await.unwrap();
+    assert_eq!(vec!["Simmer", "Serve"], chili.steps);
+    // The sync's write to `a` comes back as an event, but isn't sent back
+    // to `b` to bounce around forever.
+    assert!(a_events.try_recv().is_ok());
+    assert!(matches!(a_events.try_recv(), Err(TryRecvError::Empty)));
+    let chili = b.get_by_id::<Recipe>(&"Chili".to_owned()).await.unwrap();
+    assert_eq!(vec!["Simmer", "Serve"], chili.steps);
+
+    a.update::<Recipe>(&"Brisket".to_owned(), &UpdatedRecipe::default().edited_at(2))
+        .await
+        .unwrap();
+    eventually(|| async {
+        b.get_by_id::<Recipe>(&"Brisket".to_owned()).await.unwrap().edited_at == 2
+    })
+    .await;
+
+    b.delete_by_id::<Recipe>(&"Pie".to_owned()).await.unwrap();
+    eventually(|| async { steps(&*a, "Pie").await.is_none() }).await;
+    assert_eq!(2, a.get_all::<Recipe>().await.unwrap().len());
+
+    sync.abort();
+}
+
+#[tokio::test]
+async fn test_sync_bidirectional_custom_resolver() {
+    let a = Arc::new(InMemStore::new(16));
+    let b = Arc::new(InMemStore::new(16));
+    a.create(&recipe("Stew", &["Chop"], 1)).await.unwrap();
+    b.create(&recipe("Stew", &["Boil"], 1)).await.unwrap();
+
+    let policy = ConflictPolicy::Resolve(Box::new(|a: &Recipe, b: &Recipe| {
+        let mut merged = a.clone();
+        merged.steps.extend(b.steps.iter().cloned());
+        merged
+    }));
+    let (sync_a, sync_b) = (a.clone(), b.clone());
+    let sync = tokio::spawn(async move {
+        sync_bidirectional::<Recipe, _, _>(sync_a, sync_b, policy, 16)
+            .await
+            .unwrap()
+    });
+    eventually(|| async {
+        steps(&*a, "Stew").await == steps(&*b, "Stew").await
+    })
+    .await;
+
+    for store in [&a, &b] {
+        let stew = store.get_by_id::<Recipe>(&"Stew".to_owned()).await.unwrap();
+        assert_eq!(vec!["Chop", "Boil"], stew.steps);
+    }
+    sync.abort();
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "memos"]
+struct Memo {
+    #[entity_id]
+    id: u32,
+    text: String,
+    #[entity(created_at)]
+    created_at: Timestamp,
+    #[entity(updated_at)]
+    updated_at: Timestamp,
+    #[entity(modified_by)]
+    edited_by: Option<String>,
+}
+
+#[tokio::test]
+async fn test_sync_bidirectional_timestamped_entities_settle() {
+    let a = Arc::new(InMemStore::new(16).acting_as("alice"));
+    let b = Arc::new(InMemStore::new(16).acting_as("bob"));
+    let memo = Memo {
+        id: 1,
+        text: "Draft".to_owned(),
+        created_at: Timestamp::from_millis(0),
+        updated_at: Timestamp::from_millis(0),
+        edited_by: None,
+    };
+    a.create(&memo).await.unwrap();
+
+    let (sync_a, sync_b) = (a.clone(), b.clone());
+    let sync = tokio::spawn(async move {
+        sync_bidirectional::<Memo, _, _>(sync_a, sync_b, ConflictPolicy::PreferFirst, 16)
+            .await
+            .unwrap()
+    });
+    eventually(|| async { b.get_by_id::<Memo>(&1).await.is_ok() }).await;
+    let created = a.get_by_id::<Memo>(&1).await.unwrap();
+    assert_eq!(created.created_at, b.get_by_id::<Memo>(&1).await.unwrap().created_at);
+
+    let (a_tx, mut a_events) = channel(16);
+    let (b_tx, mut b_events) = channel(16);
+    let (watched_a, watched_b) = (a.clone(), b.clone());
+    tokio::spawn(async move { watched_a.watch::<Memo>(a_tx).await.unwrap() });
+    tokio::spawn(async move { watched_b.watch::<Memo>(b_tx).await.unwrap() });
+    tokio::task::yield_now().await;
+
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    b.update::<Memo>(&1, &UpdatedMemo::default().text("Final".to_owned()))
+        .await
+        .unwrap();
+    eventually(|| async { a.get_by_id::<Memo>(&1).await.unwrap().text == "Final" }).await;
+    quiet().await;
+
+    // Each side stamps its own `updated_at` and `edited_by`, which isn't
+    // taken as another change to send back.
+    let mut count = 0;
+    while a_events.try_recv().is_ok() || b_events.try_recv().is_ok() {
+        count += 1;
+    }
+    assert_eq!(2, count);
+    quiet().await;
+    assert!(matches!(a_events.try_recv(), Err(TryRecvError::Empty)));
+    assert!(matches!(b_events.try_recv(), Err(TryRecvError::Empty)));
+    sync.abort();
+}
+
+/// Fails every call for the recipe named "Burnt".
+struct Burnt;
+
+#[async_trait]
+impl Interceptor for Burnt {
+    async fn before<E: Entity>(&self, call: &mut Call<E>) -> Result<(), Box<dyn Error>> {
+        let id = match call {
+            Call::Create(entity) | Call::InsertRaw { entity, .. } => entity.get_id().into_owned(),
+            Call::Update { id, .. } | Call::DeleteById(id) | Call::GetById(id) => id.clone(),
+            Call::GetExpiry(id) => id.clone(),
+            _ => return Ok(()),
+        };
+        if serde_json::to_value(&id)? == "Burnt" {
+            return Err("Store unavailable".into());
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_sync_bidirectional_carries_on_after_failures() {
+    let a = Arc::new(Intercepted::new(Arc::new(InMemStore::new(16)), Burnt));
+    let b = Arc::new(InMemStore::new(16));
+    b.create(&recipe("Burnt", &["Forget"], 1)).await.unwrap();
+    b.create(&recipe("Soup", &["Simmer"], 1)).await.unwrap();
+
+    let (sync_a, sync_b) = (a.clone(), b.clone());
+    let sync = tokio::spawn(async move {
+        sync_bidirectional::<Recipe, _, _>(sync_a, sync_b, ConflictPolicy::PreferFirst, 16)
+            .await
+            .unwrap()
+    });
+    eventually(|| async { steps(&**a.inner(), "Soup").await.is_some() }).await;
+
+    // The recipe that couldn't be synced doesn't hold up the others.
+    assert!(!sync.is_finished());
+    let recipes = a.inner().get_all::<Recipe>().await.unwrap();
+    assert_eq!(vec!["Soup"], recipes.iter().map(|r| r.name.as_str()).collect::<Vec<_>>());
+
+    b.create(&recipe("Salad", &["Toss"], 1)).await.unwrap();
+    b.update::<Recipe>(&"Burnt".to_owned(), &UpdatedRecipe::default().edited_at(2))
+        .await
+        .unwrap();
+    eventually(|| async { steps(&**a.inner(), "Salad").await.is_some() }).await;
+    quiet().await;
+    assert!(!sync.is_finished());
+    assert_eq!(2, a.inner().get_all::<Recipe>().await.unwrap().len());
+    // Nor is it taken to have been synced, so it isn't deleted from `b`.
+    assert!(b.get_by_id::<Recipe>(&"Burnt".to_owned()).await.is_ok());
+    sync.abort();
+}
+
+/// Refuses to be watched.
+struct Unwatchable;
+
+#[async_trait]
+impl Interceptor for Unwatchable {
+    async fn before<E: Entity>(&self, call: &mut Call<E>) -> Result<(), Box<dyn Error>> {
+        match call {
+            Call::Watch(_) => Err("Watch refused".into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_sync_bidirectional_stops_when_either_watch_ends() {
+    let a = Arc::new(InMemStore::new(16));
+    let b = Arc::new(Intercepted::new(Arc::new(InMemStore::new(16)), Unwatchable));
+    let sync = sync_bidirectional::<Recipe, _, _>(a, b, ConflictPolicy::PreferFirst, 16);
+    tokio::time::timeout(Duration::from_secs(1), sync)
+        .await
+        .expect("Sync kept running after a watch ended.")
+        .unwrap();
+}
@@ -0,0 +1,183 @@
+#![cfg(feature = "in-mem")]
+
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use live_entity::cached::CachedStore;
+use live_entity::derive::Entity;
+use live_entity::in_mem::InMemStore;
+use live_entity::{Event, Store};
+use serde::{Deserialize, Serialize};
+use test_utils::storage_test::{
+    test_storage_functions, test_storage_singleton_functions, test_storage_soft_delete,
+    test_storage_undo_redo, test_storage_update_operations,
+};
+use test_utils::wait::eventually;
+use tokio::sync::broadcast::Sender;
+
+#[tokio::test]
+async fn test_cached_store() {
+    let storage = Arc::new(CachedStore::new(Arc::new(InMemStore::new(8)), 8));
+    test_storage_functions(storage.clone()).await;
+    test_storage_singleton_functions(storage.clone()).await;
+    test_storage_update_operations(storage.clone()).await;
+    test_storage_soft_delete(storage.clone()).await;
+    test_storage_undo_redo(storage).await;
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "lawns"]
+struct Lawn {
+    #[entity_id]
+    address: String,
+    #[entity(increment)]
+    mowings: u32,
+}
+
+#[tokio::test]
+async fn test_cached_store_follows_inner_store() {
+    let inner = Arc::new(InMemStore::new(8));
+    let rainey = "84 Rainey Street".to_owned();
+    inner
+        .create(&Lawn {
+            address: rainey.clone(),
+            mowings: 1,
+        })
+        .await
+        .unwrap();
+
+    let cached = CachedStore::new(inner.clone(), 8);
+    cached.warm_up::<Lawn>().await.unwrap();
+    tokio::task::yield_now().await;
+    assert_eq!(1, cached.get_all::<Lawn>().await.unwrap().len());
+
+    // Writes made to the inner store directly still reach the cache.
+    inner
+        .update::<Lawn>(&rainey, &UpdatedLawn::default().increment_mowings(2))
+        .await
+        .unwrap();
+    eventually(|| async { cached.get_by_id::<Lawn>(&rainey).await.unwrap().mowings == 3 }).await;
+
+    let alley = "The Alley".to_owned();
+    inner
+        .create(&Lawn {
+            address: alley.clone(),
+            mowings: 0,
+        })
+        .await
+        .unwrap();
+    eventually(|| async { cached.get_all::<Lawn>().await.unwrap().len() == 2 }).await;
+
+    inner.delete_by_id::<Lawn>(&rainey).await.unwrap();
+    eventually(|| async { cached.get_all::<Lawn>().await.unwrap().len() == 1 }).await;
+    assert!(cached.get_by_id::<Lawn>(&rainey).await.is_err());
+
+    // A miss falls back to the inner store.
+    let cold = CachedStore::new(inner.clone(), 8);
+    assert_eq!(0, cold.get_by_id::<Lawn>(&alley).await.unwrap().mowings);
+}
+
+#[tokio::test]
+async fn test_cached_store_warm_up_misses_nothing() {
+    let inner = Arc::new(InMemStore::new(8));
+    let cached = CachedStore::new(inner.clone(), 8);
+    cached.warm_up::<Lawn>().await.unwrap();
+
+    // Written before the watch task has had a turn to run, so it's only seen
+    // if warming up waited for the watch to start.
+    inner
+        .create(&Lawn {
+            address: "Arlen Park".to_owned(),
+            mowings: 0,
+        })
+        .await
+        .unwrap();
+    eventually(|| async { cached.get_all::<Lawn>().await.unwrap().len() == 1 }).await;
+}
+
+/// Fails the first watch made of it.
+struct FailingWatchStore {
+    inner: InMemStore,
+    failed: AtomicBool,
+}
+
+#[derive(Debug)]
+struct WatchError;
+impl std::fmt::Display for WatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Watch failed.")
+    }
+}
+impl Error for WatchError {}
+
+#[async_trait]
+impl Store for FailingWatchStore {
+    async fn create<E: live_entity::Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
+        self.inner.create(entity).await
+    }
+
+    async fn update<E: live_entity::Entity>(
+        &self,
+        id: &E::ID,
+        update: &E::Update,
+    ) -> Result<(), Box<dyn Error>> {
+        self.inner.update::<E>(id, update).await
+    }
+
+    async fn delete_all<E: live_entity::Entity>(&self) -> Result<(), Box<dyn Error>> {
+        self.inner.delete_all::<E>().await
+    }
+
+    async fn delete_by_id<E: live_entity::Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.inner.delete_by_id::<E>(id).await
+    }
+
+    async fn get_all<E: live_entity::Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        self.inner.get_all().await
+    }
+
+    async fn get_by_id<E: live_entity::Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
+        self.inner.get_by_id(id).await
+    }
+
+    async fn watch<E: live_entity::Entity>(
+        &self,
+        channel: Sender<Event<E>>,
+    ) -> Result<(), Box<dyn Error>> {
+        if !self.failed.swap(true, Ordering::SeqCst) {
+            return Err(WatchError.into());
+        }
+        self.inner.watch(channel).await
+    }
+}
+
+#[tokio::test]
+async fn test_cached_store_watches_again_after_watch_ends() {
+    let inner = Arc::new(FailingWatchStore {
+        inner: InMemStore::new(8),
+        failed: AtomicBool::new(false),
+    });
+    let cached = CachedStore::new(inner.clone(), 8);
+    cached.warm_up::<Lawn>().await.unwrap();
+    assert!(cached.get_all::<Lawn>().await.unwrap().is_empty());
+
+    // The first watch failed, so the cache can't have heard of this, and
+    // mustn't keep serving what it had.
+    inner
+        .create(&Lawn {
+            address: "Arlen Park".to_owned(),
+            mowings: 0,
+        })
+        .await
+        .unwrap();
+    eventually(|| async { cached.get_all::<Lawn>().await.unwrap().len() == 1 }).await;
+
+    // Watched again, so changes reach the cache once more.
+    inner
+        .update::<Lawn>(&"Arlen Park".to_owned(), &UpdatedLawn::default().increment_mowings(1))
+        .await
+        .unwrap();
+    eventually(|| async { cached.get_all::<Lawn>().await.unwrap()[0].mowings == 1 }).await;
+}
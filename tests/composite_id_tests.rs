@@ -0,0 +1,140 @@
+#![cfg(feature = "in-mem")]
+
+use std::sync::Arc;
+
+use live_entity::derive::Entity;
+use live_entity::in_mem::InMemStore;
+use live_entity::validating::ValidatingStore;
+use live_entity::{Event, IdPrefixError, Store};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "stock"]
+struct Stock {
+    #[entity_id]
+    tenant: String,
+    #[entity_id]
+    warehouse: String,
+    #[entity_id]
+    sku: u32,
+    count: u32,
+}
+
+fn stock(tenant: &str, warehouse: &str, sku: u32, count: u32) -> Stock {
+    Stock {
+        tenant: tenant.to_owned(),
+        warehouse: warehouse.to_owned(),
+        sku,
+        count,
+    }
+}
+
+fn id(tenant: &str, warehouse: &str, sku: u32) -> StockId {
+    StockId {
+        tenant: tenant.to_owned(),
+        warehouse: warehouse.to_owned(),
+        sku,
+    }
+}
+
+async fn stocked_store() -> InMemStore {
+    let store = InMemStore::new(100);
+    store.create(&stock("acme", "north", 1, 5)).await.unwrap();
+    store.create(&stock("acme", "north", 2, 0)).await.unwrap();
+    store.create(&stock("acme", "south", 1, 7)).await.unwrap();
+    store.create(&stock("globex", "north", 1, 2)).await.unwrap();
+    store
+}
+
+fn counts(mut found: Vec<Stock>) -> Vec<u32> {
+    found.sort_by_key(|s| (s.warehouse.clone(), s.sku));
+    found.iter().map(|s| s.count).collect()
+}
+
+#[tokio::test]
+async fn test_composite_ids() {
+    let store = stocked_store().await;
+    let found = store
+        .get_by_id::<Stock>(&id("acme", "south", 1))
+        .await
+        .unwrap();
+    assert_eq!(7, found.count);
+
+    let update = UpdatedStock::default().count(6);
+    store
+        .update::<Stock>(&id("acme", "south", 1), &update)
+        .await
+        .unwrap();
+    let found = store
+        .get_by_id::<Stock>(&id("acme", "south", 1))
+        .await
+        .unwrap();
+    assert_eq!(6, found.count);
+
+    store
+        .delete_by_id::<Stock>(&id("acme", "north", 2))
+        .await
+        .unwrap();
+    assert_eq!(3, store.get_all::<Stock>().await.unwrap().len());
+    assert!(store
+        .get_by_id::<Stock>(&id("acme", "north", 2))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_get_by_id_prefix() {
+    let store = stocked_store().await;
+    let acme = store.get_by_id_prefix::<Stock, _>(&"acme").await.unwrap();
+    assert_eq!(vec![5, 0, 7], counts(acme));
+    let north = store
+        .get_by_id_prefix::<Stock, _>(&("acme", "north"))
+        .await
+        .unwrap();
+    assert_eq!(vec![5, 0], counts(north));
+    let one = store
+        .get_by_id_prefix::<Stock, _>(&("acme", "north", 2))
+        .await
+        .unwrap();
+    assert_eq!(vec![0], counts(one));
+    let none = store
+        .get_by_id_prefix::<Stock, _>(&("initech",))
+        .await
+        .unwrap();
+    assert!(none.is_empty());
+
+    let err = store
+        .get_by_id_prefix::<Stock, _>(&("acme", "north", 2, 0))
+        .await
+        .unwrap_err();
+    let err = err.downcast_ref::<IdPrefixError>().unwrap();
+    assert_eq!((3, 4), (err.fields, err.given));
+}
+
+#[tokio::test]
+async fn test_get_by_id_prefix_through_wrappers() {
+    let store = ValidatingStore::new(Arc::new(stocked_store().await));
+    let globex = store.get_by_id_prefix::<Stock, _>(&"globex").await.unwrap();
+    assert_eq!(vec![2], counts(globex));
+}
+
+#[tokio::test]
+async fn test_watch_composite_ids() {
+    let store = Arc::new(InMemStore::new(100));
+    let (sender, mut receiver) = broadcast::channel::<Event<Stock>>(16);
+    let watching = store.clone();
+    let watch = tokio::spawn(async move { watching.watch(sender).await.unwrap() });
+    tokio::task::yield_now().await;
+
+    store.create(&stock("acme", "north", 1, 5)).await.unwrap();
+    store
+        .delete_by_id::<Stock>(&id("acme", "north", 1))
+        .await
+        .unwrap();
+    let created = receiver.recv().await.unwrap();
+    assert_eq!(id("acme", "north", 1), *created.id());
+    let deleted = receiver.recv().await.unwrap();
+    assert_eq!(id("acme", "north", 1), *deleted.id());
+    watch.abort();
+}
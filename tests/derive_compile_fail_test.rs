@@ -0,0 +1,7 @@
+#[test]
+fn test_derive_diagnostics() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+    #[cfg(feature = "regex")]
+    t.compile_fail("tests/ui/regex/*.rs");
+}
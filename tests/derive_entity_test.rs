@@ -22,7 +22,7 @@ fn test_derived_entity() {
         body: body.clone(),
     };
 
-    assert_eq!(&id, article.get_id());
+    assert_eq!(id, *article.get_id());
 
     let same_by_eq = Article {
         id,
@@ -37,3 +37,154 @@ fn test_derived_entity() {
     assert_eq!(new_title, article.title);
     assert_eq!(body, article.body)
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone, Entity)]
+#[entity_name = "boxes"]
+struct Crate<T> {
+    #[entity_id]
+    label: String,
+    contents: Vec<T>,
+}
+
+#[test]
+fn test_derived_generic_entity() {
+    fn type_name<E: Entity>(_: &E) -> &'static str {
+        E::TYPE_NAME
+    }
+
+    let mut apples = Crate {
+        label: "apples".to_owned(),
+        contents: vec![1u8, 2],
+    };
+    assert_eq!("boxes", type_name(&apples));
+    assert_eq!("apples", *apples.get_id());
+
+    apples.update(&UpdatedCrate::default().contents(vec![3]));
+    assert_eq!(vec![3], apples.contents);
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Entity)]
+#[entity_name = "tags"]
+struct Tag(#[entity_id] u32, String);
+
+#[test]
+fn test_derived_tuple_entity() {
+    let mut tag = Tag(7, "urgent".to_owned());
+    assert_eq!(7, *tag.get_id());
+
+    let update: UpdatedTag = Tag(7, "whenever".to_owned()).into();
+    tag.update(&update);
+    assert_eq!("whenever", tag.1);
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Entity)]
+#[entity_name = "accounts"]
+#[serde(rename_all = "camelCase")]
+#[entity(update_name = "AccountChange", update_derive(PartialEq))]
+struct Account {
+    #[entity_id]
+    #[serde(rename = "_id")]
+    account_id: u64,
+    display_name: String,
+    #[serde(rename = "mail")]
+    email_address: String,
+    #[entity(readonly)]
+    opened_on: String,
+}
+
+#[test]
+fn test_derived_entity_configuration() {
+    let mut account = Account {
+        account_id: 3,
+        display_name: "Dale".to_owned(),
+        email_address: "rusty@shackleford.net".to_owned(),
+        opened_on: "1997-01-12".to_owned(),
+    };
+    let update: AccountChange = AccountChange::default()
+        .display_name("Rusty Shackleford".to_owned())
+        .email_address("dale@gribble.net".to_owned());
+    assert_eq!(
+        update,
+        AccountChange::default()
+            .display_name("Rusty Shackleford".to_owned())
+            .email_address("dale@gribble.net".to_owned())
+    );
+
+    let json = serde_json::to_value(&update).unwrap();
+    assert_eq!(
+        serde_json::json!({ "displayName": "Rusty Shackleford", "mail": "dale@gribble.net" }),
+        json
+    );
+    let entity_json = serde_json::to_value(&account).unwrap();
+    assert_eq!("Dale", entity_json["displayName"]);
+    assert_eq!("rusty@shackleford.net", entity_json["mail"]);
+
+    account.update(&update);
+    assert_eq!("Rusty Shackleford", account.display_name);
+    assert_eq!("dale@gribble.net", account.email_address);
+    assert_eq!("1997-01-12", account.opened_on);
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Entity)]
+#[entity_name = "tickets"]
+struct Ticket {
+    #[entity_id(generate = "sequence")]
+    number: u32,
+    title: String,
+}
+
+#[test]
+fn test_derived_generated_id() {
+    use live_entity::{GeneratedId, IdGenerator};
+
+    let IdGenerator::Sequence(from_sequence) = Ticket::ID_GENERATOR else {
+        panic!("Expected a sequence.");
+    };
+    let mut ticket = Ticket {
+        number: 0,
+        title: "Printer on fire".to_owned(),
+    };
+    ticket.set_id(from_sequence(12).unwrap());
+    assert_eq!(12, *ticket.get_id());
+    assert!(from_sequence(u64::MAX).is_err());
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Entity)]
+#[entity_name = "stock"]
+struct Stock {
+    #[entity_id]
+    warehouse: String,
+    #[entity_id]
+    #[serde(rename = "SKU")]
+    sku: u32,
+    count: u32,
+}
+
+#[test]
+fn test_derived_composite_id() {
+    use live_entity::CompositeId;
+
+    let mut stock = Stock {
+        warehouse: "north".to_owned(),
+        sku: 12,
+        count: 3,
+    };
+    let id = StockId {
+        warehouse: "north".to_owned(),
+        sku: 12,
+    };
+    assert_eq!(id, *stock.get_id());
+    assert_eq!(&["warehouse", "SKU"], StockId::FIELDS);
+    assert_eq!(
+        serde_json::json!({ "warehouse": "north", "SKU": 12 }),
+        serde_json::to_value(&id).unwrap()
+    );
+
+    stock.update(&UpdatedStock::default().count(4));
+    assert_eq!(4, stock.count);
+    let elsewhere = Stock {
+        warehouse: "south".to_owned(),
+        ..stock.clone()
+    };
+    assert_ne!(stock, elsewhere);
+}
@@ -0,0 +1,26 @@
+use live_entity::derive::Singleton;
+use live_entity::{Singleton, Updatable};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Singleton)]
+#[singleton(type_name = "settings", id = "site")]
+struct SiteSettings {
+    title: String,
+    motd: Option<String>,
+}
+
+#[test]
+fn test_derived_singleton() {
+    assert_eq!("settings", <SiteSettings as Singleton>::TYPE_NAME);
+    assert_eq!("site", SiteSettings::ENTITY_ID);
+
+    let mut settings = SiteSettings {
+        title: "Strickland Propane".to_owned(),
+        motd: None,
+    };
+    let update: <SiteSettings as Singleton>::Update =
+        UpdatedSiteSettings::default().motd(Some("Taste the meat, not the heat.".to_owned()));
+    settings.update(&update);
+    assert_eq!("Strickland Propane", settings.title);
+    assert_eq!(Some("Taste the meat, not the heat.".to_owned()), settings.motd);
+}
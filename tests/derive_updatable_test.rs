@@ -1,5 +1,8 @@
+use std::collections::HashMap;
+
 use live_entity::derive::Updatable;
-use live_entity::Updatable;
+use live_entity::{Diff, ListUpdate, MapUpdate, NumericUpdate, Patch, Updatable};
+use serde::{Deserialize, Serialize};
 
 #[derive(Updatable)]
 struct Person {
@@ -18,3 +21,313 @@ fn test_derived_updatable() {
     assert_eq!("Leto", person.first_name);
     assert_eq!("Atreides", person.last_name);
 }
+
+#[derive(Updatable)]
+struct Playlist {
+    #[entity(list)]
+    songs: Vec<String>,
+    #[entity(map)]
+    ratings: HashMap<String, u8>,
+    #[entity(increment)]
+    plays: u32,
+}
+
+#[test]
+fn test_derived_update_operations() {
+    let mut playlist = Playlist {
+        songs: vec!["Sandstorm".to_owned(), "Darude".to_owned()],
+        ratings: HashMap::from([("Sandstorm".to_owned(), 5)]),
+        plays: 10,
+    };
+    let update = UpdatedPlaylist::default()
+        .push_songs("Children".to_owned())
+        .push_songs("Insomnia".to_owned())
+        .insert_ratings("Children".to_owned(), 4)
+        .increment_plays(3);
+    playlist.update(&update);
+    assert_eq!(
+        vec!["Sandstorm", "Darude", "Children", "Insomnia"],
+        playlist.songs
+    );
+    assert_eq!(Some(&4), playlist.ratings.get("Children"));
+    assert_eq!(13, playlist.plays);
+
+    let update = UpdatedPlaylist::default()
+        .pull_songs("Darude".to_owned())
+        .remove_ratings("Sandstorm".to_owned())
+        .plays(0);
+    playlist.update(&update);
+    assert_eq!(vec!["Sandstorm", "Children", "Insomnia"], playlist.songs);
+    assert!(!playlist.ratings.contains_key("Sandstorm"));
+    assert_eq!(0, playlist.plays);
+
+    let update = UpdatedPlaylist::default().songs(vec!["Silence".to_owned()]);
+    playlist.update(&update);
+    assert_eq!(vec!["Silence"], playlist.songs);
+}
+
+#[derive(Updatable)]
+struct Profile {
+    name: String,
+    nickname: Option<String>,
+}
+
+#[test]
+fn test_derived_patch_for_option() {
+    let mut profile = Profile {
+        name: "Robert".to_owned(),
+        nickname: None,
+    };
+    profile.update(&UpdatedProfile::default().nickname(Some("Bob".to_owned())));
+    assert_eq!(Some("Bob".to_owned()), profile.nickname);
+
+    profile.update(&UpdatedProfile::default().name("Bobby".to_owned()));
+    assert_eq!("Bobby", profile.name);
+    assert_eq!(Some("Bob".to_owned()), profile.nickname);
+
+    profile.update(&UpdatedProfile::default().clear_nickname());
+    assert_eq!(None, profile.nickname);
+}
+
+#[derive(Updatable)]
+struct Labeled<T: Clone>
+where
+    T: Default,
+{
+    label: String,
+    value: T,
+}
+
+#[test]
+fn test_derived_updatable_generic() {
+    let mut labeled = Labeled {
+        label: "answer".to_owned(),
+        value: 41u64,
+    };
+    labeled.update(&UpdatedLabeled::default().value(42));
+    assert_eq!("answer", labeled.label);
+    assert_eq!(42, labeled.value);
+}
+
+#[derive(Updatable)]
+struct Point(f32, f32, #[entity(list)] Vec<String>);
+
+#[test]
+fn test_derived_updatable_tuple_struct() {
+    let mut point = Point(1.0, 2.0, vec![]);
+    point.update(&UpdatedPoint::default()._1(5.0).push_2("origin".to_owned()));
+    assert_eq!(1.0, point.0);
+    assert_eq!(5.0, point.1);
+    assert_eq!(vec!["origin"], point.2);
+}
+
+#[derive(Updatable, Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Shape {
+    Circle { radius: f32 },
+    Rect(f32, f32),
+    Empty,
+}
+
+#[test]
+fn test_derived_updatable_enum() {
+    let mut shape = Shape::Circle { radius: 1.0 };
+    shape.update(&UpdatedShape::Circle { radius: Some(2.0) });
+    assert_eq!(Shape::Circle { radius: 2.0 }, shape);
+
+    shape.update(&UpdatedShape::Rect(Some(3.0), None));
+    assert_eq!(Shape::Circle { radius: 2.0 }, shape);
+
+    shape.update(&UpdatedShape::Replace(Shape::Rect(3.0, 4.0)));
+    assert_eq!(Shape::Rect(3.0, 4.0), shape);
+
+    shape.update(&UpdatedShape::Rect(None, Some(5.0)));
+    assert_eq!(Shape::Rect(3.0, 5.0), shape);
+
+    shape.update(&UpdatedShape::Unchanged);
+    assert_eq!(Shape::Rect(3.0, 5.0), shape);
+
+    shape.update(&UpdatedShape::Replace(Shape::Empty));
+    assert_eq!(Shape::Empty, shape);
+}
+
+#[derive(Updatable, Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Account {
+    Personal {
+        #[entity(readonly)]
+        opened: u32,
+        name: String,
+    },
+    Shared(#[entity(readonly)] u32, String),
+}
+
+#[test]
+fn test_derived_enum_with_readonly_fields() {
+    let mut account = Account::Personal {
+        opened: 1999,
+        name: "Hank".to_owned(),
+    };
+    account.update(&UpdatedAccount::Personal {
+        name: Some("Peggy".to_owned()),
+    });
+    assert_eq!(
+        Account::Personal {
+            opened: 1999,
+            name: "Peggy".to_owned()
+        },
+        account
+    );
+
+    let old = Account::Shared(2001, "Hills".to_owned());
+    let new = Account::Shared(2001, "Gribbles".to_owned());
+    let mut shared = old.clone();
+    let update: UpdatedAccount = Account::diff(&old, &new);
+    shared.update(&update);
+    assert_eq!(new, shared);
+}
+
+#[derive(Updatable, Clone)]
+struct Inventory {
+    owner: String,
+    note: Option<String>,
+    #[entity(list)]
+    items: Vec<String>,
+    #[entity(map)]
+    prices: HashMap<String, u32>,
+}
+
+#[test]
+fn test_derived_diff() {
+    let old = Inventory {
+        owner: "Buck".to_owned(),
+        note: Some("Megalo Mart".to_owned()),
+        items: vec!["Grill".to_owned()],
+        prices: HashMap::from([("Grill".to_owned(), 300), ("Tank".to_owned(), 30)]),
+    };
+    let mut new = old.clone();
+    new.note = None;
+    new.items.push("Spatula".to_owned());
+    new.prices.remove("Tank");
+
+    let update = Inventory::diff(&old, &new);
+    assert_eq!(None, update.owner);
+    assert_eq!(Patch::Clear, update.note);
+    assert_eq!(Some(ListUpdate::Push(vec!["Spatula".to_owned()])), update.items);
+    assert_eq!(Some(MapUpdate::Remove(vec!["Tank".to_owned()])), update.prices);
+
+    let mut patched = old.clone();
+    patched.update(&update);
+    assert_eq!(new.note, patched.note);
+    assert_eq!(new.items, patched.items);
+    assert_eq!(new.prices, patched.prices);
+
+    let unchanged = Inventory::diff(&new, &new);
+    assert_eq!(None, unchanged.owner);
+    assert_eq!(Patch::Unchanged, unchanged.note);
+    assert_eq!(None, unchanged.items);
+    assert_eq!(None, unchanged.prices);
+}
+
+#[test]
+fn test_derived_enum_diff() {
+    let circle = Shape::Circle { radius: 1.0 };
+    let bigger = Shape::Circle { radius: 2.0 };
+    let mut shape = circle.clone();
+    let update: UpdatedShape = Shape::diff(&circle, &bigger);
+    shape.update(&update);
+    assert_eq!(bigger, shape);
+
+    let rect = Shape::Rect(1.0, 2.0);
+    let update: UpdatedShape = Shape::diff(&bigger, &rect);
+    assert!(matches!(update, UpdatedShape::Replace(_)));
+    shape.update(&update);
+    assert_eq!(rect, shape);
+
+    let update: UpdatedShape = Shape::diff(&Shape::Empty, &Shape::Empty);
+    assert!(matches!(update, UpdatedShape::Unchanged));
+}
+
+#[test]
+fn test_derived_merge() {
+    let mut playlist = Playlist {
+        songs: vec!["Sandstorm".to_owned()],
+        ratings: HashMap::from([("Sandstorm".to_owned(), 5)]),
+        plays: 10,
+    };
+    let first = UpdatedPlaylist::default()
+        .push_songs("Children".to_owned())
+        .insert_ratings("Children".to_owned(), 4)
+        .increment_plays(3);
+    let second = UpdatedPlaylist::default()
+        .push_songs("Insomnia".to_owned())
+        .remove_ratings("Sandstorm".to_owned())
+        .increment_plays(2);
+
+    let merged = first.merge(second);
+    assert_eq!(
+        Some(ListUpdate::Push(vec!["Children".to_owned(), "Insomnia".to_owned()])),
+        merged.songs
+    );
+    assert_eq!(Some(MapUpdate::Remove(vec!["Sandstorm".to_owned()])), merged.ratings);
+    assert_eq!(Some(NumericUpdate::Increment(5)), merged.plays);
+
+    let merged = merged.merge(UpdatedPlaylist::default().plays(0));
+    playlist.update(&merged);
+    assert_eq!(vec!["Sandstorm", "Children", "Insomnia"], playlist.songs);
+    assert_eq!(0, playlist.plays);
+
+    let merged = UpdatedPlaylist::default()
+        .songs(vec!["Silence".to_owned()])
+        .merge(UpdatedPlaylist::default().push_songs("Noise".to_owned()));
+    assert_eq!(
+        Some(ListUpdate::Set(vec!["Silence".to_owned(), "Noise".to_owned()])),
+        merged.songs
+    );
+}
+
+#[test]
+fn test_derived_inverse() {
+    let original = Inventory {
+        owner: "Buck".to_owned(),
+        note: None,
+        items: vec!["Grill".to_owned()],
+        prices: HashMap::from([("Grill".to_owned(), 300)]),
+    };
+    let update = UpdatedInventory::default()
+        .owner("Hank".to_owned())
+        .note(Some("Strickland Propane".to_owned()))
+        .push_items("Tank".to_owned())
+        .insert_prices("Tank".to_owned(), 30);
+    let inverse = update.inverse_against(&original);
+    assert_eq!(Patch::Clear, inverse.note);
+    assert_eq!(Some(MapUpdate::Remove(vec!["Tank".to_owned()])), inverse.prices);
+
+    let mut inventory = original.clone();
+    inventory.update(&update);
+    inventory.update(&inverse);
+    assert_eq!(original.owner, inventory.owner);
+    assert_eq!(original.note, inventory.note);
+    assert_eq!(original.items, inventory.items);
+    assert_eq!(original.prices, inventory.prices);
+
+    let untouched = UpdatedInventory::default().inverse_against(&original);
+    assert_eq!(None, untouched.owner);
+    assert_eq!(Patch::Unchanged, untouched.note);
+}
+
+#[test]
+fn test_derived_enum_merge_and_inverse() {
+    let merged = UpdatedShape::Replace(Shape::Circle { radius: 1.0 })
+        .merge(UpdatedShape::Circle { radius: Some(2.0) });
+    assert!(matches!(merged, UpdatedShape::Replace(Shape::Circle { radius }) if radius == 2.0));
+
+    let merged = UpdatedShape::Rect(Some(1.0), None).merge(UpdatedShape::Rect(None, Some(3.0)));
+    assert!(matches!(merged, UpdatedShape::Rect(Some(w), Some(h)) if w == 1.0 && h == 3.0));
+
+    let original = Shape::Rect(1.0, 2.0);
+    let mut shape = original.clone();
+    let update = UpdatedShape::Replace(Shape::Empty);
+    let inverse = update.inverse_against(&shape);
+    shape.update(&update);
+    shape.update(&inverse);
+    assert_eq!(original, shape);
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use live_entity::derive::{Entity, Validate};
+use live_entity::{Validate, ValidationError};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Entity, Validate)]
+#[entity_name = "listings"]
+#[validate(custom = "check_discount", update = "check_price_change")]
+struct Listing {
+    #[entity_id]
+    id: u32,
+    #[validate(non_empty, length(max = 12))]
+    title: String,
+    #[validate(range(min = 1, max = 10_000))]
+    price: i64,
+    #[validate(range(min = 0))]
+    discount: i64,
+    #[validate(length(min = 1, max = 3))]
+    tags: Vec<String>,
+    #[validate(non_empty)]
+    details: HashMap<String, String>,
+    #[validate(length(min = 2))]
+    note: Option<String>,
+}
+
+fn check_discount(listing: &Listing) -> Result<(), ValidationError> {
+    if listing.discount > listing.price {
+        return Err(ValidationError::new("discount is more than the price"));
+    }
+    Ok(())
+}
+
+fn check_price_change(current: &Listing, update: &UpdatedListing) -> Result<(), ValidationError> {
+    match update.price {
+        Some(price) if price > current.price * 2 => Err(ValidationError::for_field(
+            "price",
+            "can't more than double at once",
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn listing() -> Listing {
+    Listing {
+        id: 1,
+        title: "Armchair".to_owned(),
+        price: 120,
+        discount: 20,
+        tags: vec!["furniture".to_owned()],
+        details: HashMap::from([("colour".to_owned(), "green".to_owned())]),
+        note: None,
+    }
+}
+
+fn invalid_field(listing: Listing) -> Option<String> {
+    listing.validate().unwrap_err().field
+}
+
+#[test]
+fn test_derived_validate() {
+    assert_eq!(Ok(()), listing().validate());
+
+    let empty_title = Listing {
+        title: String::new(),
+        ..listing()
+    };
+    assert_eq!(
+        Err(ValidationError::for_field("title", "must not be empty")),
+        empty_title.validate()
+    );
+    let long_title = Listing {
+        title: "Reclining armchair".to_owned(),
+        ..listing()
+    };
+    assert_eq!(Some("title".to_owned()), invalid_field(long_title));
+    // Lengths of strings count characters rather than bytes.
+    let accented = Listing {
+        title: "Chaise à bras".chars().take(12).collect(),
+        ..listing()
+    };
+    assert_eq!(Ok(()), accented.validate());
+
+    let free = Listing {
+        price: 0,
+        discount: 0,
+        ..listing()
+    };
+    assert_eq!(
+        Err(ValidationError::for_field("price", "must be at least 1")),
+        free.validate()
+    );
+    let negative = Listing {
+        discount: -5,
+        ..listing()
+    };
+    assert_eq!(Some("discount".to_owned()), invalid_field(negative));
+
+    let untagged = Listing {
+        tags: vec![],
+        ..listing()
+    };
+    assert_eq!(Some("tags".to_owned()), invalid_field(untagged));
+    let no_details = Listing {
+        details: HashMap::new(),
+        ..listing()
+    };
+    assert_eq!(Some("details".to_owned()), invalid_field(no_details));
+
+    let short_note = Listing {
+        note: Some("!".to_owned()),
+        ..listing()
+    };
+    assert_eq!(Some("note".to_owned()), invalid_field(short_note));
+    let note = Listing {
+        note: Some("Slightly worn".to_owned()),
+        ..listing()
+    };
+    assert_eq!(Ok(()), note.validate());
+
+    let overdiscounted = Listing {
+        discount: 200,
+        ..listing()
+    };
+    assert_eq!(
+        Err(ValidationError::new("discount is more than the price")),
+        overdiscounted.validate()
+    );
+}
+
+#[test]
+fn test_derived_validate_update() {
+    let current = listing();
+    assert_eq!(
+        Ok(()),
+        current.validate_update(&UpdatedListing::default().price(200))
+    );
+    assert_eq!(
+        Some("price".to_owned()),
+        current
+            .validate_update(&UpdatedListing::default().price(250))
+            .unwrap_err()
+            .field
+    );
+    assert_eq!(
+        Some("title".to_owned()),
+        current
+            .validate_update(&UpdatedListing::default().title(String::new()))
+            .unwrap_err()
+            .field
+    );
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn test_derived_validate_regex() {
+    #[derive(Debug, Serialize, Deserialize, Clone, Entity, Validate)]
+    #[entity_name = "accounts"]
+    struct Account {
+        #[entity_id]
+        #[validate(regex = "^[a-z][a-z0-9_]*$")]
+        username: String,
+        #[validate(regex = "^[^@]+@[^@]+$")]
+        email: Option<String>,
+    }
+
+    let account = |username: &str, email: Option<&str>| Account {
+        username: username.to_owned(),
+        email: email.map(str::to_owned),
+    };
+    assert_eq!(Ok(()), account("ada_99", None).validate());
+    assert_eq!(Ok(()), account("ada", Some("ada@example.com")).validate());
+    assert_eq!(
+        Err(ValidationError::for_field(
+            "username",
+            "must match ^[a-z][a-z0-9_]*$"
+        )),
+        account("99ada", None).validate()
+    );
+    assert_eq!(
+        Some("email".to_owned()),
+        account("ada", Some("ada")).validate().unwrap_err().field
+    );
+}
@@ -0,0 +1,193 @@
+#![cfg(feature = "in-mem")]
+
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use live_entity::derive::Entity;
+use live_entity::event_sourced::{EventLog, EventSourcedStore, Snapshot};
+use live_entity::in_mem::InMemEventLog;
+use live_entity::{Entity, Event, SoftDeleteUnsupportedError, Store};
+use serde::{Deserialize, Serialize};
+use test_utils::storage_test::{
+    test_storage_functions, test_storage_singleton_functions, test_storage_undo_redo,
+    test_storage_update_operations,
+};
+use tokio::sync::broadcast::channel;
+
+#[tokio::test]
+async fn test_event_sourced_store() {
+    let storage = Arc::new(EventSourcedStore::new(InMemEventLog::new(), 1));
+    test_storage_functions(storage.clone()).await;
+    test_storage_singleton_functions(storage.clone()).await;
+    test_storage_update_operations(storage.clone()).await;
+    test_storage_undo_redo(storage).await;
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "ledger_entries"]
+struct LedgerEntry {
+    #[entity_id]
+    id: u32,
+    #[entity(increment)]
+    amount: i64,
+}
+
+#[tokio::test]
+async fn test_event_sourced_store_snapshots() {
+    let log = InMemEventLog::new();
+    let storage = EventSourcedStore::new(log.clone(), 1).snapshot_every(3);
+    storage.create(&LedgerEntry { id: 1, amount: 10 }).await.unwrap();
+    storage.create(&LedgerEntry { id: 2, amount: 20 }).await.unwrap();
+    assert!(log.load_snapshot::<LedgerEntry>().await.unwrap().is_none());
+
+    let deposit = UpdatedLedgerEntry::default().increment_amount(5);
+    storage.update::<LedgerEntry>(&1, &deposit).await.unwrap();
+    let snapshot = log
+        .load_snapshot::<LedgerEntry>()
+        .await
+        .unwrap()
+        .expect("No snapshot after three events.");
+    assert_eq!(3, snapshot.position);
+    assert_eq!(2, snapshot.entities.len());
+
+    storage.update::<LedgerEntry>(&1, &deposit).await.unwrap();
+    storage.delete_by_id::<LedgerEntry>(&2).await.unwrap();
+    let events = log.read::<LedgerEntry>(0).await.unwrap();
+    assert_eq!(5, events.len());
+    assert!(matches!(events[4], Event::Delete(2)));
+
+    // A new store over the same log starts from the snapshot and replays
+    // what came after it.
+    let reopened = EventSourcedStore::new(log.clone(), 1);
+    let entries = reopened.get_all::<LedgerEntry>().await.unwrap();
+    assert_eq!(1, entries.len());
+    assert_eq!(20, entries[0].amount);
+
+    reopened.rebuild::<LedgerEntry>().await.unwrap();
+    assert_eq!(20, reopened.get_by_id::<LedgerEntry>(&1).await.unwrap().amount);
+    assert!(reopened.get_by_id::<LedgerEntry>(&2).await.is_err());
+}
+
+/// A log that can't save its first snapshot.
+#[derive(Clone, Default)]
+struct FirstSnapshotFails {
+    log: InMemEventLog,
+    attempts: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl EventLog for FirstSnapshotFails {
+    async fn append<E: Entity>(&self, event: &Event<E>) -> Result<u64, Box<dyn Error>> {
+        self.log.append(event).await
+    }
+
+    async fn read<E: Entity>(&self, from: u64) -> Result<Vec<Event<E>>, Box<dyn Error>> {
+        self.log.read(from).await
+    }
+
+    async fn save_snapshot<E: Entity>(&self, snapshot: &Snapshot<E>) -> Result<(), Box<dyn Error>> {
+        if self.attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+            return Err("Disk full".into());
+        }
+        self.log.save_snapshot(snapshot).await
+    }
+
+    async fn load_snapshot<E: Entity>(&self) -> Result<Option<Snapshot<E>>, Box<dyn Error>> {
+        self.log.load_snapshot().await
+    }
+}
+
+#[tokio::test]
+async fn test_event_sourced_store_snapshot_failure() {
+    let log = FirstSnapshotFails::default();
+    let storage = Arc::new(EventSourcedStore::new(log.clone(), 4).snapshot_every(2));
+    storage.create(&LedgerEntry { id: 1, amount: 10 }).await.unwrap();
+    let (tx, mut rx) = channel(4);
+    let watched = storage.clone();
+    tokio::spawn(async move { watched.watch::<LedgerEntry>(tx).await.unwrap() });
+    tokio::task::yield_now().await;
+
+    // The event is in the log, so the write succeeds and is watched even
+    // though the snapshot after it couldn't be saved.
+    storage.create(&LedgerEntry { id: 2, amount: 20 }).await.unwrap();
+    assert!(matches!(rx.recv().await.unwrap(), Event::Create(LedgerEntry { id: 2, .. })));
+    assert_eq!(1, log.attempts.load(Ordering::SeqCst));
+    assert!(log.load_snapshot::<LedgerEntry>().await.unwrap().is_none());
+
+    // It's tried again after the next interval.
+    storage.delete_by_id::<LedgerEntry>(&1).await.unwrap();
+    assert_eq!(1, log.attempts.load(Ordering::SeqCst));
+    storage.delete_by_id::<LedgerEntry>(&2).await.unwrap();
+    assert_eq!(2, log.attempts.load(Ordering::SeqCst));
+    let snapshot = log.load_snapshot::<LedgerEntry>().await.unwrap().unwrap();
+    assert_eq!(4, snapshot.position);
+    assert_eq!(4, log.read::<LedgerEntry>(0).await.unwrap().len());
+}
+
+#[cfg(feature = "file-log")]
+mod file_log {
+    use std::path::PathBuf;
+
+    use live_entity::file_log::FileEventLog;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("live-entity-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_file_event_log() {
+        let dir = temp_dir("file-event-log");
+        let log = FileEventLog::new(&dir).await.unwrap();
+        let storage = Arc::new(EventSourcedStore::new(log, 1).snapshot_every(4));
+        test_storage_functions(storage.clone()).await;
+        test_storage_update_operations(storage).await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_event_log_persists() {
+        let dir = temp_dir("file-event-log-persists");
+        let storage = EventSourcedStore::new(FileEventLog::new(&dir).await.unwrap(), 1)
+            .snapshot_every(2);
+        storage.create(&LedgerEntry { id: 7, amount: 0 }).await.unwrap();
+        for _ in 0..3 {
+            storage
+                .update::<LedgerEntry>(&7, &UpdatedLedgerEntry::default().increment_amount(2))
+                .await
+                .unwrap();
+        }
+        drop(storage);
+
+        let log = FileEventLog::new(&dir).await.unwrap();
+        assert_eq!(4, log.read::<LedgerEntry>(0).await.unwrap().len());
+        assert_eq!(
+            4,
+            log.load_snapshot::<LedgerEntry>().await.unwrap().unwrap().position
+        );
+        let reopened = EventSourcedStore::new(log, 1);
+        assert_eq!(6, reopened.get_by_id::<LedgerEntry>(&7).await.unwrap().amount);
+        reopened
+            .update::<LedgerEntry>(&7, &UpdatedLedgerEntry::default().amount(1))
+            .await
+            .unwrap();
+        assert_eq!(5, reopened.log().read::<LedgerEntry>(0).await.unwrap().len());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_event_sourced_store_soft_delete_unsupported() {
+    let storage = EventSourcedStore::new(InMemEventLog::new(), 1);
+    storage.create(&LedgerEntry { id: 1, amount: 10 }).await.unwrap();
+    storage.delete_by_id::<LedgerEntry>(&1).await.unwrap();
+    let err = storage.restore::<LedgerEntry>(&1).await.unwrap_err();
+    assert!(err.is::<SoftDeleteUnsupportedError>());
+    let err = storage.get_deleted::<LedgerEntry>().await.unwrap_err();
+    assert!(err.is::<SoftDeleteUnsupportedError>());
+}
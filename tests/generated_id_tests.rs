@@ -0,0 +1,201 @@
+#![cfg(feature = "in-mem")]
+
+use std::error::Error;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use live_entity::derive::Entity;
+use live_entity::event_sourced::EventSourcedStore;
+use live_entity::in_mem::{InMemEventLog, InMemStore};
+use live_entity::validating::ValidatingStore;
+use live_entity::{
+    Entity, Event, MultiTenant, SequenceOutOfRangeError, SequenceUnsupportedError, Store,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::Sender;
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "tickets"]
+struct Ticket {
+    #[entity_id(generate = "sequence")]
+    number: u64,
+    title: String,
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "orders"]
+struct Order {
+    #[entity_id(generate = "sequence")]
+    number: String,
+}
+
+fn ticket(title: &str) -> Ticket {
+    Ticket {
+        number: 0,
+        title: title.to_owned(),
+    }
+}
+
+#[tokio::test]
+async fn test_sequential_ids() {
+    let store = InMemStore::new(100);
+    assert_eq!(
+        1,
+        store.insert_new(ticket("Printer on fire")).await.unwrap()
+    );
+    assert_eq!(2, store.insert_new(ticket("Out of toner")).await.unwrap());
+    assert_eq!(
+        "1",
+        store
+            .insert_new(Order {
+                number: String::new()
+            })
+            .await
+            .unwrap()
+    );
+
+    let ticket = store.get_by_id::<Ticket>(&2).await.unwrap();
+    assert_eq!("Out of toner", ticket.title);
+    assert_eq!(2, ticket.number);
+}
+
+#[tokio::test]
+async fn test_sequences_outlive_entities() {
+    let store = InMemStore::new(100);
+    store.insert_new(ticket("Printer on fire")).await.unwrap();
+    store.delete_all::<Ticket>().await.unwrap();
+    assert_eq!(2, store.insert_new(ticket("Out of toner")).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_sequences_per_tenant() {
+    let store = InMemStore::new(100);
+    let acme = store.for_tenant("acme");
+    acme.insert_new(ticket("Printer on fire")).await.unwrap();
+    assert_eq!(2, acme.insert_new(ticket("Out of toner")).await.unwrap());
+    let globex = store.for_tenant("globex");
+    assert_eq!(
+        1,
+        globex.insert_new(ticket("Printer on fire")).await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_sequences_through_wrappers() {
+    let store = ValidatingStore::new(Arc::new(InMemStore::new(100)));
+    assert_eq!(
+        1,
+        store.insert_new(ticket("Printer on fire")).await.unwrap()
+    );
+    assert_eq!(1, store.inner().get_all::<Ticket>().await.unwrap().len());
+}
+
+#[tokio::test]
+async fn test_sequences_unsupported() {
+    let store = EventSourcedStore::new(InMemEventLog::new(), 100);
+    let err = store
+        .insert_new(ticket("Printer on fire"))
+        .await
+        .unwrap_err();
+    assert!(err.is::<SequenceUnsupportedError>());
+    assert!(store.get_all::<Ticket>().await.unwrap().is_empty());
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "seats"]
+struct Seat {
+    #[entity_id(generate = "sequence")]
+    number: u32,
+}
+
+/// A store whose sequences have run past what a `u32` holds.
+struct LongRunning(InMemStore);
+
+#[async_trait]
+impl Store for LongRunning {
+    async fn create<E: Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
+        self.0.create(entity).await
+    }
+
+    async fn next_sequence<E: Entity>(&self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from(u32::MAX) + self.0.next_sequence::<E>().await?)
+    }
+
+    async fn update<E: Entity>(&self, id: &E::ID, update: &E::Update) -> Result<(), Box<dyn Error>> {
+        self.0.update::<E>(id, update).await
+    }
+
+    async fn delete_all<E: Entity>(&self) -> Result<(), Box<dyn Error>> {
+        self.0.delete_all::<E>().await
+    }
+
+    async fn delete_by_id<E: Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.0.delete_by_id::<E>(id).await
+    }
+
+    async fn get_all<E: Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        self.0.get_all().await
+    }
+
+    async fn get_by_id<E: Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
+        self.0.get_by_id(id).await
+    }
+
+    async fn watch<E: Entity>(&self, channel: Sender<Event<E>>) -> Result<(), Box<dyn Error>> {
+        self.0.watch(channel).await
+    }
+}
+
+#[tokio::test]
+async fn test_sequences_out_of_range() {
+    let store = LongRunning(InMemStore::new(100));
+    let err = store.insert_new(Seat { number: 0 }).await.unwrap_err();
+    assert!(err.is::<SequenceOutOfRangeError>());
+    assert!(store.get_all::<Seat>().await.unwrap().is_empty());
+    // IDs that can hold the value still get it.
+    assert_eq!(
+        u64::from(u32::MAX) + 1,
+        store.insert_new(ticket("Printer on fire")).await.unwrap()
+    );
+}
+
+#[cfg(feature = "uuid")]
+#[tokio::test]
+async fn test_uuid_ids() {
+    use live_entity::uuid::Uuid;
+
+    #[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+    #[entity_name = "sessions"]
+    struct Session {
+        #[entity_id(generate = "uuid")]
+        id: Uuid,
+    }
+
+    let store = InMemStore::new(100);
+    let first = store.insert_new(Session { id: Uuid::nil() }).await.unwrap();
+    let second = store.insert_new(Session { id: Uuid::nil() }).await.unwrap();
+    assert_ne!(first, second);
+    assert_eq!(4, first.get_version_num());
+    assert_eq!(2, store.get_all::<Session>().await.unwrap().len());
+}
+
+#[cfg(feature = "ulid")]
+#[tokio::test]
+async fn test_ulid_ids() {
+    use live_entity::ulid::Ulid;
+
+    #[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+    #[entity_name = "uploads"]
+    struct Upload {
+        #[entity_id(generate = "ulid")]
+        id: String,
+    }
+
+    let store = InMemStore::new(100);
+    let id = store
+        .insert_new(Upload { id: String::new() })
+        .await
+        .unwrap();
+    assert!(Ulid::from_string(&id).is_ok());
+    store.get_by_id::<Upload>(&id).await.unwrap();
+}
@@ -1,9 +1,14 @@
 #![cfg(feature = "in-mem")]
 
 use std::sync::Arc;
+use std::time::Duration;
 
-use live_entity::in_mem::InMemStore;
-use test_utils::storage_test::{test_storage_functions, test_storage_singleton_functions};
+use live_entity::in_mem::{InMemHistorySink, InMemStore};
+use test_utils::storage_test::{
+    test_history_sink, test_storage_functions, test_storage_singleton_functions,
+    test_storage_soft_delete, test_storage_soft_delete_expiry, test_storage_undo_redo,
+    test_storage_update_operations,
+};
 
 #[tokio::test]
 async fn test_in_mem_store() {
@@ -16,3 +21,33 @@ async fn test_in_mem_store_singletons() {
     let storage = Arc::new(InMemStore::new(1));
     test_storage_singleton_functions(storage).await;
 }
+
+#[tokio::test]
+async fn test_in_mem_store_update_operations() {
+    let storage = Arc::new(InMemStore::new(1));
+    test_storage_update_operations(storage).await;
+}
+
+#[tokio::test]
+async fn test_in_mem_store_soft_delete() {
+    let storage = Arc::new(InMemStore::new(1));
+    test_storage_soft_delete(storage).await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_in_mem_store_soft_delete_expiry() {
+    let storage = Arc::new(InMemStore::new(4));
+    test_storage_soft_delete_expiry(storage, Duration::from_secs(5)).await;
+}
+
+#[tokio::test]
+async fn test_in_mem_store_undo_redo() {
+    let storage = Arc::new(InMemStore::new(1));
+    test_storage_undo_redo(storage).await;
+}
+
+#[tokio::test]
+async fn test_in_mem_history_sink() {
+    let storage = Arc::new(InMemStore::new(4));
+    test_history_sink(storage, Arc::new(InMemHistorySink::new())).await;
+}
@@ -0,0 +1,178 @@
+#![cfg(feature = "in-mem")]
+
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use live_entity::cached::CachedStore;
+use live_entity::derive::{Entity, Validate};
+use live_entity::in_mem::InMemStore;
+use live_entity::layer::{Call, Intercepted, Interceptor, InterceptorLayer, Layered, Outcome};
+use live_entity::validating::ValidatingStore;
+use live_entity::{Entity, Store, ValidationError};
+use serde::{Deserialize, Serialize};
+use test_utils::storage_test::{
+    test_storage_functions, test_storage_singleton_functions, test_storage_soft_delete,
+    test_storage_update_operations,
+};
+
+#[derive(Clone, Default)]
+struct Recorder {
+    calls: Arc<Mutex<Vec<String>>>,
+}
+
+impl Recorder {
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.calls.lock().unwrap())
+    }
+}
+
+#[async_trait]
+impl Interceptor for Recorder {
+    async fn before<E: Entity>(&self, call: &mut Call<E>) -> Result<(), Box<dyn Error>> {
+        let entry = format!("before {} {}", call.operation(), call.type_name());
+        self.calls.lock().unwrap().push(entry);
+        Ok(())
+    }
+
+    fn after<E: Entity>(&self, call: &Call<E>, result: &mut Result<Outcome<E>, Box<dyn Error>>) {
+        let status = if result.is_ok() { "ok" } else { "err" };
+        let entry = format!("after {} {} {}", call.operation(), call.type_name(), status);
+        self.calls.lock().unwrap().push(entry);
+    }
+}
+
+#[derive(Entity, Validate, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "bins"]
+struct Bin {
+    #[entity_id]
+    label: String,
+    #[validate(range(max = 100))]
+    fill: u8,
+}
+
+fn bin(label: &str, fill: u8) -> Bin {
+    Bin {
+        label: label.to_owned(),
+        fill,
+    }
+}
+
+#[tokio::test]
+async fn test_intercepted_store() {
+    let storage = Arc::new(Intercepted::new(
+        Arc::new(InMemStore::new(8)),
+        Recorder::default(),
+    ));
+    test_storage_functions(storage.clone()).await;
+    test_storage_singleton_functions(storage.clone()).await;
+    test_storage_update_operations(storage.clone()).await;
+    test_storage_soft_delete(storage).await;
+}
+
+#[tokio::test]
+async fn test_interceptor_hooks() {
+    let recorder = Recorder::default();
+    let storage = InMemStore::new(8).with_layer(InterceptorLayer(recorder.clone()));
+
+    storage.create(&bin("kitchen", 10)).await.unwrap();
+    storage
+        .get_by_id::<Bin>(&"garage".to_owned())
+        .await
+        .unwrap_err();
+    storage.delete_all::<Bin>().await.unwrap();
+    assert_eq!(
+        vec![
+            "before create bins",
+            "after create bins ok",
+            "before get_by_id bins",
+            "after get_by_id bins err",
+            "before delete_all bins",
+            "after delete_all bins ok",
+        ],
+        recorder.take()
+    );
+}
+
+/// Caps fills at 100, hides empty bins and refuses to delete everything.
+struct Janitor;
+
+#[async_trait]
+impl Interceptor for Janitor {
+    async fn before<E: Entity>(&self, call: &mut Call<E>) -> Result<(), Box<dyn Error>> {
+        match call {
+            Call::DeleteAll => Err("Not allowed to delete everything".into()),
+            Call::Create(entity) => {
+                let mut value = serde_json::to_value(&*entity)?;
+                if value["fill"].as_u64().is_some_and(|fill| fill > 100) {
+                    value["fill"] = 100.into();
+                }
+                *entity = serde_json::from_value(value)?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn after<E: Entity>(&self, _call: &Call<E>, result: &mut Result<Outcome<E>, Box<dyn Error>>) {
+        if let Ok(Outcome::Entities(entities)) = result {
+            entities.retain(|e| serde_json::to_value(e).unwrap()["fill"] != 0);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_interceptor_changes_calls() {
+    let inner = Arc::new(InMemStore::new(8));
+    let storage = Intercepted::new(inner.clone(), Janitor);
+
+    storage.create(&bin("kitchen", 150)).await.unwrap();
+    storage.create(&bin("garage", 0)).await.unwrap();
+    assert_eq!(
+        100,
+        inner
+            .get_by_id::<Bin>(&"kitchen".to_owned())
+            .await
+            .unwrap()
+            .fill
+    );
+    assert_eq!(2, inner.get_all::<Bin>().await.unwrap().len());
+    assert_eq!(
+        vec![bin("kitchen", 100)],
+        storage.get_all::<Bin>().await.unwrap()
+    );
+
+    assert!(storage.delete_all::<Bin>().await.is_err());
+    assert_eq!(2, inner.get_all::<Bin>().await.unwrap().len());
+}
+
+#[tokio::test]
+async fn test_stacked_layers() {
+    let recorder = Recorder::default();
+    let storage = InMemStore::new(8)
+        .with_layer(InterceptorLayer(recorder.clone()))
+        .with_layer(|s| CachedStore::new(s, 8))
+        .with_layer(|s| ValidatingStore::new(s).validate::<Bin>());
+
+    let err = storage.create(&bin("kitchen", 150)).await.unwrap_err();
+    assert!(err.is::<ValidationError>());
+    assert!(recorder.take().is_empty());
+
+    // The cache starts watching the inner store before its first write.
+    storage.create(&bin("kitchen", 50)).await.unwrap();
+    let calls = recorder.take();
+    assert_eq!("before watch bins", calls[0]);
+    assert_eq!(
+        vec!["before create bins", "after create bins ok"],
+        calls[1..3]
+    );
+    // Once warm, reads are served by the cache without reaching the inner
+    // store, though the cache may still be refreshing from it.
+    storage.get_all::<Bin>().await.unwrap();
+    recorder.take();
+    assert_eq!(
+        vec![bin("kitchen", 50)],
+        storage.get_all::<Bin>().await.unwrap()
+    );
+    assert!(!recorder.take().iter().any(|call| call.contains("get_all")));
+}
@@ -0,0 +1,162 @@
+#![cfg(feature = "in-mem")]
+
+use std::sync::{Arc, Mutex};
+
+use live_entity::derive::Entity;
+use live_entity::in_mem::InMemStore;
+use live_entity::migration::{copy_all, Checkpoint, Migration};
+use live_entity::Store;
+use serde::{Deserialize, Serialize};
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "customers"]
+struct Customer {
+    #[entity_id]
+    id: u32,
+    name: String,
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "orders"]
+struct Order {
+    #[entity_id]
+    id: String,
+    customer: u32,
+}
+
+async fn seeded_source() -> Arc<InMemStore> {
+    let source = Arc::new(InMemStore::new(16));
+    for id in 1..=5 {
+        let name = format!("Customer {}", id);
+        source.create(&Customer { id, name }).await.unwrap();
+    }
+    for (id, customer) in [("A-1", 1), ("A-2", 2), ("B-1", 3)] {
+        let id = id.to_owned();
+        source.create(&Order { id, customer }).await.unwrap();
+    }
+    source
+}
+
+#[tokio::test]
+async fn test_copy_all() {
+    let source = seeded_source().await;
+    let target = InMemStore::new(16);
+    target
+        .create(&Customer {
+            id: 1,
+            name: "Stale".to_owned(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(5, copy_all::<Customer>(&*source, &target).await.unwrap());
+    assert_eq!(5, target.get_all::<Customer>().await.unwrap().len());
+    assert_eq!("Customer 1", target.get_by_id::<Customer>(&1).await.unwrap().name);
+    assert!(target.get_all::<Order>().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_migration_batches_and_resumes() {
+    let source = seeded_source().await;
+    let target = Arc::new(InMemStore::new(16));
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let seen = reports.clone();
+    let migration = Migration::new(source.clone(), target.clone())
+        .entity::<Customer>()
+        .entity::<Order>()
+        .batch_size(2)
+        .on_progress(move |p| seen.lock().unwrap().push(p.clone()));
+    let done = migration.run(Checkpoint::default()).await.unwrap();
+    assert_eq!(5, target.get_all::<Customer>().await.unwrap().len());
+    assert_eq!(3, target.get_all::<Order>().await.unwrap().len());
+
+    let reports = reports.lock().unwrap().clone();
+    let counts: Vec<_> = reports.iter().map(|p| (p.type_name, p.copied)).collect();
+    assert_eq!(
+        vec![
+            ("customers", 2),
+            ("customers", 4),
+            ("customers", 5),
+            ("orders", 2),
+            ("orders", 3),
+        ],
+        counts
+    );
+    assert_eq!(done, reports.last().unwrap().checkpoint);
+
+    // Resuming after the first batch copies what came after it.
+    let resumed_target = Arc::new(InMemStore::new(16));
+    for id in [1, 2] {
+        let customer = source.get_by_id::<Customer>(&id).await.unwrap();
+        resumed_target.create(&customer).await.unwrap();
+    }
+    let resumed_reports = Arc::new(Mutex::new(Vec::new()));
+    let seen = resumed_reports.clone();
+    let resumed = Migration::new(source.clone(), resumed_target.clone())
+        .entity::<Customer>()
+        .entity::<Order>()
+        .batch_size(2)
+        .on_progress(move |p| seen.lock().unwrap().push((p.type_name, p.copied)));
+    let finished = resumed.run(reports[0].checkpoint.clone()).await.unwrap();
+    assert_eq!(5, resumed_target.get_all::<Customer>().await.unwrap().len());
+    assert_eq!(3, resumed_target.get_all::<Order>().await.unwrap().len());
+    assert_eq!(done, finished);
+    assert_eq!(
+        vec![("customers", 2), ("customers", 3), ("orders", 2), ("orders", 3)],
+        *resumed_reports.lock().unwrap()
+    );
+
+    // Changes made before the checkpoint between runs are copied too.
+    source
+        .update::<Customer>(&1, &UpdatedCustomer::default().name("Renamed".to_owned()))
+        .await
+        .unwrap();
+    let name = "Customer 0".to_owned();
+    source.create(&Customer { id: 0, name }).await.unwrap();
+    Migration::new(source, target.clone())
+        .entity::<Customer>()
+        .batch_size(2)
+        .run(done)
+        .await
+        .unwrap();
+    assert_eq!(6, target.get_all::<Customer>().await.unwrap().len());
+    assert_eq!("Renamed", target.get_by_id::<Customer>(&1).await.unwrap().name);
+}
+
+#[tokio::test]
+async fn test_migration_follows_until_cutover() {
+    let source = seeded_source().await;
+    let target = Arc::new(InMemStore::new(16));
+    let migration = Migration::new(source.clone(), target.clone())
+        .entity::<Customer>()
+        .entity::<Order>();
+    let (cutover, cutover_rx) = tokio::sync::oneshot::channel::<()>();
+    let running = tokio::spawn(async move {
+        migration
+            .run_until(Checkpoint::default(), async {
+                let _ = cutover_rx.await;
+            })
+            .await
+            .unwrap();
+    });
+    for _ in 0..10 {
+        tokio::task::yield_now().await;
+    }
+
+    // The source stays in use while the migration is following it.
+    source
+        .update::<Customer>(&2, &UpdatedCustomer::default().name("Renamed".to_owned()))
+        .await
+        .unwrap();
+    source.delete_by_id::<Customer>(&5).await.unwrap();
+    let id = "C-1".to_owned();
+    source.create(&Order { id, customer: 2 }).await.unwrap();
+    for _ in 0..10 {
+        tokio::task::yield_now().await;
+    }
+    cutover.send(()).unwrap();
+    running.await.unwrap();
+
+    assert_eq!(4, target.get_all::<Customer>().await.unwrap().len());
+    assert_eq!("Renamed", target.get_by_id::<Customer>(&2).await.unwrap().name);
+    assert_eq!(4, target.get_all::<Order>().await.unwrap().len());
+}
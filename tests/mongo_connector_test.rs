@@ -25,4 +25,312 @@ async fn test_mongodb_connector() {
 async fn test_mongodb_connector_singletons() {
     let storage = Arc::new(get_store().await);
     test_storage_singleton_functions(storage).await;
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_mongodb_connector_update_operations() {
+    let storage = Arc::new(get_store().await);
+    test_storage_update_operations(storage).await;
+}
+#[tokio::test]
+#[ignore]
+async fn test_mongodb_connector_soft_delete() {
+    let storage = Arc::new(get_store().await);
+    test_storage_soft_delete(storage).await;
+}
+
+// MongoDB looks for expired documents about once a minute.
+#[tokio::test]
+#[ignore]
+async fn test_mongodb_connector_soft_delete_expiry() {
+    let storage = Arc::new(get_store().await);
+    test_storage_soft_delete_expiry(storage, std::time::Duration::from_secs(130)).await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_mongodb_connector_undo_redo() {
+    let storage = Arc::new(get_store().await);
+    test_storage_undo_redo(storage).await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_mongodb_history_sink() {
+    let storage = Arc::new(get_store().await);
+    let sink = Arc::new(storage.history_sink());
+    test_history_sink(storage, sink).await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_mongodb_tenant() {
+    use live_entity::MultiTenant;
+
+    let storage = Arc::new(get_store().await.for_tenant("acme"));
+    test_storage_functions(storage.clone()).await;
+    test_history_sink(storage.clone(), Arc::new(storage.history_sink())).await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_mongodb_generated_ids() {
+    use live_entity::derive::Entity;
+    use live_entity::Store;
+    use mongodb::bson::oid::ObjectId;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+    #[entity_name = "generated_receipts"]
+    struct Receipt {
+        #[entity_id(generate = "object_id")]
+        #[serde(rename = "_id")]
+        id: ObjectId,
+        number: u64,
+    }
+
+    #[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+    #[entity_name = "generated_invoices"]
+    struct Invoice {
+        #[entity_id(generate = "sequence")]
+        #[serde(rename = "_id")]
+        number: u64,
+    }
+
+    let store = get_store().await;
+    store.delete_all::<Receipt>().await.unwrap();
+    let first = store.insert_new(Invoice { number: 0 }).await.unwrap();
+    let second = store.insert_new(Invoice { number: 0 }).await.unwrap();
+    assert_eq!(first + 1, second);
+
+    let receipt = Receipt {
+        id: ObjectId::new(),
+        number: second,
+    };
+    let id = store.insert_new(receipt).await.unwrap();
+    assert_eq!(
+        second,
+        store.get_by_id::<Receipt>(&id).await.unwrap().number
+    );
+
+    store.delete_all::<Receipt>().await.unwrap();
+    store.delete_all::<Invoice>().await.unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_mongodb_composite_ids() {
+    use live_entity::derive::Entity;
+    use live_entity::Store;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+    #[entity_name = "composite_stock"]
+    struct Stock {
+        #[entity_id]
+        warehouse: String,
+        #[entity_id]
+        sku: u32,
+        count: u32,
+    }
+
+    let store = get_store().await;
+    store.delete_all::<Stock>().await.unwrap();
+    for (warehouse, sku, count) in [("north", 1, 5), ("north", 2, 0), ("south", 1, 7)] {
+        let stock = Stock {
+            warehouse: warehouse.to_owned(),
+            sku,
+            count,
+        };
+        store.create(&stock).await.unwrap();
+    }
+    let id = StockId {
+        warehouse: "south".to_owned(),
+        sku: 1,
+    };
+    assert_eq!(7, store.get_by_id::<Stock>(&id).await.unwrap().count);
+    let north = store.get_by_id_prefix::<Stock, _>(&"north").await.unwrap();
+    assert_eq!(2, north.len());
+    let one = store
+        .get_by_id_prefix::<Stock, _>(&("north", 2))
+        .await
+        .unwrap();
+    assert_eq!(0, one[0].count);
+
+    store.delete_all::<Stock>().await.unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_mongodb_timestamps() {
+    use live_entity::derive::Entity;
+    use live_entity::{ActingAs, Store, Timestamp};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+    #[entity_name = "stamped_documents"]
+    struct Document {
+        #[entity_id]
+        id: u32,
+        title: String,
+        #[entity(created_at)]
+        created_at: Timestamp,
+        #[entity(updated_at)]
+        updated_at: Timestamp,
+        #[entity(modified_by)]
+        modified_by: Option<String>,
+    }
+
+    let store = get_store().await;
+    store.delete_all::<Document>().await.unwrap();
+    let document = Document {
+        id: 1,
+        title: "Draft".to_owned(),
+        created_at: Timestamp::from_millis(0),
+        updated_at: Timestamp::from_millis(0),
+        modified_by: None,
+    };
+    store.acting_as("ann").create(&document).await.unwrap();
+    let created = store.get_by_id::<Document>(&1).await.unwrap();
+    assert!(created.created_at.millis() > 0);
+    assert_eq!(Some("ann"), created.modified_by.as_deref());
+
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    let update = UpdatedDocument::default().title("Final".to_owned());
+    store
+        .acting_as("bob")
+        .update::<Document>(&1, &update)
+        .await
+        .unwrap();
+    let updated = store.get_by_id::<Document>(&1).await.unwrap();
+    assert_eq!(created.created_at, updated.created_at);
+    assert!(updated.updated_at > created.updated_at);
+    assert_eq!(Some("bob"), updated.modified_by.as_deref());
+
+    store.delete_all::<Document>().await.unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_mongodb_ttl() {
+    use live_entity::derive::Entity;
+    use live_entity::{Event, Store};
+    use serde::{Deserialize, Serialize};
+    use std::time::Duration;
+    use tokio::sync::broadcast;
+
+    #[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+    #[entity_name = "expiring_invites"]
+    struct Invite {
+        #[entity_id]
+        code: u32,
+        email: String,
+    }
+
+    let store = Arc::new(get_store().await);
+    store.delete_all::<Invite>().await.unwrap();
+    let (sender, mut receiver) = broadcast::channel::<Event<Invite>>(16);
+    let watching = store.clone();
+    let watch = tokio::spawn(async move { watching.watch(sender).await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let invite = Invite {
+        code: 1,
+        email: "ann@example.com".to_owned(),
+    };
+    store
+        .create_with_ttl(&invite, Duration::from_secs(1))
+        .await
+        .unwrap();
+    let stored = store.get_by_id::<Invite>(&1).await.unwrap();
+    assert_eq!("ann@example.com", stored.email);
+
+    // MongoDB looks for expired documents about once a minute.
+    let deleted = tokio::time::timeout(Duration::from_secs(130), async {
+        loop {
+            if let Event::Delete(id) = receiver.recv().await.unwrap() {
+                return id;
+            }
+        }
+    });
+    assert_eq!(1, deleted.await.unwrap());
+    watch.abort();
+}
+
+mod v1 {
+    use live_entity_derive::Entity;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+    #[entity_name = "versioned_contacts"]
+    pub struct Contact {
+        #[entity_id]
+        #[serde(rename = "_id")]
+        pub id: i32,
+        pub name: String,
+    }
+}
+
+mod v2 {
+    use live_entity_derive::Entity;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+    #[entity_name = "versioned_contacts"]
+    #[entity(version = 2)]
+    pub struct Contact {
+        #[entity_id]
+        #[serde(rename = "_id")]
+        pub id: i32,
+        pub full_name: String,
+        pub nickname: Option<String>,
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_mongodb_schema_migrations() {
+    use live_entity::{SchemaMigrations, Store};
+
+    let old = get_store().await;
+    old.delete_all::<v1::Contact>().await.unwrap();
+    for (id, name) in [(1, "Ann"), (2, "Bob"), (3, "Cy")] {
+        let contact = v1::Contact {
+            id,
+            name: name.to_owned(),
+        };
+        old.create(&contact).await.unwrap();
+    }
+
+    let store = get_store()
+        .await
+        .with_migrations(
+            SchemaMigrations::new().register::<v2::Contact>(1, |mut value| {
+                let name = value["name"].take();
+                value["full_name"] = name;
+                Ok(value)
+            }),
+        );
+    let mut all = store.get_all::<v2::Contact>().await.unwrap();
+    all.sort_by_key(|c| c.id);
+    let names: Vec<_> = all.iter().map(|c| c.full_name.as_str()).collect();
+    assert_eq!(vec!["Ann", "Bob", "Cy"], names);
+
+    let update = v2::UpdatedContact::default().nickname(Some("Annie".to_owned()));
+    store.update::<v2::Contact>(&1, &update).await.unwrap();
+    let ann = store.get_by_id::<v2::Contact>(&1).await.unwrap();
+    assert_eq!("Ann", ann.full_name);
+    assert_eq!(Some("Annie".to_owned()), ann.nickname);
+
+    assert_eq!(2, store.rewrite_outdated::<v2::Contact>().await.unwrap());
+    assert_eq!(0, store.rewrite_outdated::<v2::Contact>().await.unwrap());
+    let bob = get_store()
+        .await
+        .get_by_id::<v2::Contact>(&2)
+        .await
+        .unwrap();
+    assert_eq!("Bob", bob.full_name);
+
+    store.delete_all::<v2::Contact>().await.unwrap();
+}
@@ -0,0 +1,195 @@
+#![cfg(feature = "in-mem")]
+
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use live_entity::derive::Entity;
+use live_entity::in_mem::InMemStore;
+use live_entity::replicated::{ReplicatedStore, RetryPolicy};
+use live_entity::{Event, Store};
+use serde::{Deserialize, Serialize};
+use test_utils::storage_test::{
+    test_storage_functions, test_storage_singleton_functions, test_storage_soft_delete,
+    test_storage_update_operations,
+};
+use tokio::sync::broadcast::Sender;
+
+#[tokio::test]
+async fn test_replicated_store() {
+    let primary = Arc::new(InMemStore::new(8));
+    let secondary = Arc::new(InMemStore::new(8));
+    let storage = Arc::new(ReplicatedStore::new(
+        primary,
+        vec![secondary],
+        RetryPolicy::default(),
+    ));
+    test_storage_functions(storage.clone()).await;
+    test_storage_singleton_functions(storage.clone()).await;
+    test_storage_update_operations(storage.clone()).await;
+    test_storage_soft_delete(storage).await;
+}
+
+/// Fails the first few writes made to it.
+struct FlakyStore {
+    inner: InMemStore,
+    failures: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct FlakyError;
+impl std::fmt::Display for FlakyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Flaky store failed a write.")
+    }
+}
+impl Error for FlakyError {}
+
+impl FlakyStore {
+    fn check(&self) -> Result<(), Box<dyn Error>> {
+        let failed = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if failed {
+            Err(FlakyError.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl Store for FlakyStore {
+    async fn create<E: live_entity::Entity>(&self, entity: &E) -> Result<(), Box<dyn Error>> {
+        self.check()?;
+        self.inner.create(entity).await
+    }
+
+    async fn update<E: live_entity::Entity>(
+        &self,
+        id: &E::ID,
+        update: &E::Update,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check()?;
+        self.inner.update::<E>(id, update).await
+    }
+
+    async fn delete_all<E: live_entity::Entity>(&self) -> Result<(), Box<dyn Error>> {
+        self.check()?;
+        self.inner.delete_all::<E>().await
+    }
+
+    async fn delete_by_id<E: live_entity::Entity>(&self, id: &E::ID) -> Result<(), Box<dyn Error>> {
+        self.check()?;
+        self.inner.delete_by_id::<E>(id).await
+    }
+
+    async fn get_all<E: live_entity::Entity>(&self) -> Result<Vec<E>, Box<dyn Error>> {
+        self.inner.get_all().await
+    }
+
+    async fn get_by_id<E: live_entity::Entity>(&self, id: &E::ID) -> Result<E, Box<dyn Error>> {
+        self.inner.get_by_id(id).await
+    }
+
+    async fn watch<E: live_entity::Entity>(
+        &self,
+        channel: Sender<Event<E>>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.inner.watch(channel).await
+    }
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "tanks"]
+struct Tank {
+    #[entity_id]
+    serial: u32,
+    #[entity(increment)]
+    gallons: u32,
+}
+
+#[tokio::test]
+async fn test_replicated_store_retries_failed_writes() {
+    let primary = Arc::new(InMemStore::new(8));
+    let steady = Arc::new(FlakyStore {
+        inner: InMemStore::new(8),
+        failures: AtomicUsize::new(0),
+    });
+    let flaky = Arc::new(FlakyStore {
+        inner: InMemStore::new(8),
+        failures: AtomicUsize::new(3),
+    });
+    let storage = ReplicatedStore::new(
+        primary.clone(),
+        vec![steady.clone(), flaky.clone()],
+        RetryPolicy {
+            attempts: 5,
+            delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(4),
+        },
+    );
+
+    storage.create(&Tank { serial: 1, gallons: 0 }).await.unwrap();
+    for _ in 0..4 {
+        storage
+            .update::<Tank>(&1, &UpdatedTank::default().increment_gallons(5))
+            .await
+            .unwrap();
+    }
+    // Reads come from the primary, whether or not the mirrors have caught up.
+    assert_eq!(20, storage.get_by_id::<Tank>(&1).await.unwrap().gallons);
+
+    storage.flush().await;
+    assert_eq!(0, storage.pending());
+    assert_eq!(0, flaky.failures.load(Ordering::SeqCst));
+    // Retried writes are still applied in order, and exactly once.
+    assert_eq!(20, steady.get_by_id::<Tank>(&1).await.unwrap().gallons);
+    assert_eq!(20, flaky.get_by_id::<Tank>(&1).await.unwrap().gallons);
+
+    storage.delete_by_id::<Tank>(&1).await.unwrap();
+    storage.flush().await;
+    assert!(flaky.get_all::<Tank>().await.unwrap().is_empty());
+    assert!(storage.take_failed().is_empty());
+}
+
+#[tokio::test]
+async fn test_replicated_store_gives_up_on_failing_writes() {
+    let primary = Arc::new(InMemStore::new(8));
+    let secondary = Arc::new(FlakyStore {
+        inner: InMemStore::new(8),
+        failures: AtomicUsize::new(3),
+    });
+    let storage = ReplicatedStore::new(
+        primary.clone(),
+        vec![secondary.clone()],
+        RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+        },
+    );
+
+    // The secondary fails the update every time it's tried.
+    primary.create(&Tank { serial: 1, gallons: 0 }).await.unwrap();
+    storage
+        .update::<Tank>(&1, &UpdatedTank::default().increment_gallons(5))
+        .await
+        .unwrap();
+    storage.create(&Tank { serial: 8, gallons: 3 }).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(1), storage.flush())
+        .await
+        .expect("Mirroring never got past the failing write.");
+
+    assert_eq!(3, secondary.get_by_id::<Tank>(&8).await.unwrap().gallons);
+    let failed = storage.take_failed();
+    assert_eq!(1, failed.len());
+    assert_eq!(0, failed[0].secondary);
+    assert_eq!("update", failed[0].operation);
+    assert_eq!("tanks", failed[0].type_name);
+    assert_eq!(Some("1".to_owned()), failed[0].id);
+    assert!(storage.take_failed().is_empty());
+}
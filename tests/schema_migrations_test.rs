@@ -0,0 +1,71 @@
+use live_entity::{Entity, MissingMigrationError, SchemaMigrations};
+use live_entity_derive::Entity;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Entity)]
+#[entity_name = "contacts"]
+#[entity(version = 3)]
+struct Contact {
+    #[entity_id]
+    id: i32,
+    full_name: String,
+    emails: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Entity)]
+#[entity_name = "notes"]
+struct Note {
+    #[entity_id]
+    id: i32,
+}
+
+fn migrations() -> SchemaMigrations {
+    SchemaMigrations::new()
+        .register::<Contact>(1, |mut value| {
+            let name = value["name"].take();
+            value["full_name"] = name;
+            Ok(value)
+        })
+        .register::<Contact>(2, |mut value| {
+            let email = value["email"].take();
+            value["emails"] = json!([email]);
+            Ok(value)
+        })
+}
+
+#[test]
+fn test_derived_version() {
+    assert_eq!(3, Contact::VERSION);
+    assert_eq!(1, Note::VERSION);
+}
+
+#[test]
+fn test_upgrade() {
+    let v1 = json!({ "id": 1, "name": "Ann", "email": "ann@example.com" });
+    let upgraded = migrations().upgrade::<Contact>(v1, 1).unwrap();
+    let contact: Contact = serde_json::from_value(upgraded).unwrap();
+    assert_eq!("Ann", contact.full_name);
+    assert_eq!(vec!["ann@example.com".to_owned()], contact.emails);
+
+    let v2 = json!({ "id": 2, "full_name": "Bob", "email": "bob@example.com" });
+    let upgraded = migrations().upgrade::<Contact>(v2, 2).unwrap();
+    let contact: Contact = serde_json::from_value(upgraded).unwrap();
+    assert_eq!("Bob", contact.full_name);
+
+    let current = json!({ "id": 3, "full_name": "Cy", "emails": [] });
+    assert_eq!(
+        current,
+        migrations().upgrade::<Contact>(current.clone(), 3).unwrap()
+    );
+}
+
+#[test]
+fn test_missing_migration() {
+    let migrations = SchemaMigrations::new().register::<Contact>(2, Ok::<Value, _>);
+    let err = migrations
+        .upgrade::<Contact>(json!({ "id": 1 }), 1)
+        .unwrap_err();
+    let err = err.downcast_ref::<MissingMigrationError>().unwrap();
+    assert_eq!(("contacts", 1), (err.type_name, err.from));
+}
@@ -0,0 +1,170 @@
+#![cfg(all(feature = "metrics", feature = "in-mem"))]
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use live_entity::derive::Entity;
+use live_entity::in_mem::InMemStore;
+use live_entity::Store;
+use metrics::{
+    Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+};
+use serde::{Deserialize, Serialize};
+use test_utils::wait::eventually;
+
+#[derive(Default)]
+struct Count(AtomicU64);
+impl HistogramFn for Count {
+    fn record(&self, _value: f64) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+type Registry<T> = Arc<Mutex<Vec<(Key, Arc<T>)>>>;
+
+/// Keeps counter and gauge values and histogram sample counts, by key.
+#[derive(Clone, Default)]
+struct TestRecorder {
+    values: Registry<AtomicU64>,
+    samples: Registry<Count>,
+}
+
+impl TestRecorder {
+    fn find<T>(entries: &Mutex<Vec<(Key, Arc<T>)>>, key: &Key) -> Option<Arc<T>> {
+        let entries = entries.lock().unwrap();
+        entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    }
+
+    fn register<T: Default>(entries: &Mutex<Vec<(Key, Arc<T>)>>, key: &Key) -> Arc<T> {
+        Self::find(entries, key).unwrap_or_else(|| {
+            let value = Arc::new(T::default());
+            entries.lock().unwrap().push((key.clone(), value.clone()));
+            value
+        })
+    }
+
+    fn matching(key: &Key, name: &str, labels: &HashMap<&str, &str>) -> bool {
+        key.name() == name
+            && labels
+                .iter()
+                .all(|(k, v)| key.labels().any(|l| l.key() == *k && l.value() == *v))
+    }
+
+    fn counter(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        let labels = labels.iter().copied().collect();
+        let values = self.values.lock().unwrap();
+        values
+            .iter()
+            .filter(|(k, _)| Self::matching(k, name, &labels))
+            .map(|(_, v)| v.load(Ordering::SeqCst))
+            .sum()
+    }
+
+    fn gauge(&self, name: &str, labels: &[(&str, &str)]) -> f64 {
+        let labels = labels.iter().copied().collect();
+        let values = self.values.lock().unwrap();
+        values
+            .iter()
+            .filter(|(k, _)| Self::matching(k, name, &labels))
+            .map(|(_, v)| f64::from_bits(v.load(Ordering::SeqCst)))
+            .sum()
+    }
+
+    fn samples(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        let labels = labels.iter().copied().collect();
+        let samples = self.samples.lock().unwrap();
+        samples
+            .iter()
+            .filter(|(k, _)| Self::matching(k, name, &labels))
+            .map(|(_, v)| v.0.load(Ordering::SeqCst))
+            .sum()
+    }
+}
+
+impl Recorder for TestRecorder {
+    fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+        Counter::from_arc(Self::register(&self.values, key))
+    }
+
+    fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+        Gauge::from_arc(Self::register(&self.values, key))
+    }
+
+    fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+        Histogram::from_arc(Self::register(&self.samples, key))
+    }
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "kettles"]
+struct Kettle {
+    #[entity_id]
+    id: u32,
+    full: bool,
+}
+
+#[tokio::test]
+async fn test_in_mem_store_metrics() {
+    let recorder = TestRecorder::default();
+    metrics::set_global_recorder(recorder.clone()).unwrap();
+    let labels = |operation, outcome| {
+        [
+            ("store", "in_mem"),
+            ("entity", "kettles"),
+            ("operation", operation),
+            ("outcome", outcome),
+        ]
+    };
+
+    let store = Arc::new(InMemStore::new(1));
+    store.create(&Kettle { id: 1, full: false }).await.unwrap();
+    store.get_by_id::<Kettle>(&2).await.unwrap_err();
+    store.get_all::<Kettle>().await.unwrap();
+    let operations = "live_entity_operations_total";
+    assert_eq!(1, recorder.counter(operations, &labels("create", "ok")));
+    assert_eq!(
+        1,
+        recorder.counter(operations, &labels("get_by_id", "error"))
+    );
+    assert_eq!(1, recorder.counter(operations, &labels("get_all", "ok")));
+    assert_eq!(
+        1,
+        recorder.samples(
+            "live_entity_operation_duration_seconds",
+            &[("entity", "kettles"), ("operation", "create")]
+        )
+    );
+
+    let watchers = "live_entity_watchers";
+    let (tx, _rx) = tokio::sync::broadcast::channel(8);
+    let watching = store.clone();
+    tokio::spawn(async move { watching.watch::<Kettle>(tx).await.unwrap() });
+    eventually(|| async { recorder.gauge(watchers, &[("entity", "kettles")]) == 1.0 }).await;
+
+    store.create(&Kettle { id: 2, full: true }).await.unwrap();
+    eventually(|| async {
+        recorder.counter(
+            "live_entity_events_total",
+            &[("entity", "kettles"), ("kind", "create")],
+        ) == 1
+    })
+    .await;
+
+    // More events than the store retains, before the watch can forward any.
+    for id in 3..6 {
+        store.create(&Kettle { id, full: true }).await.unwrap();
+    }
+    eventually(|| async {
+        recorder.counter("live_entity_lagged_total", &[("entity", "kettles")]) == 1
+    })
+    .await;
+    eventually(|| async { recorder.gauge(watchers, &[("entity", "kettles")]) == 0.0 }).await;
+}
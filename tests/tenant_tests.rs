@@ -0,0 +1,91 @@
+#![cfg(feature = "in-mem")]
+
+use std::sync::Arc;
+
+use live_entity::derive::Entity;
+use live_entity::in_mem::InMemStore;
+use live_entity::{Event, MultiTenant, Store};
+use serde::{Deserialize, Serialize};
+use test_utils::storage_test::{
+    test_storage_functions, test_storage_singleton_functions, test_storage_soft_delete,
+    test_storage_update_operations,
+};
+use tokio::sync::broadcast;
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "employees"]
+struct Employee {
+    #[entity_id]
+    id: u32,
+    name: String,
+}
+
+fn employee(id: u32, name: &str) -> Employee {
+    Employee {
+        id,
+        name: name.to_owned(),
+    }
+}
+
+#[tokio::test]
+async fn test_tenant_conformance() {
+    let store = InMemStore::new(100);
+    test_storage_functions(Arc::new(store.for_tenant("acme"))).await;
+    test_storage_singleton_functions(Arc::new(store.for_tenant("acme"))).await;
+    test_storage_update_operations(Arc::new(store.for_tenant("acme"))).await;
+    test_storage_soft_delete(Arc::new(store.for_tenant("acme"))).await;
+}
+
+#[tokio::test]
+async fn test_tenants_are_isolated() {
+    let store = InMemStore::new(100);
+    let acme = store.for_tenant("acme");
+    let globex = store.for_tenant("globex");
+
+    acme.create(&employee(1, "Ann")).await.unwrap();
+    globex.create(&employee(1, "Bob")).await.unwrap();
+    globex.create(&employee(2, "Cy")).await.unwrap();
+
+    assert_eq!("Ann", acme.get_by_id::<Employee>(&1).await.unwrap().name);
+    assert_eq!("Bob", globex.get_by_id::<Employee>(&1).await.unwrap().name);
+    assert_eq!(1, acme.get_all::<Employee>().await.unwrap().len());
+    assert!(store.get_all::<Employee>().await.unwrap().is_empty());
+
+    globex.delete_all::<Employee>().await.unwrap();
+    assert_eq!(1, acme.get_all::<Employee>().await.unwrap().len());
+}
+
+#[tokio::test]
+async fn test_views_of_a_tenant_share_entities() {
+    let store = InMemStore::new(100);
+    store
+        .for_tenant("acme")
+        .create(&employee(1, "Ann"))
+        .await
+        .unwrap();
+
+    let again = store.for_tenant("globex").for_tenant("acme");
+    assert_eq!("Ann", again.get_by_id::<Employee>(&1).await.unwrap().name);
+}
+
+#[tokio::test]
+async fn test_watches_only_see_their_tenant() {
+    let store = InMemStore::new(100);
+    let acme = Arc::new(store.for_tenant("acme"));
+    let globex = store.for_tenant("globex");
+
+    let (sender, mut receiver) = broadcast::channel::<Event<Employee>>(16);
+    let watching = acme.clone();
+    let watch = tokio::spawn(async move { watching.watch(sender).await.unwrap() });
+    tokio::task::yield_now().await;
+
+    globex.create(&employee(1, "Bob")).await.unwrap();
+    store.create(&employee(2, "Cy")).await.unwrap();
+    acme.create(&employee(3, "Ann")).await.unwrap();
+
+    match receiver.recv().await.unwrap() {
+        Event::Create(created) => assert_eq!("Ann", created.name),
+        other => panic!("Unexpected event: {:?}", other),
+    }
+    watch.abort();
+}
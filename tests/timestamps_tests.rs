@@ -0,0 +1,232 @@
+#![cfg(feature = "in-mem")]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use live_entity::cached::CachedStore;
+use live_entity::derive::Entity;
+use live_entity::in_mem::InMemStore;
+use live_entity::migration::copy_all;
+use live_entity::replicated::{ReplicatedStore, RetryPolicy};
+use live_entity::{ActingAs, Entity, Event, Patch, Store, Timestamp, Updatable};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "documents"]
+struct Document {
+    #[entity_id]
+    id: u32,
+    title: String,
+    #[entity(created_at)]
+    created_at: Timestamp,
+    #[entity(updated_at)]
+    updated_at: Timestamp,
+    #[entity(modified_by)]
+    modified_by: Option<String>,
+}
+
+fn document(id: u32, title: &str) -> Document {
+    Document {
+        id,
+        title: title.to_owned(),
+        created_at: Timestamp::from_millis(0),
+        updated_at: Timestamp::from_millis(0),
+        modified_by: None,
+    }
+}
+
+#[tokio::test]
+async fn test_timestamps_are_set_on_create() {
+    let store = InMemStore::new(100);
+    let before = Timestamp::now();
+    store.create(&document(1, "Draft")).await.unwrap();
+
+    let created = store.get_by_id::<Document>(&1).await.unwrap();
+    assert!(created.created_at >= before);
+    assert_eq!(created.created_at, created.updated_at);
+    assert_eq!(None, created.modified_by);
+}
+
+#[tokio::test]
+async fn test_only_updated_at_changes_on_update() {
+    let store = InMemStore::new(100);
+    store.create(&document(1, "Draft")).await.unwrap();
+    let created = store.get_by_id::<Document>(&1).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+    let update = UpdatedDocument::default().title("Final".to_owned());
+    store.update::<Document>(&1, &update).await.unwrap();
+
+    let updated = store.get_by_id::<Document>(&1).await.unwrap();
+    assert_eq!("Final", updated.title);
+    assert_eq!(created.created_at, updated.created_at);
+    assert!(updated.updated_at > created.updated_at);
+}
+
+#[tokio::test]
+async fn test_modified_by_is_the_actor() {
+    let store = InMemStore::new(100);
+    store
+        .acting_as("ann")
+        .create(&document(1, "Draft"))
+        .await
+        .unwrap();
+    let created = store.get_by_id::<Document>(&1).await.unwrap();
+    assert_eq!(Some("ann"), created.modified_by.as_deref());
+
+    let update = UpdatedDocument::default().title("Final".to_owned());
+    store
+        .acting_as("bob")
+        .update::<Document>(&1, &update)
+        .await
+        .unwrap();
+    let updated = store.get_by_id::<Document>(&1).await.unwrap();
+    assert_eq!(Some("bob"), updated.modified_by.as_deref());
+
+    // Without an actor, whoever last made a change is kept.
+    store.update::<Document>(&1, &update).await.unwrap();
+    let updated = store.get_by_id::<Document>(&1).await.unwrap();
+    assert_eq!(Some("bob"), updated.modified_by.as_deref());
+
+    // Nor can an update claim to be someone else's.
+    let forged = UpdatedDocument {
+        modified_by: Patch::Set("mallory".to_owned()),
+        ..Default::default()
+    };
+    store.update::<Document>(&1, &forged).await.unwrap();
+    let updated = store.get_by_id::<Document>(&1).await.unwrap();
+    assert_eq!(Some("bob"), updated.modified_by.as_deref());
+}
+
+#[tokio::test]
+async fn test_watchers_see_stamped_updates() {
+    let store = Arc::new(InMemStore::new(100));
+    store.create(&document(1, "Draft")).await.unwrap();
+
+    let (sender, mut receiver) = broadcast::channel::<Event<Document>>(16);
+    let watching = store.clone();
+    let watch = tokio::spawn(async move { watching.watch(sender).await.unwrap() });
+    tokio::task::yield_now().await;
+
+    let update = UpdatedDocument::default().title("Final".to_owned());
+    store
+        .acting_as("ann")
+        .update::<Document>(&1, &update)
+        .await
+        .unwrap();
+
+    let mut watched = document(1, "Draft");
+    match receiver.recv().await.unwrap() {
+        Event::Update { update, .. } => watched.update(&update),
+        other => panic!("Unexpected event: {:?}", other),
+    }
+    let stored = store.get_by_id::<Document>(&1).await.unwrap();
+    assert_eq!("Final", watched.title);
+    assert_eq!(stored.updated_at, watched.updated_at);
+    assert_eq!(Some("ann"), watched.modified_by.as_deref());
+    watch.abort();
+}
+
+#[tokio::test]
+async fn test_copies_keep_timestamps_and_expiry() {
+    let source = Arc::new(InMemStore::new(100));
+    source
+        .acting_as("ann")
+        .create_with_ttl(&document(1, "Draft"), Duration::from_secs(60))
+        .await
+        .unwrap();
+    let original = source.get_by_id::<Document>(&1).await.unwrap();
+    let expires_at = source.get_expiry::<Document>(&1).await.unwrap().unwrap();
+    tokio::time::sleep(Duration::from_millis(5)).await;
+
+    let cached = CachedStore::new(source.clone(), 100);
+    let cached_copy = &cached.get_all::<Document>().await.unwrap()[0];
+    assert_eq!(original.created_at, cached_copy.created_at);
+    assert_eq!(original.updated_at, cached_copy.updated_at);
+
+    let target = InMemStore::new(100);
+    assert_eq!(1, copy_all::<Document>(&*source, &target).await.unwrap());
+    let migrated = target.get_by_id::<Document>(&1).await.unwrap();
+    assert_eq!(original.created_at, migrated.created_at);
+    assert_eq!(original.updated_at, migrated.updated_at);
+    assert_eq!(Some("ann"), migrated.modified_by.as_deref());
+    let migrated_expiry = target.get_expiry::<Document>(&1).await.unwrap().unwrap();
+    let drift = migrated_expiry
+        .duration_since(expires_at)
+        .unwrap_or_else(|e| e.duration());
+    assert!(drift < Duration::from_millis(50));
+}
+
+#[tokio::test]
+async fn test_replicas_keep_the_primary_stamps() {
+    let primary = InMemStore::new(100);
+    let secondary = Arc::new(InMemStore::new(100));
+    let as_ann = ReplicatedStore::new(
+        Arc::new(primary.acting_as("ann")),
+        vec![secondary.clone()],
+        RetryPolicy::default(),
+    );
+    as_ann.create(&document(1, "Draft")).await.unwrap();
+    as_ann.flush().await;
+
+    // Made later, by someone else, than the secondary would stamp it.
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let as_bob = ReplicatedStore::new(
+        Arc::new(primary.acting_as("bob")),
+        vec![secondary.clone()],
+        RetryPolicy::default(),
+    );
+    as_bob
+        .update::<Document>(&1, &UpdatedDocument::default().title("Final".to_owned()))
+        .await
+        .unwrap();
+    as_bob.flush().await;
+    let original = primary.get_by_id::<Document>(&1).await.unwrap();
+    let replica = secondary.get_by_id::<Document>(&1).await.unwrap();
+    assert_eq!("Final", replica.title);
+    assert_eq!(original.updated_at, replica.updated_at);
+    assert_eq!(Some("bob"), replica.modified_by.as_deref());
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "notes"]
+#[serde(rename_all = "camelCase")]
+struct Note {
+    #[entity_id]
+    id: u32,
+    #[entity(updated_at)]
+    last_edited: Timestamp,
+    #[entity(updated_at)]
+    #[serde(rename = "touched")]
+    touched_at: Option<Timestamp>,
+}
+
+#[test]
+fn test_updated_at_fields_are_serialized_names() {
+    assert_eq!(&["lastEdited", "touched"], Note::UPDATED_AT_FIELDS);
+    assert_eq!(&["updated_at"], Document::UPDATED_AT_FIELDS);
+    assert_eq!(
+        &["created_at", "updated_at", "modified_by"],
+        Document::MAINTAINED_FIELDS
+    );
+}
+
+#[test]
+fn test_timestamp_serde() {
+    let timestamp = Timestamp::from_millis(1_700_000_000_123);
+    let json = serde_json::to_value(timestamp).unwrap();
+    assert_eq!(
+        serde_json::json!({"$date": {"$numberLong": "1700000000123"}}),
+        json
+    );
+    assert_eq!(timestamp, serde_json::from_value(json).unwrap());
+    assert_eq!(
+        timestamp,
+        serde_json::from_value(serde_json::json!(1_700_000_000_123i64)).unwrap()
+    );
+    assert_eq!(
+        timestamp,
+        serde_json::from_value(serde_json::json!({"$date": 1_700_000_000_123i64})).unwrap()
+    );
+}
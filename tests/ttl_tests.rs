@@ -0,0 +1,160 @@
+#![cfg(feature = "in-mem")]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use live_entity::derive::Entity;
+use live_entity::event_sourced::EventSourcedStore;
+use live_entity::in_mem::{InMemEventLog, InMemStore};
+use live_entity::validating::ValidatingStore;
+use live_entity::{Entity, Event, ExpiryUnsupportedError, NotFoundError, Store};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::time::sleep;
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "sessions"]
+#[entity(ttl_secs = 60)]
+struct Session {
+    #[entity_id]
+    token: String,
+    user: String,
+}
+
+#[derive(Entity, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "invites"]
+struct Invite {
+    #[entity_id]
+    code: u32,
+    email: String,
+}
+
+fn session(token: &str, user: &str) -> Session {
+    Session {
+        token: token.to_owned(),
+        user: user.to_owned(),
+    }
+}
+
+fn invite(code: u32, email: &str) -> Invite {
+    Invite {
+        code,
+        email: email.to_owned(),
+    }
+}
+
+async fn exists<E: Entity>(store: &impl Store, id: &E::ID) -> bool {
+    match store.get_by_id::<E>(id).await {
+        Ok(_) => true,
+        Err(e) if e.is::<NotFoundError<E::ID>>() => false,
+        Err(e) => panic!("Unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_derived_ttl() {
+    assert_eq!(Some(Duration::from_secs(60)), Session::TTL);
+    assert_eq!(None, Invite::TTL);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_entities_expire_after_their_ttl() {
+    let store = InMemStore::new(100);
+    store
+        .create_with_ttl(&invite(1, "ann@example.com"), Duration::from_secs(10))
+        .await
+        .unwrap();
+    store.create(&invite(2, "bob@example.com")).await.unwrap();
+
+    sleep(Duration::from_secs(9)).await;
+    assert!(exists::<Invite>(&store, &1).await);
+    sleep(Duration::from_secs(2)).await;
+    assert!(!exists::<Invite>(&store, &1).await);
+    assert!(exists::<Invite>(&store, &2).await);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_entities_expire_after_the_ttl_of_their_type() {
+    let store = InMemStore::new(100);
+    store.create(&session("abc", "ann")).await.unwrap();
+    store
+        .create_with_ttl(&session("def", "bob"), Duration::from_secs(600))
+        .await
+        .unwrap();
+
+    sleep(Duration::from_secs(61)).await;
+    assert!(!exists::<Session>(&store, &"abc".to_owned()).await);
+    assert!(exists::<Session>(&store, &"def".to_owned()).await);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_earlier_deadlines_wake_the_reaper() {
+    let store = InMemStore::new(100);
+    store
+        .create_with_ttl(&invite(1, "ann@example.com"), Duration::from_secs(100))
+        .await
+        .unwrap();
+    store
+        .create_with_ttl(&invite(2, "bob@example.com"), Duration::from_secs(10))
+        .await
+        .unwrap();
+
+    sleep(Duration::from_secs(11)).await;
+    assert!(exists::<Invite>(&store, &1).await);
+    assert!(!exists::<Invite>(&store, &2).await);
+    sleep(Duration::from_secs(90)).await;
+    assert!(!exists::<Invite>(&store, &1).await);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_recreating_without_ttl_keeps_the_entity() {
+    let store = InMemStore::new(100);
+    store
+        .create_with_ttl(&invite(1, "ann@example.com"), Duration::from_secs(10))
+        .await
+        .unwrap();
+    store.delete_by_id::<Invite>(&1).await.unwrap();
+    store.create(&invite(1, "bob@example.com")).await.unwrap();
+
+    sleep(Duration::from_secs(11)).await;
+    assert_eq!(
+        "bob@example.com",
+        store.get_by_id::<Invite>(&1).await.unwrap().email
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_watchers_see_expiry() {
+    let store = Arc::new(InMemStore::new(100));
+    let (sender, mut receiver) = broadcast::channel::<Event<Invite>>(16);
+    let watching = store.clone();
+    let watch = tokio::spawn(async move { watching.watch(sender).await.unwrap() });
+    tokio::task::yield_now().await;
+
+    let wrapped = ValidatingStore::new(store.clone());
+    wrapped
+        .create_with_ttl(&invite(1, "ann@example.com"), Duration::from_secs(10))
+        .await
+        .unwrap();
+
+    match receiver.recv().await.unwrap() {
+        Event::Create(created) => assert_eq!("ann@example.com", created.email),
+        other => panic!("Unexpected event: {:?}", other),
+    }
+    match receiver.recv().await.unwrap() {
+        Event::Delete(id) => assert_eq!(1, id),
+        other => panic!("Unexpected event: {:?}", other),
+    }
+    watch.abort();
+}
+
+#[tokio::test]
+async fn test_expiry_unsupported() {
+    let store = EventSourcedStore::new(InMemEventLog::new(), 100);
+    let err = store
+        .create_with_ttl(&invite(1, "ann@example.com"), Duration::from_secs(10))
+        .await
+        .unwrap_err();
+    assert!(err.is::<ExpiryUnsupportedError>());
+    assert!(store.get_all::<Invite>().await.unwrap().is_empty());
+}
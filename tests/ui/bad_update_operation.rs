@@ -0,0 +1,11 @@
+use live_entity::derive::Updatable;
+
+#[derive(Updatable)]
+struct Counter {
+    #[entity(list)]
+    count: u32,
+    #[entity(shuffle)]
+    names: Vec<String>,
+}
+
+fn main() {}
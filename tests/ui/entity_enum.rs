@@ -0,0 +1,9 @@
+use live_entity::derive::Entity;
+
+#[derive(Entity)]
+#[entity_name = "shapes"]
+enum Shape {
+    Circle { radius: f32 },
+}
+
+fn main() {}
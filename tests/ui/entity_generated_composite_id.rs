@@ -0,0 +1,14 @@
+use live_entity::derive::Entity;
+use serde::{Deserialize, Serialize};
+
+#[derive(Entity, Clone, Debug, Serialize, Deserialize)]
+#[entity_name = "stock"]
+struct Stock {
+    #[entity_id]
+    warehouse: String,
+    #[entity_id(generate = "sequence")]
+    sku: u32,
+    count: u32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use live_entity::derive::Entity;
+use serde::{Deserialize, Serialize};
+
+#[derive(Entity, Clone, Debug, Serialize, Deserialize)]
+#[entity_name = "notes"]
+struct Note {
+    body: String,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use live_entity::derive::Entity;
+use serde::{Deserialize, Serialize};
+
+#[derive(Entity, Clone, Debug, Serialize, Deserialize)]
+#[entity_name = "notes"]
+struct Note {
+    #[entity_id(generate = "random")]
+    id: String,
+    body: String,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use live_entity::derive::Updatable;
+use live_entity::Timestamp;
+
+#[derive(Updatable)]
+struct Post {
+    #[entity(updated_at, readonly)]
+    edited: Timestamp,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use live_entity::derive::Updatable;
+
+#[derive(Updatable)]
+struct Ledger {
+    #[entity(readonly, list)]
+    entries: Vec<u64>,
+}
+
+fn main() {}
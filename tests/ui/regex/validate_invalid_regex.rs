@@ -0,0 +1,13 @@
+use live_entity::derive::{Entity, Validate};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Entity, Validate)]
+#[entity_name = "accounts"]
+struct Account {
+    #[entity_id]
+    id: u32,
+    #[validate(regex = "^[a-z+$")]
+    handle: String,
+}
+
+fn main() {}
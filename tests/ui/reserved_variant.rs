@@ -0,0 +1,9 @@
+use live_entity::derive::Updatable;
+use serde::{Deserialize, Serialize};
+
+#[derive(Updatable, Clone, Debug, Serialize, Deserialize)]
+enum Action {
+    Replace { with: String },
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use live_entity::derive::Singleton;
+use serde::{Deserialize, Serialize};
+
+#[derive(Singleton, Clone, Debug, Serialize, Deserialize)]
+#[singleton(type_name = "pages")]
+struct AboutPage {
+    body: String,
+}
+
+fn main() {}
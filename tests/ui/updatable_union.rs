@@ -0,0 +1,9 @@
+use live_entity::derive::Updatable;
+
+#[derive(Updatable)]
+union Bits {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use live_entity::derive::{Entity, Validate};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Entity, Validate)]
+#[entity_name = "parcels"]
+struct Parcel {
+    #[entity_id]
+    id: u32,
+    #[validate(between(min = 1, max = 30))]
+    weight: u32,
+}
+
+fn main() {}
@@ -0,0 +1,106 @@
+#![cfg(feature = "in-mem")]
+
+use std::sync::Arc;
+
+use live_entity::derive::{Entity, Validate};
+use live_entity::in_mem::InMemStore;
+use live_entity::validating::ValidatingStore;
+use live_entity::{NotFoundError, Store, ValidationError};
+use serde::{Deserialize, Serialize};
+use test_utils::storage_test::{
+    test_storage_functions, test_storage_soft_delete, test_storage_update_operations,
+};
+
+#[derive(Entity, Validate, Clone, Serialize, Deserialize, Debug)]
+#[entity_name = "seats"]
+#[validate(update = "no_unbooking")]
+struct Seat {
+    #[entity_id]
+    #[validate(non_empty)]
+    label: String,
+    #[validate(range(min = 1, max = 4))]
+    class: u8,
+    booked: bool,
+}
+
+fn no_unbooking(current: &Seat, update: &UpdatedSeat) -> Result<(), ValidationError> {
+    if current.booked && update.booked == Some(false) {
+        return Err(ValidationError::for_field("booked", "can't be cancelled"));
+    }
+    Ok(())
+}
+
+fn seat(label: &str, class: u8) -> Seat {
+    Seat {
+        label: label.to_owned(),
+        class,
+        booked: false,
+    }
+}
+
+#[tokio::test]
+async fn test_validating_store() {
+    let storage = Arc::new(ValidatingStore::new(Arc::new(InMemStore::new(8))).validate::<Seat>());
+    test_storage_functions(storage.clone()).await;
+    test_storage_update_operations(storage.clone()).await;
+    test_storage_soft_delete(storage).await;
+}
+
+#[tokio::test]
+async fn test_validating_store_rejects_invalid_writes() {
+    let inner = Arc::new(InMemStore::new(8));
+    let storage = ValidatingStore::new(inner.clone()).validate::<Seat>();
+
+    let err = storage.create(&seat("14C", 5)).await.unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    assert_eq!(Some("class".to_owned()), err.field);
+    assert!(storage.create(&seat("", 2)).await.is_err());
+    assert!(inner.get_all::<Seat>().await.unwrap().is_empty());
+
+    storage.create(&seat("14C", 2)).await.unwrap();
+    let err = storage
+        .update::<Seat>(&"14C".to_owned(), &UpdatedSeat::default().class(0))
+        .await
+        .unwrap_err();
+    assert!(err.is::<ValidationError>());
+    storage
+        .update::<Seat>(&"14C".to_owned(), &UpdatedSeat::default().booked(true))
+        .await
+        .unwrap();
+    let err = storage
+        .update::<Seat>(&"14C".to_owned(), &UpdatedSeat::default().booked(false))
+        .await
+        .unwrap_err();
+    assert!(err.is::<ValidationError>());
+    let stored = inner.get_by_id::<Seat>(&"14C".to_owned()).await.unwrap();
+    assert_eq!((2, true), (stored.class, stored.booked));
+
+    let mut upgraded = stored.clone();
+    upgraded.class = 9;
+    assert!(storage.save(&upgraded).await.is_err());
+    upgraded.class = 1;
+    storage.save(&upgraded).await.unwrap();
+    assert_eq!(
+        1,
+        inner
+            .get_by_id::<Seat>(&"14C".to_owned())
+            .await
+            .unwrap()
+            .class
+    );
+}
+
+#[tokio::test]
+async fn test_validating_store_passes_through_unregistered_types() {
+    let inner = Arc::new(InMemStore::new(8));
+    let storage = ValidatingStore::new(inner.clone());
+    storage.create(&seat("", 9)).await.unwrap();
+    assert_eq!(1, inner.get_all::<Seat>().await.unwrap().len());
+
+    let validating = ValidatingStore::new(inner).validate::<Seat>();
+    let err = validating
+        .update::<Seat>(&"1A".to_owned(), &UpdatedSeat::default().class(2))
+        .await
+        .unwrap_err();
+    assert!(err.is::<NotFoundError<String>>());
+}